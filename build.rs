@@ -1,5 +1,8 @@
+#[cfg(feature = "rtlsdr-ffi")]
 extern crate bindgen;
+#[cfg(feature = "rtlsdr-ffi")]
 use std::env;
+#[cfg(feature = "rtlsdr-ffi")]
 use std::path::PathBuf;
 
 fn main() {
@@ -7,7 +10,14 @@ fn main() {
     //     println!("cargo:warning=Skipping build.rs because this is a dependency.");
     //     return;
     // }
-    
+
+    // librtlsdr is only needed for the legacy FFI wrapper, the SDR drivers go through SoapySDR
+    #[cfg(feature = "rtlsdr-ffi")]
+    generate_rtlsdr_bindings();
+}
+
+#[cfg(feature = "rtlsdr-ffi")]
+fn generate_rtlsdr_bindings() {
     println!("cargo:rustc-link-search={}", "src/c_lib");
     println!("cargo:rustc-link-lib=convenience");
     println!("cargo:rustc-link-search={}", "/usr/local/lib");
@@ -0,0 +1,415 @@
+use crate::constants::gps_property_constants::GPS_L1_FREQ_HZ;
+use crate::rinex::{GnssRinexNavRecord, get_sats_from_rinex};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde::Deserialize;
+use std::error::Error;
+use std::fmt;
+
+const GM: f64 = 3.986005e14; // m^3/s^2, as used by GPS
+const EARTH_ROTATION_RAD_PER_S: f64 = 7.2921151467e-5;
+const SPEED_OF_LIGHT_M_PER_S: f64 = 299_792_458.0;
+const SECONDS_PER_WEEK: f64 = 604_800.0;
+const GPS_UTC_LEAP_S: i64 = 18; // Since 2017
+const WGS84_A_M: f64 = 6_378_137.0;
+const WGS84_F: f64 = 1.0 / 298.257_223_563;
+
+/// Approximate position, time and orbits to predict the visible satellites and their Doppler
+#[derive(Debug, Clone, Deserialize)]
+pub struct AssistanceConfig {
+    pub rinex_path: Option<String>, // RINEX 3 GPS navigation file, preferred
+    pub almanac_path: Option<String>, // YUMA almanac, used without RINEX
+    pub latitude_deg: f64,
+    pub longitude_deg: f64,
+    #[serde(default)]
+    pub height_m: f64,
+    pub time_utc: Option<String>, // RFC 3339 time of the first sample, now when absent
+    #[serde(default = "default_position_uncertainty_km")]
+    pub position_uncertainty_km: f64,
+    #[serde(default = "default_time_uncertainty_s")]
+    pub time_uncertainty_s: f64,
+    #[serde(default = "default_oscillator_ppm")]
+    pub oscillator_ppm: f64, // Frequency error of the receiver clock
+    #[serde(default = "default_elevation_mask_deg")]
+    pub elevation_mask_deg: f64,
+}
+
+fn default_position_uncertainty_km() -> f64 {
+    100.0
+}
+
+fn default_time_uncertainty_s() -> f64 {
+    60.0
+}
+
+fn default_oscillator_ppm() -> f64 {
+    1.0
+}
+
+fn default_elevation_mask_deg() -> f64 {
+    5.0
+}
+
+#[derive(Debug)]
+pub struct AssistanceError(String);
+
+impl fmt::Display for AssistanceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Assistance error: {}", self.0)
+    }
+}
+
+impl Error for AssistanceError {}
+
+/// Keplerian elements of a broadcast ephemeris, or of an almanac with the harmonic terms zero
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Orbit {
+    pub prn: u8,
+    pub week: u32, // Full GPS week of `toe`
+    pub toe: f64, // Reference time, s of the week
+    pub sqrt_a: f64,
+    pub e: f64,
+    pub m0: f64,
+    pub delta_n: f64,
+    pub omega0: f64,
+    pub omega_dot: f64,
+    pub i0: f64,
+    pub idot: f64,
+    pub omega: f64,
+    pub cuc: f64,
+    pub cus: f64,
+    pub crc: f64,
+    pub crs: f64,
+    pub cic: f64,
+    pub cis: f64,
+    pub healthy: bool,
+}
+
+impl Orbit {
+    /// Orbit of a GPS record, None for the other systems
+    pub fn from_rinex(record: &GnssRinexNavRecord) -> Option<Self> {
+        let prn = record.satellite_sys_num.strip_prefix('G')?.trim().parse().ok()?;
+        Some(Self {
+            prn,
+            week: record.orbit5.gps_week as u32,
+            toe: record.orbit3.toe,
+            sqrt_a: record.orbit2.sqrt_a,
+            e: record.orbit2.e_eccentricity,
+            m0: record.orbit1.m0,
+            delta_n: record.orbit1.delta_n,
+            omega0: record.orbit3.omega0,
+            omega_dot: record.orbit4.omega_dot,
+            i0: record.orbit4.i0,
+            idot: record.orbit5.idot,
+            omega: record.orbit4.omega,
+            cuc: record.orbit2.cuc,
+            cus: record.orbit2.cus,
+            crc: record.orbit4.crc,
+            crs: record.orbit1.crs,
+            cic: record.orbit3.cic,
+            cis: record.orbit3.cis,
+            healthy: record.orbit6.sv_health == 0.0,
+        })
+    }
+
+    /// Orbits of a YUMA almanac, its 10-bit weeks taken as the closest to `gps_week`
+    pub fn from_yuma(text: &str, gps_week: u32) -> Result<Vec<Self>, AssistanceError> {
+        let mut orbits = Vec::new();
+        let mut orbit: Option<Orbit> = None;
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let key = key.trim().to_ascii_lowercase();
+            let value: f64 = value
+                .trim()
+                .parse()
+                .map_err(|_| AssistanceError(format!("Almanac value of {} not a number: {}", key, value.trim())))?;
+            if key == "id" {
+                orbits.extend(orbit.take());
+                orbit = Some(Orbit { prn: value as u8, healthy: true, ..Default::default() });
+                continue;
+            }
+            let Some(o) = orbit.as_mut() else {
+                continue;
+            };
+            match key.as_str() {
+                "health" => o.healthy = value == 0.0,
+                "eccentricity" => o.e = value,
+                k if k.starts_with("time of applicability") => o.toe = value,
+                k if k.starts_with("orbital inclination") => o.i0 = value,
+                k if k.starts_with("rate of right ascen") => o.omega_dot = value,
+                k if k.starts_with("sqrt(a)") => o.sqrt_a = value,
+                k if k.starts_with("right ascen at week") => o.omega0 = value,
+                k if k.starts_with("argument of perigee") => o.omega = value,
+                k if k.starts_with("mean anom") => o.m0 = value,
+                "week" => {
+                    let week = value as i64 % 1024;
+                    let rollovers = ((gps_week as i64 - week) as f64 / 1024.0).round() as i64;
+                    o.week = (week + 1024 * rollovers) as u32;
+                }
+                _ => {}
+            }
+        }
+        orbits.extend(orbit);
+        if orbits.is_empty() {
+            return Err(AssistanceError("No satellite in the almanac".to_string()));
+        }
+        Ok(orbits)
+    }
+
+    /// ECEF position in m at `gps_s` seconds of GPS time, with the IS-GPS-200 user algorithm
+    pub fn position(&self, gps_s: f64) -> [f64; 3] {
+        let tk = gps_s - (self.week as f64 * SECONDS_PER_WEEK + self.toe);
+        let a = self.sqrt_a * self.sqrt_a;
+        let n = (GM / (a * a * a)).sqrt() + self.delta_n;
+        let mean_anomaly = self.m0 + n * tk;
+        let mut ecc_anomaly = mean_anomaly;
+        for _ in 0..10 {
+            ecc_anomaly = mean_anomaly + self.e * ecc_anomaly.sin();
+        }
+        let true_anomaly = ((1.0 - self.e * self.e).sqrt() * ecc_anomaly.sin()).atan2(ecc_anomaly.cos() - self.e);
+
+        let phi = true_anomaly + self.omega;
+        let (sin_2phi, cos_2phi) = (2.0 * phi).sin_cos();
+        let u = phi + self.cus * sin_2phi + self.cuc * cos_2phi;
+        let r = a * (1.0 - self.e * ecc_anomaly.cos()) + self.crs * sin_2phi + self.crc * cos_2phi;
+        let i = self.i0 + self.cis * sin_2phi + self.cic * cos_2phi + self.idot * tk;
+        let (x, y) = (r * u.cos(), r * u.sin());
+        let node = self.omega0 + (self.omega_dot - EARTH_ROTATION_RAD_PER_S) * tk - EARTH_ROTATION_RAD_PER_S * self.toe;
+        let (sin_node, cos_node) = node.sin_cos();
+        [x * cos_node - y * i.cos() * sin_node, x * sin_node + y * i.cos() * cos_node, y * i.sin()]
+    }
+}
+
+/// Seconds of GPS time since its epoch at `utc`
+pub fn gps_seconds(utc: DateTime<Utc>) -> f64 {
+    let epoch = Utc.with_ymd_and_hms(1980, 1, 6, 0, 0, 0).unwrap();
+    (utc - epoch).num_milliseconds() as f64 / 1000.0 + GPS_UTC_LEAP_S as f64
+}
+
+/// ECEF position in m of a WGS 84 latitude, longitude and height
+pub fn geodetic_to_ecef(latitude_deg: f64, longitude_deg: f64, height_m: f64) -> [f64; 3] {
+    let (sin_lat, cos_lat) = latitude_deg.to_radians().sin_cos();
+    let (sin_lon, cos_lon) = longitude_deg.to_radians().sin_cos();
+    let e2 = WGS84_F * (2.0 - WGS84_F);
+    let n = WGS84_A_M / (1.0 - e2 * sin_lat * sin_lat).sqrt();
+    [
+        (n + height_m) * cos_lat * cos_lon,
+        (n + height_m) * cos_lat * sin_lon,
+        (n * (1.0 - e2) + height_m) * sin_lat,
+    ]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SatellitePrediction {
+    pub prn: u8,
+    pub elevation_deg: f64,
+    pub doppler_hz: f32,
+    pub doppler_uncertainty_hz: f32, // Half width of the window to search
+}
+
+pub struct Assistance {
+    pub orbits: Vec<Orbit>,
+    pub receiver_ecef: [f64; 3],
+    pub gps_time_s: f64, // At the first sample
+    up: [f64; 3], // Local vertical of the receiver
+    position_uncertainty_m: f64,
+    time_uncertainty_s: f64,
+    oscillator_hz: f64, // Doppler error of the receiver clock
+    elevation_mask_deg: f64,
+}
+
+impl Assistance {
+    pub fn new(orbits: Vec<Orbit>, config: &AssistanceConfig, gps_time_s: f64) -> Self {
+        let (sin_lat, cos_lat) = config.latitude_deg.to_radians().sin_cos();
+        let (sin_lon, cos_lon) = config.longitude_deg.to_radians().sin_cos();
+        Self {
+            orbits,
+            receiver_ecef: geodetic_to_ecef(config.latitude_deg, config.longitude_deg, config.height_m),
+            gps_time_s,
+            up: [cos_lat * cos_lon, cos_lat * sin_lon, sin_lat],
+            position_uncertainty_m: config.position_uncertainty_km * 1e3,
+            time_uncertainty_s: config.time_uncertainty_s,
+            oscillator_hz: config.oscillator_ppm * 1e-6 * GPS_L1_FREQ_HZ as f64,
+            elevation_mask_deg: config.elevation_mask_deg,
+        }
+    }
+
+    /// Reads the orbits closest to the configured time
+    pub fn from_config(config: &AssistanceConfig) -> Result<Self, AssistanceError> {
+        let utc = match &config.time_utc {
+            Some(time) => DateTime::parse_from_rfc3339(time)
+                .map_err(|e| AssistanceError(format!("time_utc {}: {}", time, e)))?
+                .with_timezone(&Utc),
+            None => Utc::now(),
+        };
+        let gps_time_s = gps_seconds(utc);
+        let orbits = if let Some(path) = &config.rinex_path {
+            // The records are labelled in GPS time
+            let (_, records) = get_sats_from_rinex(path, utc + Duration::seconds(GPS_UTC_LEAP_S))
+                .map_err(|e| AssistanceError(format!("{}: {}", path, e)))?;
+            records.values().filter_map(Orbit::from_rinex).collect()
+        } else if let Some(path) = &config.almanac_path {
+            let text = std::fs::read_to_string(path).map_err(|e| AssistanceError(format!("{}: {}", path, e)))?;
+            Orbit::from_yuma(&text, (gps_time_s / SECONDS_PER_WEEK) as u32)?
+        } else {
+            return Err(AssistanceError("rinex_path or almanac_path is needed".to_string()));
+        };
+        Ok(Self::new(orbits, config, gps_time_s))
+    }
+
+    /// Healthy satellites above the elevation mask at `gps_s`, the highest first
+    pub fn predict_at(&self, gps_s: f64) -> Vec<SatellitePrediction> {
+        let mut predictions: Vec<SatellitePrediction> = self
+            .orbits
+            .iter()
+            .filter(|orbit| orbit.healthy)
+            .filter_map(|orbit| {
+                let los = sub(orbit.position(gps_s), self.receiver_ecef);
+                let range = norm(los);
+                let elevation_deg = (dot(los, self.up) / range).asin().to_degrees();
+                if elevation_deg < self.elevation_mask_deg {
+                    return None;
+                }
+
+                let doppler = |t: f64| -range_rate(orbit, self.receiver_ecef, t) * GPS_L1_FREQ_HZ as f64 / SPEED_OF_LIGHT_M_PER_S;
+                let doppler_hz = doppler(gps_s);
+                let doppler_rate = (doppler(gps_s + 1.0) - doppler(gps_s - 1.0)) / 2.0;
+                // A receiver moved across the line of sight sees the satellite velocity across it
+                let velocity = sub(orbit.position(gps_s + 0.5), orbit.position(gps_s - 0.5));
+                let along = dot(velocity, los) / range;
+                let across = (dot(velocity, velocity) - along * along).max(0.0).sqrt();
+                let position_hz = across / range * self.position_uncertainty_m * GPS_L1_FREQ_HZ as f64 / SPEED_OF_LIGHT_M_PER_S;
+                let doppler_uncertainty_hz = self.oscillator_hz + doppler_rate.abs() * self.time_uncertainty_s + position_hz;
+                Some(SatellitePrediction {
+                    prn: orbit.prn,
+                    elevation_deg,
+                    doppler_hz: doppler_hz as f32,
+                    doppler_uncertainty_hz: doppler_uncertainty_hz as f32,
+                })
+            })
+            .collect();
+        predictions.sort_by(|a, b| b.elevation_deg.total_cmp(&a.elevation_deg));
+        predictions
+    }
+}
+
+/// Rate of the range from `receiver` to the satellite in m/s, both in the rotating ECEF frame
+fn range_rate(orbit: &Orbit, receiver: [f64; 3], gps_s: f64) -> f64 {
+    norm(sub(orbit.position(gps_s + 0.5), receiver)) - norm(sub(orbit.position(gps_s - 0.5), receiver))
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn norm(a: [f64; 3]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RINEX_FILE: &str = "src/test_data/BRDC00WRD_R_20233330000_01D_GN.rnx";
+
+    fn rinex_orbit(prn: &str, gps_time: &str) -> Orbit {
+        let time = DateTime::parse_from_rfc3339(gps_time).unwrap().with_timezone(&Utc);
+        let (_, records) = get_sats_from_rinex(RINEX_FILE, time).expect("RINEX file not read");
+        Orbit::from_rinex(&records[prn]).expect("Not a GPS record")
+    }
+
+    fn frankfurt() -> AssistanceConfig {
+        AssistanceConfig {
+            rinex_path: Some(RINEX_FILE.to_string()),
+            almanac_path: None,
+            latitude_deg: 50.09,
+            longitude_deg: 8.66,
+            height_m: 100.0,
+            time_utc: Some("2023-11-29T21:00:00Z".to_string()),
+            position_uncertainty_km: default_position_uncertainty_km(),
+            time_uncertainty_s: default_time_uncertainty_s(),
+            oscillator_ppm: default_oscillator_ppm(),
+            elevation_mask_deg: default_elevation_mask_deg(),
+        }
+    }
+
+    #[test]
+    fn test_consecutive_ephemerides_agree() {
+        // Two hours apart, both fit the orbit in between to a few metres
+        for prn in ["G01", "G05"] {
+            let first = rinex_orbit(prn, "2023-11-29T20:00:00Z");
+            let second = rinex_orbit(prn, "2023-11-29T22:00:00Z");
+            assert_eq!(second.toe - first.toe, 7200.0);
+            let t = first.week as f64 * SECONDS_PER_WEEK + first.toe + 3600.0;
+            let (p1, p2) = (first.position(t), second.position(t));
+            assert!(norm(sub(p1, p2)) < 20.0, "{} m apart", norm(sub(p1, p2)));
+            assert!((25.5e6..27.5e6).contains(&norm(p1)), "{} m from the centre", norm(p1));
+        }
+    }
+
+    #[test]
+    fn test_predictions() {
+        let assistance = Assistance::from_config(&frankfurt()).expect("Assistance not loaded");
+        let predictions = assistance.predict_at(assistance.gps_time_s);
+        assert!((5..=14).contains(&predictions.len()), "{} visible", predictions.len());
+        for (p, next) in predictions.iter().zip(predictions.iter().skip(1)) {
+            assert!(p.elevation_deg >= next.elevation_deg);
+        }
+        for p in &predictions {
+            assert!(p.elevation_deg >= 5.0 && p.doppler_hz.abs() < 5000.0, "{:?}", p);
+            // The oscillator, 60 s of Doppler rate and 100 km across the line of sight
+            assert!((1575.0..2200.0).contains(&p.doppler_uncertainty_hz), "{:?}", p);
+        }
+
+        // A rising satellite comes closer, its Doppler is positive
+        let orbit = assistance.orbits.iter().find(|o| o.prn == predictions[0].prn).unwrap();
+        let t = assistance.gps_time_s;
+        let closing = range_rate(orbit, assistance.receiver_ecef, t) < 0.0;
+        assert_eq!(closing, predictions[0].doppler_hz > 0.0);
+    }
+
+    #[test]
+    fn test_yuma_almanac() {
+        let ephemeris = rinex_orbit("G05", "2023-11-29T20:00:00Z");
+        let text = format!(
+            "******** Week {} almanac for PRN-05 ********\n\
+             ID:                         05\n\
+             Health:                     000\n\
+             Eccentricity:               {:.10E}\n\
+             Time of Applicability(s):  {:.4}\n\
+             Orbital Inclination(rad):   {:.10}\n\
+             Rate of Right Ascen(r/s):  {:.10E}\n\
+             SQRT(A)  (m 1/2):           {:.6}\n\
+             Right Ascen at Week(rad):   {:.10E}\n\
+             Argument of Perigee(rad):   {:.9}\n\
+             Mean Anom(rad):             {:.10E}\n\
+             Af0(s):                     0.0000000000E+000\n\
+             Af1(s/s):                   0.0000000000E+000\n\
+             week:                        {}\n",
+            ephemeris.week % 1024,
+            ephemeris.e,
+            ephemeris.toe,
+            ephemeris.i0,
+            ephemeris.omega_dot,
+            ephemeris.sqrt_a,
+            ephemeris.omega0,
+            ephemeris.omega,
+            ephemeris.m0,
+            ephemeris.week % 1024,
+        );
+        let almanac = Orbit::from_yuma(&text, 2290).expect("Almanac not read");
+        assert_eq!(almanac.len(), 1);
+        assert_eq!((almanac[0].prn, almanac[0].week), (5, 2290));
+        assert!(almanac[0].healthy);
+
+        // Without the harmonic terms, still within a few km
+        let t = ephemeris.week as f64 * SECONDS_PER_WEEK + ephemeris.toe + 1800.0;
+        let error = norm(sub(almanac[0].position(t), ephemeris.position(t)));
+        assert!(error < 5e3, "{} m", error);
+    }
+}
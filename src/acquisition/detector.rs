@@ -0,0 +1,288 @@
+use serde::Deserialize;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::simd::f32x8;
+use std::simd::num::SimdFloat;
+
+/// How acquisition decides a correlation peak is a satellite
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum DetectorConfig {
+    /// Peak over the mean of all the code phases of its Doppler bin, the threshold set for `pfa`
+    ChiSquare {
+        #[serde(default = "default_pfa")]
+        pfa: f64, // False alarms per satellite search
+    },
+    /// Peak over the mean of `reference_cells` code phases on each side, past `guard_chips`
+    CaCfar {
+        #[serde(default = "default_pfa")]
+        pfa: f64,
+        #[serde(default = "default_guard_chips")]
+        guard_chips: f32,
+        #[serde(default = "default_reference_cells")]
+        reference_cells: usize,
+    },
+    /// Peak over the highest other peak of its Doppler bin, past `guard_chips`
+    PeakToSecondPeak {
+        #[serde(default = "default_peak_ratio")]
+        threshold: f32,
+        #[serde(default = "default_guard_chips")]
+        guard_chips: f32,
+    },
+}
+
+fn default_pfa() -> f64 {
+    1e-3
+}
+
+fn default_guard_chips() -> f32 {
+    1.5
+}
+
+fn default_reference_cells() -> usize {
+    128
+}
+
+fn default_peak_ratio() -> f32 {
+    2.0
+}
+
+impl Default for DetectorConfig {
+    fn default() -> Self {
+        DetectorConfig::ChiSquare { pfa: default_pfa() }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetectionStatistic {
+    ChiSquare,
+    CaCfar,
+    PeakToSecondPeak,
+}
+
+/// Outcome of a detection test, kept with the acquisition result to tune the detector
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Detection {
+    pub statistic: DetectionStatistic,
+    pub value: f32, // The statistic of the peak
+    pub threshold: f32, // Detected above it
+}
+
+impl Detection {
+    pub fn detected(&self) -> bool {
+        self.value > self.threshold
+    }
+}
+
+impl Display for Detection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{:?} {:.2}, threshold {:.2}", self.statistic, self.value, self.threshold)
+    }
+}
+
+/// P(X > x) for X ~ Gamma(k, 1), the power of k non-coherent integrations of unit noise
+pub fn gamma_tail(k: usize, x: f64) -> f64 {
+    let mut term = (-x).exp();
+    let mut sum = term;
+    for i in 1..k {
+        term *= x / i as f64;
+        sum += term;
+    }
+    sum
+}
+
+/// P(X > t Y) for X ~ Gamma(k, 1) and Y ~ Gamma(m, 1), a cell against the sum of the
+/// reference cells
+pub fn gamma_ratio_tail(k: usize, m: usize, t: f64) -> f64 {
+    // Terms C(m + i - 1, i) t^i / (1 + t)^(m + i), in logs as m is large
+    let mut ln_binomial = 0.0;
+    (0..k)
+        .map(|i| {
+            if i > 0 {
+                ln_binomial += ((m + i - 1) as f64 / i as f64).ln();
+            }
+            (ln_binomial + i as f64 * t.ln() - (m + i) as f64 * t.ln_1p()).exp()
+        })
+        .sum()
+}
+
+/// Smallest x with `tail(x) <= p`, `tail` decreasing from 1
+fn solve_tail(p: f64, tail: impl Fn(f64) -> f64) -> f64 {
+    let mut high = 1.0;
+    while tail(high) > p {
+        high *= 2.0;
+    }
+    let mut low = 0.0;
+    for _ in 0..100 {
+        let mid = 0.5 * (low + high);
+        if tail(mid) > p { low = mid } else { high = mid }
+    }
+    high
+}
+
+/// Tests the accumulated correlation power of the best Doppler bin. The thresholds of the
+/// statistics set by a false-alarm probability are computed for the whole search, all code phases
+/// of all Doppler bins, assuming independent cells.
+pub struct Detector {
+    pub config: DetectorConfig,
+    cached: Option<(usize, usize, usize, f32)>, // Integrations, cells, reference cells and threshold
+}
+
+impl Detector {
+    pub fn new(config: DetectorConfig) -> Self {
+        Self { config, cached: None }
+    }
+
+    /// Threshold of the statistic for `num_integrations` non-coherent integrations over `cells`
+    /// code phases and Doppler bins, CA-CFAR averaging `reference_cells` on each side of the peak
+    pub fn threshold(&mut self, num_integrations: usize, cells: usize, reference_cells: usize) -> f32 {
+        if let Some((k, n, reference, threshold)) = self.cached
+            && (k, n, reference) == (num_integrations, cells, reference_cells)
+        {
+            return threshold;
+        }
+        let k = num_integrations.max(1);
+        let threshold = match self.config {
+            DetectorConfig::ChiSquare { pfa } => {
+                // The peak over the mean noise power is Gamma(k) / k
+                (solve_tail(cell_pfa(pfa, cells), |x| gamma_tail(k, x)) / k as f64) as f32
+            }
+            DetectorConfig::CaCfar { pfa, .. } => {
+                // Against the mean of n reference cells, a sum of Gamma(k n)
+                let n = 2 * reference_cells;
+                (solve_tail(cell_pfa(pfa, cells), |t| gamma_ratio_tail(k, k * n, t)) * n as f64) as f32
+            }
+            DetectorConfig::PeakToSecondPeak { threshold, .. } => threshold,
+        };
+        self.cached = Some((num_integrations, cells, reference_cells, threshold));
+        threshold
+    }
+
+    /// Test the peak at `peak` of `power`, one Doppler bin out of `num_doppler_bins`
+    pub fn test(
+        &mut self,
+        power: &[f32],
+        peak: usize,
+        num_integrations: usize,
+        num_doppler_bins: usize,
+        samples_per_chip: f32,
+    ) -> Detection {
+        let n = power.len();
+        let reference = self.reference_cells(n, samples_per_chip);
+        let threshold = self.threshold(num_integrations, n * num_doppler_bins, reference);
+        let (statistic, value) = match self.config {
+            DetectorConfig::ChiSquare { .. } => {
+                let noise = (simd_sum(power) - power[peak]) / (n - 1) as f32;
+                (DetectionStatistic::ChiSquare, power[peak] / noise)
+            }
+            DetectorConfig::CaCfar { guard_chips, .. } => {
+                let guard = (guard_chips * samples_per_chip).ceil() as usize;
+                let noise = (guard + 1..=guard + reference)
+                    .map(|d| power[(peak + d) % n] + power[(peak + n - d) % n])
+                    .sum::<f32>()
+                    / (2 * reference) as f32;
+                (DetectionStatistic::CaCfar, power[peak] / noise)
+            }
+            DetectorConfig::PeakToSecondPeak { guard_chips, .. } => {
+                let guard = (guard_chips * samples_per_chip).ceil() as usize;
+                let second = (guard + 1..n.saturating_sub(guard))
+                    .map(|d| power[(peak + d) % n])
+                    .fold(f32::MIN_POSITIVE, f32::max);
+                (DetectionStatistic::PeakToSecondPeak, power[peak] / second)
+            }
+        };
+        Detection { statistic, value, threshold }
+    }
+
+    /// CA-CFAR reference cells on each side of the peak among `n` code phases, fewer than configured
+    /// when the two sides would overlap, 0 for the other statistics
+    pub fn reference_cells(&self, n: usize, samples_per_chip: f32) -> usize {
+        match self.config {
+            DetectorConfig::CaCfar { guard_chips, reference_cells, .. } => {
+                let guard = (guard_chips * samples_per_chip).ceil() as usize;
+                reference_cells.min(n.saturating_sub(2 * guard + 1) / 2).max(1)
+            }
+            _ => 0,
+        }
+    }
+}
+
+fn simd_sum(values: &[f32]) -> f32 {
+    let chunks = values.chunks_exact(8);
+    let remainder: f32 = chunks.remainder().iter().sum();
+    chunks
+        .map(f32x8::from_slice)
+        .fold(f32x8::splat(0.0), |acc, x| acc + x)
+        .reduce_sum()
+        + remainder
+}
+
+/// False-alarm probability of one cell for `pfa` over `cells`
+fn cell_pfa(pfa: f64, cells: usize) -> f64 {
+    // 1 - (1 - pfa)^(1 / cells), exact for the small values
+    -((-pfa).ln_1p() / cells as f64).exp_m1()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Accumulated power of `k` integrations of unit complex noise, from a xorshift generator
+    fn noise_power(k: usize, n: usize, seed: &mut u64) -> Vec<f32> {
+        let mut uniform = || {
+            *seed ^= *seed << 13;
+            *seed ^= *seed >> 7;
+            *seed ^= *seed << 17;
+            (*seed >> 11) as f64 / (1u64 << 53) as f64
+        };
+        (0..n)
+            .map(|_| (0..k).map(|_| -(1.0 - uniform()).ln()).sum::<f64>() as f32)
+            .collect()
+    }
+
+    #[test]
+    fn test_tails() {
+        assert!((gamma_tail(1, 5.0) - (-5.0f64).exp()).abs() < 1e-12);
+        assert!((gamma_ratio_tail(1, 20, 0.1) - 1.1f64.powi(-20)).abs() < 1e-12);
+        let x = solve_tail(1e-6, |x| gamma_tail(10, x));
+        assert!((gamma_tail(10, x) / 1e-6 - 1.0).abs() < 1e-6);
+        // The reference cells only estimate the noise, CA-CFAR needs a higher threshold
+        let mut chi_square = Detector::new(DetectorConfig::ChiSquare { pfa: 1e-3 });
+        let mut cfar = Detector::new(DetectorConfig::CaCfar { pfa: 1e-3, guard_chips: 1.5, reference_cells: 16 });
+        assert!(cfar.threshold(4, 4092 * 29, 16) > chi_square.threshold(4, 4092 * 29, 0));
+    }
+
+    #[test]
+    fn test_false_alarm_rate() {
+        let mut seed = 0x2545_f491_4f6c_dd1d;
+        let (k, cells, trials) = (4, 1024, 4000);
+        for config in [
+            DetectorConfig::ChiSquare { pfa: 0.05 },
+            DetectorConfig::CaCfar { pfa: 0.05, guard_chips: 0.0, reference_cells: 16 },
+            // More reference cells than the search has, 511 on each side are averaged
+            DetectorConfig::CaCfar { pfa: 0.05, guard_chips: 0.0, reference_cells: 2048 },
+        ] {
+            let mut detector = Detector::new(config);
+            // The highest noise peak of each search, against the threshold for the search
+            let mut alarms = 0;
+            for _ in 0..trials {
+                let power = noise_power(k, cells, &mut seed);
+                let peak = (0..cells).fold(0, |best, i| if power[i] > power[best] { i } else { best });
+                alarms += detector.test(&power, peak, k, 1, 1.0).detected() as usize;
+            }
+            let rate = alarms as f64 / trials as f64;
+            assert!((0.04..0.06).contains(&rate), "{:?}: {}", config, rate);
+        }
+    }
+
+    #[test]
+    fn test_clamped_reference_cells() {
+        let mut seed = 0x9e37_79b9_7f4a_7c15;
+        let power = noise_power(4, 64, &mut seed);
+        let mut cfar = Detector::new(DetectorConfig::CaCfar { pfa: 1e-3, guard_chips: 1.0, reference_cells: 64 });
+        // 2 guard cells on each side of the peak leave 29 reference cells
+        assert_eq!(cfar.reference_cells(64, 2.0), 29);
+        let detection = cfar.test(&power, 0, 4, 1, 2.0);
+        assert_eq!(detection.threshold, cfar.threshold(4, 64, 29));
+        assert!(detection.threshold > cfar.threshold(4, 64, 64));
+    }
+}
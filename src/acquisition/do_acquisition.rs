@@ -1,26 +1,38 @@
+use crate::acquisition::assistance::{Assistance, AssistanceConfig, SatellitePrediction};
+use crate::acquisition::detector::{Detection, Detector, DetectorConfig};
 use crate::acquisition::doppler_shift::{DopplerShiftTable, apply_doppler_shift};
+use crate::acquisition::input_spectrum::InputSpectrum;
 use crate::constants::gps_property_constants::{
     GPS_L1_CA_CODE_LENGTH_CHIPS, GPS_L1_CA_CODE_RATE_CHIPS_PER_S,
 };
+use crate::rf::nco_lut::NcoLut;
+use crate::sdr_store::sample_time::SampleTime;
 use crate::tracking::do_tracking::TrackingMessage;
 use crate::utilities::ca_code::generate_ca_code_samples;
-use crate::utilities::multicast_ring_buffer::MulticastRingBuffer;
+use crate::utilities::multicast_ring_buffer::{MulticastRingBuffer, RingSample};
 use num_complex::Complex32;
 use crossbeam_channel::{Sender, Receiver};
 use rayon::prelude::*;
 use rustfft::{Fft, FftPlanner};
+use serde::Deserialize;
 use std::collections::HashSet;
 use std::error::Error;
 use std::fmt;
-use std::simd::f32x8;
-use std::simd::num::SimdFloat;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, PoisonError};
 
 // const FFT_LENGTH_MS: u8 = 1;
 const FREQ_SEARCH_ACQUISITION_HZ: f32 = 14e3; // Hz
 const FREQ_SEARCH_STEP_HZ: u16 = 500; // Hz
 pub const PRN_SEARCH_ACQUISITION_TOTAL: u8 = 32; // 32 PRN codes to search
-const LONG_SAMPLES_LENGTH: usize = 10; // ms
+const DATA_BIT_MS: usize = 20;
+// Fine Doppler: the code and coarse carrier are wiped off and the samples summed over half
+// code periods, a 2 kHz rate covering the bin found and its neighbours. The zero-padded
+// spectrum of those sums peaks at the residual carrier.
+const FINE_BLOCKS_PER_CODE: usize = 2;
+const FINE_FFT_SIZE: usize = 256;
+const FINE_MAX_CODE_PERIODS: usize = 10; // The sums keep the data bits, longer windows see more of their edges
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ChannelState {
@@ -38,19 +50,42 @@ pub enum SearchMode {
 
 pub struct AcquisitionManager {
     mode: SearchMode,
+    assistance: Option<Assistance>,
+    predictions: Vec<SatellitePrediction>, // Visible satellites, the highest first
 }
 
 impl AcquisitionManager {
     pub fn new() -> Self {
         Self {
             mode: SearchMode::ColdStart,
+            assistance: None,
+            predictions: Vec::new(),
         }
     }
 
+    /// Searches the predicted satellites only, in their Doppler windows
+    pub fn with_assistance(mut self, assistance: Assistance) -> Self {
+        self.predictions = assistance.predict_at(assistance.gps_time_s);
+        self.assistance = Some(assistance);
+        self
+    }
+
+    /// Predicts the visible satellites `stream_time_s` after the first sample
+    pub fn update_predictions(&mut self, stream_time_s: f64) {
+        if let Some(assistance) = &self.assistance {
+            self.predictions = assistance.predict_at(assistance.gps_time_s + stream_time_s);
+        }
+    }
+
+    fn is_assisted(&self) -> bool {
+        !self.predictions.is_empty()
+    }
+
     pub fn update_mode(&mut self, trked_acount: usize) {
+        // The assistance is the prior information of a warm start
         self.mode = match trked_acount {
-            0 => SearchMode::ColdStart,
-            1..=4 => SearchMode::WarmStart,
+            0 if !self.is_assisted() => SearchMode::ColdStart,
+            0..=4 => SearchMode::WarmStart,
             _ => SearchMode::SteadyState,
         };
     }
@@ -58,11 +93,19 @@ impl AcquisitionManager {
     pub fn get_pacing_and_list(&self, active_prns: &HashSet<u8>) -> (u64, u32) {
         let (interval, search_size) = match self.mode {
             SearchMode::ColdStart => (500, PRN_SEARCH_ACQUISITION_TOTAL),
+            // All the visible satellites, in narrow windows
+            SearchMode::WarmStart if self.is_assisted() => (500, PRN_SEARCH_ACQUISITION_TOTAL),
             SearchMode::WarmStart => (1000, 8),
             SearchMode::SteadyState => (2000, 5),
         };
 
-        let mut candidates = (1..=PRN_SEARCH_ACQUISITION_TOTAL)
+        let prns: Vec<u8> = if self.is_assisted() {
+            self.predictions.iter().map(|p| p.prn).collect()
+        } else {
+            (1..=PRN_SEARCH_ACQUISITION_TOTAL).collect()
+        };
+        let mut candidates = prns
+            .into_iter()
             .filter(|prn| !active_prns.contains(prn))
             .collect::<Vec<u8>>();
         candidates.truncate(search_size as usize);
@@ -71,6 +114,28 @@ impl AcquisitionManager {
 
         (interval, mask)
     }
+
+    /// Predicted Doppler range of `prn`, None to search all of it
+    pub fn doppler_window(&self, prn: u8) -> Option<(f32, f32)> {
+        self.predictions
+            .iter()
+            .find(|p| p.prn == prn)
+            .map(|p| (p.doppler_hz - p.doppler_uncertainty_hz, p.doppler_hz + p.doppler_uncertainty_hz))
+    }
+}
+
+/// Tables covering the Doppler `window`, at least two, all of them when it misses the search
+fn doppler_range(tables: &[DopplerShiftTable], f_if: f32, (low, high): (f32, f32)) -> Range<usize> {
+    let half_step = FREQ_SEARCH_STEP_HZ as f32 / 2.0;
+    let start = tables.iter().position(|t| t.doppler_freq_hz - f_if >= low - half_step);
+    let end = tables.iter().rposition(|t| t.doppler_freq_hz - f_if <= high + half_step);
+    match (start, end) {
+        (Some(start), Some(end)) if start <= end => {
+            let end = (end + 1).max(start + 2).min(tables.len());
+            start.min(end.saturating_sub(2))..end
+        }
+        _ => 0..tables.len(),
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -90,15 +155,62 @@ impl<T> From<PoisonError<T>> for AcqError {
     }
 }
 
+/// How the coherent sums of a search are combined
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Accumulation {
+    #[default]
+    NonCoherent, // Sum of the powers
+    DifferentialCoherent, // Power of the sum of the sums turned by the phase of the previous one, a bit edge flips one term
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AcquisitionConfig {
+    #[serde(default)]
+    pub detector: DetectorConfig,
+    #[serde(default = "default_coherent_ms")]
+    pub coherent_ms: usize, // Code periods summed in phase, 1 to 10
+    #[serde(default = "default_non_coherent")]
+    pub non_coherent: usize, // Coherent sums accumulated
+    #[serde(default)]
+    pub accumulation: Accumulation,
+    #[serde(default)]
+    pub half_bit_alternation: bool, // Drop the coherent sums with the data bit edges, coherent_ms divides 20
+    pub assistance: Option<AssistanceConfig>, // Search the predicted satellites and Doppler when present
+}
+
+fn default_coherent_ms() -> usize {
+    1
+}
+
+fn default_non_coherent() -> usize {
+    10
+}
+
+impl Default for AcquisitionConfig {
+    fn default() -> Self {
+        Self {
+            detector: DetectorConfig::default(),
+            coherent_ms: default_coherent_ms(),
+            non_coherent: default_non_coherent(),
+            accumulation: Accumulation::default(),
+            half_bit_alternation: false,
+            assistance: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct AcquisitionResult {
     pub prn: u8,
-    pub code_phase_samples: usize,
-    pub code_phase_chips: f32,
-    pub carrier_freq: f32,
+    pub code_phase_samples: usize, // Code start from the first sample searched, to the nearest sample
+    pub code_phase_chips: f32, // Code phase at `sample_global_index`, with the sub-sample offset of the code start
+    pub carrier_freq: f32, // Refined around the search bin, IF included
     pub fs: f32,
     pub mag_relative: f32,
     pub sample_global_index: usize,
+    pub sample_time: Option<SampleTime>, // Time of the sample at `sample_global_index`
+    pub detection: Option<Detection>, // The test passed by the peak, None when not searched
 }
 
 impl AcquisitionResult {
@@ -111,6 +223,30 @@ impl AcquisitionResult {
             fs: 0.0,
             mag_relative: 0.0,
             sample_global_index: 0,
+            sample_time: None,
+            detection: None,
+        }
+    }
+}
+
+/// Where the correlations of a search come from, one per Doppler bin and code period
+enum Correlator<'a> {
+    Tables(&'a [DopplerShiftTable]), // Each code period wiped off and transformed by the worker
+    Spectrum(&'a InputSpectrum, Range<usize>), // The shared transform shifted to the bins searched
+}
+
+impl Correlator<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Correlator::Tables(tables) => tables.len(),
+            Correlator::Spectrum(_, bins) => bins.len(),
+        }
+    }
+
+    fn doppler_freq_hz(&self, bin: usize) -> f32 {
+        match self {
+            Correlator::Tables(tables) => tables[bin].doppler_freq_hz,
+            Correlator::Spectrum(spectrum, bins) => spectrum.bins[bins.start + bin].doppler_freq_hz,
         }
     }
 }
@@ -123,8 +259,14 @@ pub struct AcquisitionWorker {
     scratch_buf: Vec<Complex32>,
     freq_sampling_hz: f32,
     // doppler_table: &DopplerShiftTable,
+    ca_code_samples: Vec<i8>, // One code period, for the fine Doppler search
     ca_code_samples_fft: Vec<Complex32>,
     result_buf: Vec<Complex32>,
+    detector: Detector,
+    coherent_ms: usize, // Code periods summed in phase
+    accumulation: Accumulation,
+    half_bit_alternation: bool,
+    pub last_detection: Option<Detection>, // Test of the best peak of the last search, found or not
 }
 
 impl AcquisitionWorker {
@@ -132,7 +274,7 @@ impl AcquisitionWorker {
         let mut planner = FftPlanner::new();
         let ca_code_samples =
             generate_ca_code_samples(prn, GPS_L1_CA_CODE_RATE_CHIPS_PER_S, freq_sampling_hz);
-        let mut ca_code_samples_fft: Vec<Complex32> = ca_code_samples.into_iter().map(|x| Complex32::new(x as f32, 0.0)).collect();
+        let mut ca_code_samples_fft: Vec<Complex32> = ca_code_samples.iter().map(|x| Complex32::new(*x as f32, 0.0)).collect();
         planner
             .plan_fft_forward(fft_size)
             .process(&mut ca_code_samples_fft);
@@ -150,74 +292,184 @@ impl AcquisitionWorker {
             scratch_buf: vec![Complex32::new(0.0, 0.0); scratch_len],
             freq_sampling_hz: freq_sampling_hz,
             // doppler_table: doppler_table.as_slice(),
+            ca_code_samples,
             ca_code_samples_fft: ca_code_samples_fft,
             result_buf: vec![Complex32::new(0.0, 0.0); fft_size],
+            detector: Detector::new(DetectorConfig::default()),
+            coherent_ms: 1,
+            accumulation: Accumulation::NonCoherent,
+            half_bit_alternation: false,
+            last_detection: None,
         }
     }
 
+    pub fn with_detector(mut self, config: DetectorConfig) -> Self {
+        self.detector = Detector::new(config);
+        self
+    }
+
+    /// Sums `coherent_ms` code periods in phase, the carrier searched in that many sub-bins between
+    /// the tables, then accumulates `num_integrations` of those sums
+    pub fn with_coherent_integration(mut self, coherent_ms: usize, accumulation: Accumulation, half_bit_alternation: bool) -> Self {
+        self.coherent_ms = coherent_ms.max(1);
+        self.accumulation = accumulation;
+        self.half_bit_alternation = half_bit_alternation;
+        self
+    }
+
+    /// Searches `num_integrations` coherent sums of `samples_chunk`, the code periods from its start
     pub fn search_satellite(
         &mut self,
         samples_chunk: &[Complex32],
         doppler_table: &[DopplerShiftTable],
         local_tail: usize,
         num_integrations: usize,
+    ) -> Option<AcquisitionResult> {
+        self.search(samples_chunk, Correlator::Tables(doppler_table), local_tail, num_integrations)
+    }
+
+    /// Same search over `bins` of `spectrum`, the shared transform of `samples_chunk`
+    pub fn search_spectrum(
+        &mut self,
+        samples_chunk: &[Complex32],
+        spectrum: &InputSpectrum,
+        bins: Range<usize>,
+        local_tail: usize,
+        num_integrations: usize,
+    ) -> Option<AcquisitionResult> {
+        self.search(samples_chunk, Correlator::Spectrum(spectrum, bins), local_tail, num_integrations)
+    }
+
+    fn search(
+        &mut self,
+        samples_chunk: &[Complex32],
+        correlator: Correlator,
+        local_tail: usize,
+        num_integrations: usize,
     ) -> Option<AcquisitionResult> {
         let mut global_max_val: f32 = 0.0;
         let mut best_doppler_freq: f32 = 0.0;
         let mut best_code_phase: usize = 0;
         let mut best_power_results = vec![0.0; self.fft_size];
-        let mut accumulated_power = vec![0.0; self.fft_size];
+        let mut best_kept_sums = num_integrations;
 
-        for doppler in doppler_table.iter() {
-            accumulated_power.fill(0.0);
+        // The sub-bins split the spacing of the tables, narrower bins for the longer sums
+        let step = if correlator.len() > 1 {
+            correlator.doppler_freq_hz(1) - correlator.doppler_freq_hz(0)
+        } else {
+            0.0
+        };
+        let sub_bins: Vec<f32> = (0..self.coherent_ms)
+            .map(|s| step * ((s as f32 + 0.5) / self.coherent_ms as f32 - 0.5))
+            .collect();
+        let num_classes = match self.accumulation {
+            Accumulation::NonCoherent
+                if self.half_bit_alternation
+                    && DATA_BIT_MS.is_multiple_of(self.coherent_ms)
+                    && num_integrations >= DATA_BIT_MS / self.coherent_ms =>
+            {
+                DATA_BIT_MS / self.coherent_ms
+            }
+            _ => 1,
+        };
+        let code_period_s = self.fft_size as f64 / self.freq_sampling_hz as f64;
+        let zeros = vec![Complex32::new(0.0, 0.0); self.fft_size];
+        let mut coherent = vec![zeros.clone(); sub_bins.len()];
+        let mut previous = vec![zeros.clone(); sub_bins.len()];
+        let mut differential = vec![zeros; sub_bins.len()];
+        let mut class_power = vec![vec![vec![0.0; self.fft_size]; num_classes]; sub_bins.len()];
+        let mut accumulated_power = vec![0.0; self.fft_size];
 
-            for c in 0..num_integrations {
-                let offset = c * self.fft_size;
-                let chunk = &samples_chunk[offset..offset + self.fft_size];
-                apply_doppler_shift(
-                    chunk,
-                    doppler,
-                    &mut self.result_buf,
-                );
-                self.fft.process_with_scratch(&mut self.result_buf, &mut self.scratch_buf);
-
-                for i in 0..self.fft_size {
-                    self.result_buf[i] *= self.ca_code_samples_fft[i].conj();
+        for bin in 0..correlator.len() {
+            let doppler_freq_hz = correlator.doppler_freq_hz(bin);
+            class_power.iter_mut().flatten().for_each(|p| p.fill(0.0));
+            differential.iter_mut().for_each(|d| d.fill(Complex32::new(0.0, 0.0)));
+
+            for n in 0..num_integrations {
+                coherent.iter_mut().for_each(|c| c.fill(Complex32::new(0.0, 0.0)));
+                for m in 0..self.coherent_ms {
+                    let c = n * self.coherent_ms + m;
+                    self.correlate(&correlator, samples_chunk, bin, c);
+
+                    // Every code period is wiped off from a zero carrier phase, put back in line
+                    for (sum, sub_bin) in coherent.iter_mut().zip(&sub_bins) {
+                        let cycles = ((doppler_freq_hz + sub_bin) as f64 * c as f64 * code_period_s).fract();
+                        let rotation = Complex32::from_polar(1.0, (-std::f64::consts::TAU * cycles) as f32);
+                        for (acc, val) in sum.iter_mut().zip(&self.result_buf) {
+                            *acc += val * rotation;
+                        }
+                    }
                 }
 
-                self.ifft.process_with_scratch(&mut self.result_buf, &mut self.scratch_buf);
-
-                for (idx, val) in self.result_buf.iter().enumerate() {
-                    accumulated_power[idx] += val.norm_sqr();
+                for (s, sum) in coherent.iter().enumerate() {
+                    match self.accumulation {
+                        Accumulation::NonCoherent => {
+                            for (p, val) in class_power[s][n % num_classes].iter_mut().zip(sum) {
+                                *p += val.norm_sqr();
+                            }
+                        }
+                        Accumulation::DifferentialCoherent if n > 0 => {
+                            for ((d, val), prev) in differential[s].iter_mut().zip(sum).zip(&previous[s]) {
+                                let norm = prev.norm();
+                                if norm > 0.0 {
+                                    *d += val * prev.conj() / norm;
+                                }
+                            }
+                        }
+                        Accumulation::DifferentialCoherent => {}
+                    }
                 }
+                std::mem::swap(&mut previous, &mut coherent);
             }
 
-            let mut local_max = 0.0;
-            let mut local_best_phase = 0;
-            for (idx, &power) in accumulated_power.iter().enumerate() {
-                if power > local_max {
-                    local_max = power;
-                    local_best_phase = idx;
+            for (s, sub_bin) in sub_bins.iter().enumerate() {
+                let kept_sums = match self.accumulation {
+                    Accumulation::NonCoherent => {
+                        drop_bit_edge_class(&class_power[s], num_integrations, &mut accumulated_power)
+                    }
+                    Accumulation::DifferentialCoherent => {
+                        for (p, d) in accumulated_power.iter_mut().zip(&differential[s]) {
+                            *p = d.norm_sqr();
+                        }
+                        1 // Noise turned by an independent phase stays normal
+                    }
+                };
+
+                let (local_best_phase, local_max) = peak_of(&accumulated_power);
+                if local_max > global_max_val {
+                    global_max_val = local_max;
+                    best_doppler_freq = doppler_freq_hz + sub_bin;
+                    best_code_phase = local_best_phase;
+                    best_kept_sums = kept_sums;
+                    best_power_results.copy_from_slice(&accumulated_power);
                 }
             }
 
-            if local_max > global_max_val {
-                global_max_val = local_max;
-                best_doppler_freq = doppler.doppler_freq_hz;
-                best_code_phase = local_best_phase;
-                best_power_results.copy_from_slice(&accumulated_power);
-            }
-
-            if self.is_good_satellite(&best_power_results, global_max_val) {
+            // Every sub-bin and choice of the dropped class is a chance for a false alarm
+            let detection = self.detector.test(
+                &best_power_results,
+                best_code_phase,
+                best_kept_sums,
+                correlator.len() * sub_bins.len() * num_classes,
+                self.freq_sampling_hz / GPS_L1_CA_CODE_RATE_CHIPS_PER_S,
+            );
+            self.last_detection = Some(detection);
+            if detection.detected() {
+                // The code starts `offset` samples after `best_code_phase`, where its phase is -offset
+                let offset = code_peak_offset(&best_power_results, best_code_phase);
+                let code_phase_chips = (-offset * GPS_L1_CA_CODE_RATE_CHIPS_PER_S / self.freq_sampling_hz)
+                    .rem_euclid(GPS_L1_CA_CODE_LENGTH_CHIPS);
+                let num_code_periods = (num_integrations * self.coherent_ms).min(FINE_MAX_CODE_PERIODS);
                 return Some(AcquisitionResult {
                     prn: self.prn,
                     code_phase_samples: best_code_phase,
-                    code_phase_chips: best_code_phase as f32 * GPS_L1_CA_CODE_RATE_CHIPS_PER_S
-                        / self.freq_sampling_hz,
-                    carrier_freq: best_doppler_freq,
+                    code_phase_chips,
+                    carrier_freq: self.fine_doppler(samples_chunk, best_code_phase, best_doppler_freq, num_code_periods),
                     fs: self.freq_sampling_hz,
                     mag_relative: global_max_val,
                     sample_global_index: local_tail + best_code_phase,
+                    sample_time: None,
+                    detection: Some(detection),
                 });
             }
         }
@@ -225,25 +477,140 @@ impl AcquisitionWorker {
         return None;
     }
 
-    // SIMD sum
-    fn is_good_satellite(&self, power_results: &[f32], max_val: f32) -> bool {
-        let sum_power = power_results
-            .chunks_exact(8)
-            .map(|chunk| f32x8::from_slice(chunk))
-            .fold(f32x8::splat(0.0), |acc, x| acc + x)
-            .reduce_sum();
-        let avg_power: f32 = (sum_power - max_val) / (self.fft_size - 1) as f32;
+    /// Circular correlation of code period `c` in `bin` with the code, left in `result_buf`
+    fn correlate(&mut self, correlator: &Correlator, samples_chunk: &[Complex32], bin: usize, c: usize) {
+        match correlator {
+            Correlator::Tables(tables) => {
+                let offset = c * self.fft_size;
+                self.correlate_code_period(&samples_chunk[offset..offset + self.fft_size], &tables[bin]);
+            }
+            Correlator::Spectrum(spectrum, bins) => {
+                spectrum.multiply_code(bins.start + bin, c, &self.ca_code_samples_fft, &mut self.result_buf);
+                self.ifft.process_with_scratch(&mut self.result_buf, &mut self.scratch_buf);
+            }
+        }
+    }
+
+    /// Circular correlation of one code period with the code, left in `result_buf`
+    fn correlate_code_period(&mut self, chunk: &[Complex32], doppler: &DopplerShiftTable) {
+        apply_doppler_shift(
+            chunk,
+            doppler,
+            &mut self.result_buf,
+        );
+        self.fft.process_with_scratch(&mut self.result_buf, &mut self.scratch_buf);
+
+        for i in 0..self.fft_size {
+            self.result_buf[i] *= self.ca_code_samples_fft[i].conj();
+        }
+
+        self.ifft.process_with_scratch(&mut self.result_buf, &mut self.scratch_buf);
+    }
+
+    /// Carrier frequency refined around `coarse_freq`, from the whole code periods of
+    /// `samples_chunk` after the code start
+    pub fn fine_doppler(
+        &self,
+        samples_chunk: &[Complex32],
+        code_start: usize,
+        coarse_freq: f32,
+        num_code_periods: usize,
+    ) -> f32 {
+        let code_len = self.ca_code_samples.len();
+        let block_len = code_len / FINE_BLOCKS_PER_CODE;
+        let num_blocks = ((num_code_periods - 1) * FINE_BLOCKS_PER_CODE).min(FINE_FFT_SIZE);
+        if num_blocks < 2 {
+            return coarse_freq;
+        }
+
+        let mut nco = NcoLut::new(coarse_freq, self.freq_sampling_hz).with_interpolation();
+        let mut sums = vec![Complex32::new(0.0, 0.0); FINE_FFT_SIZE];
+        for (b, sum) in sums[..num_blocks].iter_mut().enumerate() {
+            let start = code_start + b * block_len;
+            for (n, sample) in samples_chunk[start..start + block_len].iter().enumerate() {
+                let (re, im) = nco.next_value();
+                let chip = self.ca_code_samples[(b * block_len + n) % code_len] as f32;
+                *sum += sample * Complex32::new(re, im) * chip;
+            }
+        }
+        FftPlanner::new().plan_fft_forward(FINE_FFT_SIZE).process(&mut sums);
+
+        let magnitude: Vec<f32> = sums.iter().map(|x| x.norm()).collect();
+        let peak = (0..FINE_FFT_SIZE).fold(0, |best, k| if magnitude[k] > magnitude[best] { k } else { best });
+        let (before, after) = (
+            magnitude[(peak + FINE_FFT_SIZE - 1) % FINE_FFT_SIZE],
+            magnitude[(peak + 1) % FINE_FFT_SIZE],
+        );
+        // Parabola through the peak and its neighbours
+        let denominator = before - 2.0 * magnitude[peak] + after;
+        let fraction = if denominator < 0.0 { 0.5 * (before - after) / denominator } else { 0.0 };
+        let bin = if peak > FINE_FFT_SIZE / 2 { peak as f32 - FINE_FFT_SIZE as f32 } else { peak as f32 };
+        let block_rate = self.freq_sampling_hz / block_len as f32;
+        coarse_freq + (bin + fraction) * block_rate / FINE_FFT_SIZE as f32
+    }
+}
+
+/// Index and value of the highest power
+fn peak_of(power: &[f32]) -> (usize, f32) {
+    let mut local_max = 0.0;
+    let mut local_best_phase = 0;
+    for (idx, &power) in power.iter().enumerate() {
+        if power > local_max {
+            local_max = power;
+            local_best_phase = idx;
+        }
+    }
+    (local_best_phase, local_max)
+}
+
+/// Power of the coherent sums of all the classes but the one hit by the data bit edges, the
+/// weakest at the peak. Sum `n` is in class `n % class_power.len()`, an edge every `DATA_BIT_MS`
+/// is always in the same class. Returns the number of sums kept.
+fn drop_bit_edge_class(class_power: &[Vec<f32>], num_sums: usize, power: &mut [f32]) -> usize {
+    power.fill(0.0);
+    for class in class_power {
+        for (p, x) in power.iter_mut().zip(class) {
+            *p += x;
+        }
+    }
+    let num_classes = class_power.len();
+    if num_classes == 1 {
+        return num_sums;
+    }
 
-        max_val / avg_power > 7.0
+    let (peak, _) = peak_of(power);
+    let dropped = (0..num_classes).fold(0, |weakest, c| {
+        if class_power[c][peak] < class_power[weakest][peak] { c } else { weakest }
+    });
+    power.fill(0.0);
+    for class in class_power.iter().enumerate().filter(|(c, _)| *c != dropped).map(|(_, class)| class) {
+        for (p, x) in power.iter_mut().zip(class) {
+            *p += x;
+        }
     }
+    num_sums - (num_sums - dropped).div_ceil(num_classes)
+}
+
+/// Offset of the correlation peak from the sample `peak` of `power`, -0.5 to 0.5 samples. The
+/// code correlation is a triangle, exactly located by the slopes on both sides of the peak.
+fn code_peak_offset(power: &[f32], peak: usize) -> f32 {
+    let n = power.len();
+    let before = power[(peak + n - 1) % n].sqrt();
+    let at = power[peak].sqrt();
+    let after = power[(peak + 1) % n].sqrt();
+    let drop = at - before.min(after);
+    if drop > 0.0 { (0.5 * (after - before) / drop).clamp(-0.5, 0.5) } else { 0.0 }
 }
 
-pub fn run(
-    multi_buffer: Arc<MulticastRingBuffer>,
+/// Searches the newest samples for the satellites not being tracked, until `stop` is set
+pub fn run<T: RingSample>(
+    multi_buffer: Arc<MulticastRingBuffer<T>>,
     freq_sampling_hz: f32,
     f_if: f32,
     to_tracking: Sender<AcquisitionResult>,
     from_tracking: Receiver<TrackingMessage>,
+    config: &AcquisitionConfig,
+    stop: &AtomicBool,
 ) -> Result<(), AcqError> {
     let capacity = (FREQ_SEARCH_ACQUISITION_HZ as u16 / FREQ_SEARCH_STEP_HZ) as usize + 1;
     let fft_size = (freq_sampling_hz
@@ -261,20 +628,40 @@ pub fn run(
         ));
     }
 
+    // Transformed once per search, shifted to the Doppler bins of every satellite
+    let mut spectrum = InputSpectrum::new(&doppler_table, fft_size, freq_sampling_hz);
     let mut active_prns = HashSet::new();
 
     let mut acq_manager = AcquisitionManager::new();
+    if let Some(assistance_config) = &config.assistance {
+        match Assistance::from_config(assistance_config) {
+            Ok(assistance) => {
+                acq_manager = acq_manager.with_assistance(assistance);
+                println!("Assisted acquisition: {} satellites predicted visible", acq_manager.predictions.len());
+            }
+            Err(e) => println!("Warning: Acquisition not assisted: {}", e),
+        }
+    }
 
     let mut workers = (1..=PRN_SEARCH_ACQUISITION_TOTAL)
         .into_par_iter()
-        .filter_map(|prn| Some(AcquisitionWorker::new(prn, fft_size, freq_sampling_hz)))
+        .map(|prn| {
+            AcquisitionWorker::new(prn, fft_size, freq_sampling_hz)
+                .with_detector(config.detector)
+                .with_coherent_integration(config.coherent_ms, config.accumulation, config.half_bit_alternation)
+        })
         .collect::<Vec<AcquisitionWorker>>();
 
-    let samples_integration_size = fft_size * LONG_SAMPLES_LENGTH;
+    let samples_integration_size = fft_size * config.coherent_ms * config.non_coherent;
     let mut chunk_samples = vec![Complex32::new(0.0, 0.0); samples_integration_size];
     let mut last_run = std::time::Instant::now();
 
     loop {
+        // No new samples will come to search
+        if stop.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
         while let Ok(msg) = from_tracking.try_recv() {
             match msg {
                 TrackingMessage::SatelliteLost(prn) => {
@@ -286,6 +673,7 @@ pub fn run(
             }
         }
 
+        acq_manager.update_predictions(multi_buffer.get_head() as f64 / freq_sampling_hz as f64);
         acq_manager.update_mode(active_prns.len());
         let (interval_ms, mask) = acq_manager.get_pacing_and_list(&active_prns);
 
@@ -298,21 +686,33 @@ pub fn run(
 
         if (head.wrapping_sub(samples_integration_size) as isize) >=0 {
             let local_tail = head.wrapping_sub(samples_integration_size);
-            multi_buffer.copy_to_slice(local_tail, &mut chunk_samples);
+            // Pinned while copied, the writer waits instead of overwriting the chunk
+            match multi_buffer.read(local_tail, samples_integration_size) {
+                Ok(chunk) => chunk.copy_to_slice(&mut chunk_samples),
+                Err(_) => continue, // Overwritten or no free view, retried with the newest samples
+            }
+            if mask != 0 {
+                spectrum.update(&chunk_samples, config.coherent_ms * config.non_coherent);
+            }
             let results: Vec<AcquisitionResult> = workers
                 .par_iter_mut()
                 .enumerate()
                 .filter_map(|(i, worker)| {
                     let prn = i as u8 + 1;
                     if (mask >> (prn - 1)) & 1 == 1 {
-                        worker.search_satellite(&chunk_samples, &doppler_table, local_tail, LONG_SAMPLES_LENGTH)
+                        let bins = match acq_manager.doppler_window(prn) {
+                            Some(window) => doppler_range(&doppler_table, f_if, window),
+                            None => 0..doppler_table.len(),
+                        };
+                        worker.search_spectrum(&chunk_samples, &spectrum, bins, local_tail, config.non_coherent)
                     } else {
                         None
                     }
                 })
                 .collect();
 
-            for result in results {
+            for mut result in results {
+                result.sample_time = multi_buffer.time_of(result.sample_global_index);
                 let prn = result.prn;
                 if to_tracking.send(result).is_ok() {
                     active_prns.insert(prn);
@@ -329,6 +729,8 @@ pub fn run(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::acquisition::detector::DetectionStatistic;
+    use crate::simulator::signal_generator::{SignalGenerator, SimConfig, SimSatellite};
     use num_complex::Complex32;
     use std::collections::HashSet;
     use std::fs::File;
@@ -394,6 +796,304 @@ mod tests {
         assert_eq!(mask, 2040);
     }
 
+    #[test]
+    fn test_assisted_search() {
+        const FS: f32 = 4_092_000.0;
+        const NUM_INTEGRATIONS: usize = 10;
+        let fft_size = (FS / 1000.0) as usize;
+
+        let config: AssistanceConfig = toml::from_str(
+            r#"
+            rinex_path = "src/test_data/BRDC00WRD_R_20233330000_01D_GN.rnx"
+            latitude_deg = 50.09
+            longitude_deg = 8.66
+            time_utc = "2023-11-29T21:00:00Z"
+            "#,
+        )
+        .expect("Assistance config not parsed");
+        let mut manager = AcquisitionManager::new()
+            .with_assistance(Assistance::from_config(&config).expect("Assistance not loaded"));
+        manager.update_predictions(2.0);
+        manager.update_mode(0);
+
+        // Only the visible satellites, all of them as they are cheap to search
+        let (interval, mask) = manager.get_pacing_and_list(&HashSet::new());
+        assert_eq!(interval, 500);
+        let visible = manager.predictions.iter().fold(0u32, |acc, p| acc | 1 << (p.prn - 1));
+        assert_eq!(mask, visible);
+        assert!(mask.count_ones() < 16);
+
+        let doppler_tables: Vec<DopplerShiftTable> = (0..29)
+            .map(|i| DopplerShiftTable::new(0.0, -7000.0 + 500.0 * i as f32, FS, fft_size))
+            .collect();
+        let prediction = manager.predictions[0];
+        let window = manager.doppler_window(prediction.prn).expect("No window for a visible satellite");
+        let tables = &doppler_tables[doppler_range(&doppler_tables, 0.0, window)];
+        assert!(tables.len() <= 9, "{} tables", tables.len());
+        let hidden = (1..=32).find(|prn| mask & 1 << (prn - 1) == 0).unwrap();
+        assert!(manager.doppler_window(hidden).is_none());
+
+        // The receiver clock is 0.3 ppm off, the window still has the satellite
+        let carrier = prediction.doppler_hz + 470.0;
+        let mut sim = SimConfig::new(FS as f64, 0.0, 4);
+        sim.satellites.push(SimSatellite::new(prediction.prn, 300.0, carrier as f64, 44.0));
+        let samples = SignalGenerator::new(sim).generate_samples(NUM_INTEGRATIONS * fft_size);
+        let mut worker = AcquisitionWorker::new(prediction.prn, fft_size, FS);
+        let acq = worker
+            .search_satellite(&samples, tables, 0, NUM_INTEGRATIONS)
+            .expect("Predicted satellite not acquired");
+        assert!((acq.carrier_freq - carrier).abs() < 50.0, "{} Hz, expected {}", acq.carrier_freq, carrier);
+    }
+
+    #[test]
+    fn test_doppler_range() {
+        let tables: Vec<DopplerShiftTable> = (0..29)
+            .map(|i| DopplerShiftTable::new(1000.0, -7000.0 + 500.0 * i as f32, 4e6, 8))
+            .collect();
+        assert_eq!(doppler_range(&tables, 1000.0, (-600.0, 400.0)), 13..16); // -500, 0 and 500 Hz
+        // At least two tables, all of them out of the search
+        assert_eq!(doppler_range(&tables, 1000.0, (7000.0, 7000.0)), 27..29);
+        assert_eq!(doppler_range(&tables, 1000.0, (9000.0, 9500.0)), 0..29);
+    }
+
+    #[test]
+    fn test_acquisition_with_simulated_signal() {
+        const FS: f32 = 4_092_000.0;
+        const NUM_INTEGRATIONS: usize = 10;
+        let fft_size = (FS / 1000.0) as usize;
+
+        // PRN 6 code starts at sample 800 (200 chips), PRN 17 is weaker
+        let mut config = SimConfig::new(FS as f64, 0.0, 11);
+        config.satellites.push(SimSatellite::new(6, 823.0, 2000.0, 45.0));
+        config.satellites.push(SimSatellite::new(17, 100.0, -3500.0, 42.0));
+        let samples = SignalGenerator::new(config).generate_samples(NUM_INTEGRATIONS * fft_size);
+
+        let doppler_tables: Vec<DopplerShiftTable> = (0..29)
+            .map(|i| DopplerShiftTable::new(0.0, -7000.0 + 500.0 * i as f32, FS, fft_size))
+            .collect();
+
+        let mut worker = AcquisitionWorker::new(6, fft_size, FS);
+        let acq = worker
+            .search_satellite(&samples, &doppler_tables, 0, NUM_INTEGRATIONS)
+            .expect("PRN 6 not acquired");
+        assert_eq!(acq.code_phase_samples, 800);
+        // The search stops at the first bin above the threshold, the fine search corrects it
+        assert!((acq.carrier_freq - 2000.0).abs() < 50.0, "{} Hz", acq.carrier_freq);
+
+        let mut worker = AcquisitionWorker::new(17, fft_size, FS);
+        let acq = worker
+            .search_satellite(&samples, &doppler_tables, 0, NUM_INTEGRATIONS)
+            .expect("PRN 17 not acquired");
+        assert!(acq.code_phase_samples.abs_diff(3692) <= 1); // Code Doppler drifts the code start
+        assert!((acq.carrier_freq + 3500.0).abs() < 50.0, "{} Hz", acq.carrier_freq);
+
+        let mut worker = AcquisitionWorker::new(9, fft_size, FS);
+        assert!(worker.search_satellite(&samples, &doppler_tables, 0, NUM_INTEGRATIONS).is_none());
+    }
+
+    #[test]
+    fn test_search_spectrum() {
+        const FS: f32 = 4_092_000.0;
+        const NUM_INTEGRATIONS: usize = 10;
+        let fft_size = (FS / 1000.0) as usize;
+
+        let mut config = SimConfig::new(FS as f64, 0.0, 11);
+        config.satellites.push(SimSatellite::new(6, 823.0, 2000.0, 45.0));
+        config.satellites.push(SimSatellite::new(17, 100.0, -3500.0, 42.0));
+        let samples = SignalGenerator::new(config).generate_samples(NUM_INTEGRATIONS * fft_size);
+        let doppler_tables: Vec<DopplerShiftTable> = (0..29)
+            .map(|i| DopplerShiftTable::new(0.0, -7000.0 + 500.0 * i as f32, FS, fft_size))
+            .collect();
+
+        // The 500 Hz bins fall on the 1 kHz lines or between them
+        let mut spectrum = InputSpectrum::new(&doppler_tables, fft_size, FS);
+        assert_eq!(spectrum.num_residuals(), 2);
+        spectrum.update(&samples, NUM_INTEGRATIONS);
+
+        for (prn, bins) in [(6, 0..29), (17, 0..29), (17, 5..10)] {
+            let mut worker = AcquisitionWorker::new(prn, fft_size, FS);
+            let expected = worker
+                .search_satellite(&samples, &doppler_tables[bins.clone()], 0, NUM_INTEGRATIONS)
+                .expect("Not acquired from the tables");
+            let acq = worker
+                .search_spectrum(&samples, &spectrum, bins, 0, NUM_INTEGRATIONS)
+                .expect("Not acquired from the spectrum");
+            assert_eq!(acq.code_phase_samples, expected.code_phase_samples);
+            assert!((acq.carrier_freq - expected.carrier_freq).abs() < 1.0, "{} Hz", acq.carrier_freq);
+            assert!((acq.mag_relative / expected.mag_relative - 1.0).abs() < 1e-3);
+        }
+
+        // Out of its Doppler window
+        let mut worker = AcquisitionWorker::new(17, fft_size, FS);
+        assert!(worker.search_spectrum(&samples, &spectrum, 20..29, 0, NUM_INTEGRATIONS).is_none());
+    }
+
+    /// Run with `cargo test --release -- --ignored bench_shared_input_fft --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_shared_input_fft() {
+        const FS: f32 = 16_367_600.0;
+        const IF: f32 = 4_130_400.0;
+        const NUM_INTEGRATIONS: usize = 10;
+        const ROUNDS: usize = 5;
+        let fft_size = 16368;
+
+        // Noise only, every satellite searches all the bins
+        let samples = SignalGenerator::new(SimConfig::new(FS as f64, IF as f64, 4)).generate_samples(NUM_INTEGRATIONS * fft_size);
+        let doppler_tables: Vec<DopplerShiftTable> = (0..29)
+            .map(|i| DopplerShiftTable::new(IF, -7000.0 + 500.0 * i as f32, FS, fft_size))
+            .collect();
+        let mut workers: Vec<AcquisitionWorker> = (1..=PRN_SEARCH_ACQUISITION_TOTAL)
+            .map(|prn| AcquisitionWorker::new(prn, fft_size, FS))
+            .collect();
+        let mut spectrum = InputSpectrum::new(&doppler_tables, fft_size, FS);
+
+        for shared in [false, true] {
+            let start = Instant::now();
+            for _ in 0..ROUNDS {
+                if shared {
+                    spectrum.update(&samples, NUM_INTEGRATIONS);
+                }
+                let found = workers
+                    .par_iter_mut()
+                    .filter_map(|worker| {
+                        if shared {
+                            worker.search_spectrum(&samples, &spectrum, 0..doppler_tables.len(), 0, NUM_INTEGRATIONS)
+                        } else {
+                            worker.search_satellite(&samples, &doppler_tables, 0, NUM_INTEGRATIONS)
+                        }
+                    })
+                    .count();
+                std::hint::black_box(found);
+            }
+            println!(
+                "{}: {:.1} ms per search of {} PRNs, {} bins and {} ms",
+                if shared { "shared input FFT" } else { "per-worker FFT" },
+                start.elapsed().as_secs_f64() * 1e3 / ROUNDS as f64,
+                PRN_SEARCH_ACQUISITION_TOTAL,
+                doppler_tables.len(),
+                NUM_INTEGRATIONS
+            );
+        }
+    }
+
+    #[test]
+    fn test_detectors() {
+        const FS: f32 = 4_092_000.0;
+        const NUM_INTEGRATIONS: usize = 10;
+        let fft_size = (FS / 1000.0) as usize;
+
+        let mut config = SimConfig::new(FS as f64, 0.0, 3);
+        config.satellites.push(SimSatellite::new(6, 823.0, 2000.0, 44.0));
+        let samples = SignalGenerator::new(config).generate_samples(NUM_INTEGRATIONS * fft_size);
+        let doppler_tables: Vec<DopplerShiftTable> = (0..29)
+            .map(|i| DopplerShiftTable::new(0.0, -7000.0 + 500.0 * i as f32, FS, fft_size))
+            .collect();
+
+        for (detector, statistic) in [
+            (DetectorConfig::ChiSquare { pfa: 1e-3 }, DetectionStatistic::ChiSquare),
+            (DetectorConfig::CaCfar { pfa: 1e-3, guard_chips: 1.5, reference_cells: 128 }, DetectionStatistic::CaCfar),
+            (DetectorConfig::PeakToSecondPeak { threshold: 2.0, guard_chips: 1.5 }, DetectionStatistic::PeakToSecondPeak),
+        ] {
+            let mut worker = AcquisitionWorker::new(6, fft_size, FS).with_detector(detector);
+            let acq = worker
+                .search_satellite(&samples, &doppler_tables, 0, NUM_INTEGRATIONS)
+                .unwrap_or_else(|| panic!("PRN 6 not acquired with {:?}", detector));
+            assert_eq!(acq.code_phase_samples, 800);
+            let detection = acq.detection.expect("No detection recorded");
+            assert_eq!(detection.statistic, statistic);
+            assert!(detection.value > detection.threshold);
+
+            // Noise only, the best peak of the search stays below the threshold
+            let mut worker = AcquisitionWorker::new(9, fft_size, FS).with_detector(detector);
+            assert!(worker.search_satellite(&samples, &doppler_tables, 0, NUM_INTEGRATIONS).is_none());
+            let detection = worker.last_detection.expect("No detection recorded");
+            assert!(detection.value <= detection.threshold, "{}", detection);
+        }
+    }
+
+    #[test]
+    fn test_weak_signal_coherent_integration() {
+        const FS: f32 = 4_092_000.0;
+        let fft_size = (FS / 1000.0) as usize;
+
+        // A data bit edge 15 ms in, in the middle of the second 10 ms coherent sum
+        let mut config = SimConfig::new(FS as f64, 0.0, 8);
+        let mut sat = SimSatellite::new(28, 400.0, 1320.0, 32.0);
+        sat.nav_bits = vec![1, -1];
+        sat.bit_offset_ms = 5;
+        config.satellites.push(sat);
+        let samples = SignalGenerator::new(config).generate_samples(80 * fft_size);
+        let doppler_tables: Vec<DopplerShiftTable> = (0..9)
+            .map(|i| DopplerShiftTable::new(0.0, -1000.0 + 500.0 * i as f32, FS, fft_size))
+            .collect();
+
+        // 10 ms of non-coherent sums miss it
+        let mut worker = AcquisitionWorker::new(28, fft_size, FS);
+        assert!(worker.search_satellite(&samples, &doppler_tables, 0, 10).is_none());
+
+        // The bit edges cancel half the sums, dropped by the alternation
+        for (accumulation, half_bit_alternation) in [
+            (Accumulation::NonCoherent, true),
+            (Accumulation::DifferentialCoherent, false),
+        ] {
+            let mut worker = AcquisitionWorker::new(28, fft_size, FS)
+                .with_coherent_integration(10, accumulation, half_bit_alternation);
+            let acq = worker
+                .search_satellite(&samples, &doppler_tables, 0, 8)
+                .unwrap_or_else(|| panic!("PRN 28 not acquired with {:?}, {}", accumulation, half_bit_alternation));
+            assert!(acq.code_phase_samples.abs_diff(2492) <= 1, "{}", acq.code_phase_samples);
+            assert!((acq.carrier_freq - 1320.0).abs() < 50.0, "{} Hz", acq.carrier_freq);
+            assert_eq!(acq.detection.unwrap().statistic, DetectionStatistic::ChiSquare);
+        }
+    }
+
+    #[test]
+    fn test_fine_doppler_and_code_phase_hand_over_to_tracking() {
+        use crate::tracking::do_tracking::TrackingChannel;
+        use crate::utilities::multicast_ring_buffer::MulticastRingBuffer;
+
+        // Not a multiple of the chip rate, so the samples see the chip edges at all offsets
+        const FS: f32 = 4_000_000.0;
+        const NUM_INTEGRATIONS: usize = 10;
+        let fft_size = (FS / 1000.0) as usize;
+
+        // Between the search bins and between samples: the code starts 781.4 samples in
+        let mut config = SimConfig::new(FS as f64, 0.0, 5);
+        config.satellites.push(SimSatellite::new(6, 823.2, 1830.0, 45.0));
+        let mut generator = SignalGenerator::new(config);
+        let samples = generator.generate_samples(NUM_INTEGRATIONS * fft_size);
+
+        let doppler_tables: Vec<DopplerShiftTable> = (0..29)
+            .map(|i| DopplerShiftTable::new(0.0, -7000.0 + 500.0 * i as f32, FS, fft_size))
+            .collect();
+        let mut worker = AcquisitionWorker::new(6, fft_size, FS);
+        let acq = worker
+            .search_satellite(&samples, &doppler_tables, 0, NUM_INTEGRATIONS)
+            .expect("PRN 6 not acquired");
+        assert!((acq.carrier_freq - 1830.0).abs() < 20.0, "{} Hz", acq.carrier_freq);
+        let expected = (823.2 + acq.sample_global_index as f32 * 1.023e6 / FS) % 1023.0;
+        let error = (acq.code_phase_chips - expected + 511.5).rem_euclid(1023.0) - 511.5;
+        assert!(error.abs() < 0.1, "{} chips, expected {}", acq.code_phase_chips, expected);
+
+        // Tracking correlates on the code from the first epoch on and pulls the phase in
+        let buf = Arc::new(MulticastRingBuffer::new(1 << 17));
+        let _ = buf.write_samples(&samples);
+        let _ = buf.write_samples(&generator.generate_samples(10 * fft_size));
+        let mut channel = TrackingChannel::new(0, FS);
+        channel.start(acq);
+        let (mut prompt, mut in_phase) = (Vec::new(), Vec::new());
+        while channel.next_sample_index + 2 * fft_size < buf.get_head() {
+            assert!(channel.update(buf.clone()).is_none());
+            prompt.push(channel.i_prompt.hypot(channel.q_prompt));
+            in_phase.push(channel.i_prompt.abs() / channel.i_prompt.hypot(channel.q_prompt));
+        }
+        assert!(channel.is_active());
+        let peak = prompt.iter().cloned().fold(0.0, f32::max);
+        assert!(prompt.iter().all(|p| *p > 0.5 * peak), "{:?}", prompt);
+        assert!(in_phase.iter().rev().take(5).all(|c| *c > 0.9), "{:?}", in_phase);
+    }
+
     // Checking elapsed time should use "cargo test --release" to get more realistic performance numbers 
     #[test]
     fn test_acquisition_with_real_data() {
@@ -1,5 +1,6 @@
 use std::simd::f32x8;
 use num_complex::Complex32;
+use crate::rf::nco_lut::NcoLut;
 
 
 pub struct DopplerShiftTable {
@@ -9,14 +10,10 @@ pub struct DopplerShiftTable {
 
 impl DopplerShiftTable {
     pub fn new(f_if: f32, doppler_freq_hz: f32, fs: f32, num_samples: usize) -> Self {
-        let mut table = Vec::with_capacity(num_samples);
+        let mut table = vec![Complex32::new(0.0, 0.0); num_samples];
         let carr_freq = f_if + doppler_freq_hz;
-        let phase_step = 2.0 * std::f32::consts::PI * carr_freq / fs;
-
-        for i in 0..num_samples {
-            let phase = i as f32 * phase_step;
-            table.push(Complex32::new(phase.cos(), -phase.sin())); // Negative for downconversion
-        }
+        // Same oscillator as the frontend, cos - jsin for downconversion
+        NcoLut::new(carr_freq, fs).with_interpolation().fill(&mut table);
         Self { doppler_freq_hz: carr_freq, table }
     }
 }
@@ -36,7 +33,12 @@ pub fn apply_doppler_shift(samples: & [Complex32], doppler_table: &DopplerShiftT
             let result_vec = multiply_simd_block(s_vec, t_vec);
             result_vec.copy_to_slice(std::slice::from_raw_parts_mut(o_ptr.add(i * 8), 8));
         }
-    } 
+    }
+    // The samples after the last block of 4
+    let done = chunks * 4;
+    for ((out, s), t) in output[done..samples.len()].iter_mut().zip(&samples[done..]).zip(&doppler_table.table[done..]) {
+        *out = s * t;
+    }
 }
 
 /// (a + bi) * (c + di) = (ac - bd) + (ad + bc)i
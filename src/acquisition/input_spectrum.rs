@@ -0,0 +1,152 @@
+use crate::acquisition::doppler_shift::{DopplerShiftTable, apply_doppler_shift};
+use num_complex::Complex32;
+use rayon::prelude::*;
+use rustfft::{Fft, FftPlanner};
+use std::sync::Arc;
+
+const RESIDUAL_TOLERANCE_HZ: f64 = 1.0; // Bins this close share a residual wipe-off, 0.01 cycles over 10 ms
+
+/// Doppler bin of the shared spectrum
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpectrumBin {
+    pub doppler_freq_hz: f32, // Carrier wiped off, IF included, within RESIDUAL_TOLERANCE_HZ of its table
+    pub residual: usize, // Wipe-off in time, below the line spacing
+    pub shift: usize, // Lines of the circular shift, the carrier in whole line spacings
+}
+
+/// Spectra of the code periods of a chunk, computed once and shared by the searches of all the
+/// satellites. Wiping off a carrier of k times the line spacing fs / N is a circular shift of the
+/// spectrum by k lines, only the residual carrier is wiped off in time, one FFT per residual.
+pub struct InputSpectrum {
+    fft: Arc<dyn Fft<f32>>,
+    fft_size: usize,
+    residuals: Vec<DopplerShiftTable>,
+    pub bins: Vec<SpectrumBin>, // In the order of the tables
+    spectra: Vec<Complex32>, // Code period after code period, a spectrum per residual
+    num_code_periods: usize,
+}
+
+impl InputSpectrum {
+    /// Bins of `doppler_table`, for code periods of `fft_size` samples
+    pub fn new(doppler_table: &[DopplerShiftTable], fft_size: usize, freq_sampling_hz: f32) -> Self {
+        let line_hz = freq_sampling_hz as f64 / fft_size as f64;
+        let mut residual_freqs: Vec<f64> = Vec::new();
+        let bins = doppler_table
+            .iter()
+            .map(|table| {
+                let freq_hz = table.doppler_freq_hz as f64;
+                // Residuals a whole number of lines apart are the same
+                let lines_from = |residual_hz: f64| ((freq_hz - residual_hz) / line_hz).round();
+                let residual = match residual_freqs
+                    .iter()
+                    .position(|r| (freq_hz - r - lines_from(*r) * line_hz).abs() <= RESIDUAL_TOLERANCE_HZ)
+                {
+                    Some(residual) => residual,
+                    None => {
+                        residual_freqs.push(freq_hz - lines_from(0.0) * line_hz);
+                        residual_freqs.len() - 1
+                    }
+                };
+                let lines = lines_from(residual_freqs[residual]);
+                SpectrumBin {
+                    doppler_freq_hz: (lines * line_hz + residual_freqs[residual]) as f32,
+                    residual,
+                    shift: (lines as i64).rem_euclid(fft_size as i64) as usize,
+                }
+            })
+            .collect();
+        let residuals = residual_freqs
+            .iter()
+            .map(|r| DopplerShiftTable::new(0.0, *r as f32, freq_sampling_hz, fft_size))
+            .collect();
+
+        Self {
+            fft: FftPlanner::new().plan_fft_forward(fft_size),
+            fft_size,
+            residuals,
+            bins,
+            spectra: Vec::new(),
+            num_code_periods: 0,
+        }
+    }
+
+    pub fn num_residuals(&self) -> usize {
+        self.residuals.len()
+    }
+
+    /// Transforms the first `num_code_periods` code periods of `samples_chunk`, in parallel
+    pub fn update(&mut self, samples_chunk: &[Complex32], num_code_periods: usize) {
+        let n = self.fft_size;
+        let num_residuals = self.residuals.len();
+        self.spectra.resize(num_code_periods * num_residuals * n, Complex32::new(0.0, 0.0));
+        self.num_code_periods = num_code_periods;
+
+        let (fft, residuals) = (&self.fft, &self.residuals);
+        self.spectra.par_chunks_mut(n).enumerate().for_each_init(
+            || vec![Complex32::new(0.0, 0.0); fft.get_inplace_scratch_len()],
+            |scratch, (i, spectrum)| {
+                let offset = i / num_residuals * n;
+                apply_doppler_shift(&samples_chunk[offset..offset + n], &residuals[i % num_residuals], spectrum);
+                fft.process_with_scratch(spectrum, scratch);
+            },
+        );
+    }
+
+    /// Spectrum of code period `c` wiped off at `bin`, times the conjugate of `code_fft`, in `output`
+    pub fn multiply_code(&self, bin: usize, c: usize, code_fft: &[Complex32], output: &mut [Complex32]) {
+        assert!(c < self.num_code_periods, "Code period {} not transformed", c);
+        let n = self.fft_size;
+        let SpectrumBin { residual, shift, .. } = self.bins[bin];
+        let start = (c * self.residuals.len() + residual) * n;
+        let spectrum = &self.spectra[start..start + n];
+
+        // Line m of the wiped-off code period is line m + shift of the spectrum
+        let (head, tail) = output[..n].split_at_mut(n - shift);
+        for ((out, s), code) in head.iter_mut().zip(&spectrum[shift..]).zip(code_fft) {
+            *out = s * code.conj();
+        }
+        for ((out, s), code) in tail.iter_mut().zip(&spectrum[..shift]).zip(&code_fft[n - shift..]) {
+            *out = s * code.conj();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::signal_generator::{SignalGenerator, SimConfig, SimSatellite};
+
+    #[test]
+    fn test_shift_matches_wipe_off() {
+        // The rate and IF of the real recordings, lines of 999.98 Hz
+        const FS: f32 = 16_367_600.0;
+        const IF: f32 = 4_130_400.0;
+        let fft_size = 16368;
+
+        let mut config = SimConfig::new(FS as f64, IF as f64, 2);
+        config.satellites.push(SimSatellite::new(11, 300.0, 2500.0, 45.0));
+        let samples = SignalGenerator::new(config).generate_samples(2 * fft_size);
+        let tables: Vec<DopplerShiftTable> = (0..29)
+            .map(|i| DopplerShiftTable::new(IF, -7000.0 + 500.0 * i as f32, FS, fft_size))
+            .collect();
+
+        let mut spectrum = InputSpectrum::new(&tables, fft_size, FS);
+        assert_eq!(spectrum.num_residuals(), 2);
+        spectrum.update(&samples, 2);
+
+        let fft = FftPlanner::new().plan_fft_forward(fft_size);
+        let ones = vec![Complex32::new(1.0, 0.0); fft_size];
+        let mut shifted = vec![Complex32::new(0.0, 0.0); fft_size];
+        let mut expected = vec![Complex32::new(0.0, 0.0); fft_size];
+        for (bin, table) in tables.iter().enumerate() {
+            assert!((spectrum.bins[bin].doppler_freq_hz - table.doppler_freq_hz).abs() <= 1.0);
+            spectrum.multiply_code(bin, 1, &ones, &mut shifted);
+            apply_doppler_shift(&samples[fft_size..], table, &mut expected);
+            fft.process(&mut expected);
+
+            let error: f32 = shifted.iter().zip(&expected).map(|(a, b)| (a - b).norm_sqr()).sum();
+            let power: f32 = expected.iter().map(|x| x.norm_sqr()).sum();
+            assert!(error < 1e-4 * power, "Bin {}: {} of {}", bin, error, power);
+        }
+    }
+}
@@ -1,2 +1,5 @@
+pub mod assistance;
+pub mod detector;
 pub mod do_acquisition;
-pub mod doppler_shift;
\ No newline at end of file
+pub mod doppler_shift;
+pub mod input_spectrum;
\ No newline at end of file
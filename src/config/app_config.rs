@@ -1,6 +1,18 @@
 use std::fmt::{Display, Formatter};
 use serde::Deserialize;
+use crate::acquisition::detector::DetectorConfig;
+use crate::acquisition::do_acquisition::{Accumulation, AcquisitionConfig};
+use crate::filter::FilterConfig;
+use crate::rf::agc::AgcConfig;
+use crate::rf::interference::{BlankingConfig, NotchConfig};
+use crate::sdr_store::file_source::{FileSourceConfig, SampleFormat};
+use crate::sdr_store::net_source::NetSourceConfig;
+use crate::sdr_store::rtl_tcp::RtlTcpConfig;
+use crate::sdr_store::sigmf::{SigMfMeta, SigMfPlaybackConfig, SigMfRecordConfig};
 use crate::sdr_store::sdr_wrapper::SdrConfig;
+use crate::simulator::signal_generator::SimConfig;
+use crate::spectrum::SpectrumConfig;
+use crate::utilities::multicast_ring_buffer::BufferFormat;
 use crate::constants::gps_property_constants::GPS_L1_FREQ_HZ;
 
 pub static APP_CONFIG_FILE: &str = "config/app_config.toml";
@@ -12,13 +24,39 @@ pub struct AppConfig {
     pub rf: RfConfig,
     pub pvt: PvtConfig,
     pub output: OutputConfig,
+    pub file: Option<FileSourceConfig>, // Used when device = "file"
+    pub sim: Option<SimConfig>, // Used when device = "sim"
+    pub rtl_tcp: Option<RtlTcpConfig>, // Used when device = "rtl_tcp"
+    pub net: Option<NetSourceConfig>, // Used when device = "net"
+    pub sigmf: Option<SigMfPlaybackConfig>, // Used when device = "sigmf"
+    pub record: Option<SigMfRecordConfig>, // Record the sample streams when present
+    pub spectrum: Option<SpectrumConfig>, // Monitor the spectrum of the frontend output when present
+    #[serde(default)]
+    pub acquisition: AcquisitionConfig,
 }
 
 #[derive(Clone, Copy, Deserialize, Debug)]
 pub struct RfConfig {
     pub freq_if_hz: Option<f32>,
+    #[serde(default)]
+    pub real_input: bool, // Real samples at the IF in I, set for "int8_real" recordings
+    #[serde(default)]
+    pub nco_interpolation: bool, // Interpolate the NCO LUT, lower spurs for a few more operations per sample
     pub output_sample_rate_hz: f32,
     pub enable_agc: bool,
+    #[serde(default)]
+    pub agc: AgcConfig, // Used when `enable_agc`
+    pub blanking: Option<BlankingConfig>, // Pulse blanking when present
+    pub notch: Option<NotchConfig>, // CW notching when present
+    pub channel_filter: Option<FilterConfig>, // Low-pass before resampling when present
+    #[serde(default)]
+    pub buffer_format: BufferFormat, // Sample type kept for acquisition and tracking, integers need the AGC
+    #[serde(default = "default_buffer_samples")]
+    pub buffer_samples: usize, // Samples kept for acquisition and tracking, a power of two
+}
+
+fn default_buffer_samples() -> usize {
+    1 << 20
 }
 
 #[derive(Deserialize, Debug)]
@@ -44,9 +82,144 @@ impl std::error::Error for AppConfigError {}
 impl AppConfig {
     pub fn from_toml_file(file_path: &str) -> Result<Self, AppConfigError> {
         let config_str = std::fs::read_to_string(file_path).map_err(|e| AppConfigError(format!("Failed to read config file: {}", e)))?;
-        let mut config: AppConfig = toml::from_str(config_str.as_str()).map_err(|e| AppConfigError(format!("Failed to parse toml file: {}", e)))?;
-        let f_if: f32 = config.sdr.center_frequency_hz - GPS_L1_FREQ_HZ;
-        config.rf.freq_if_hz = Some(f_if);
+        Self::from_toml_str(&config_str)
+    }
+
+    pub fn from_toml_str(config_str: &str) -> Result<Self, AppConfigError> {
+        let mut config: AppConfig = toml::from_str(config_str).map_err(|e| AppConfigError(format!("Failed to parse toml file: {}", e)))?;
+        if let Some(bits) = config.rf.agc.requantize_bits
+            && !(1..=4).contains(&bits)
+        {
+            return Err(AppConfigError(format!("rf.agc.requantize_bits must be 1 to 4, got {}", bits)));
+        }
+        if let Some(notch) = config.rf.notch
+            && !(0.0..1.0).contains(&notch.pole_radius)
+        {
+            return Err(AppConfigError(format!("rf.notch.pole_radius must be below 1, got {}", notch.pole_radius)));
+        }
+        if let Some(filter) = config.rf.channel_filter
+            && !(0.0 < filter.passband_hz && filter.passband_hz < filter.stopband_hz)
+        {
+            return Err(AppConfigError(format!(
+                "rf.channel_filter needs 0 < passband_hz < stopband_hz, got {} and {}",
+                filter.passband_hz, filter.stopband_hz
+            )));
+        }
+        if !config.rf.buffer_samples.is_power_of_two() {
+            return Err(AppConfigError(format!("rf.buffer_samples must be a power of two, got {}", config.rf.buffer_samples)));
+        }
+        if config.rf.buffer_format != BufferFormat::Complex32 && !config.rf.enable_agc {
+            // The integer types are scaled for the power set by the AGC
+            return Err(AppConfigError(format!("rf.buffer_format = {:?} needs enable_agc", config.rf.buffer_format)));
+        }
+        if let Some(spectrum) = &config.spectrum
+            && (spectrum.fft_size < 16 || !(0.0..1.0).contains(&spectrum.overlap) || spectrum.averages == 0)
+        {
+            return Err(AppConfigError(format!(
+                "spectrum needs fft_size >= 16, 0 <= overlap < 1 and averages > 0, got {}, {} and {}",
+                spectrum.fft_size, spectrum.overlap, spectrum.averages
+            )));
+        }
+        if let Some(spectrum) = &config.spectrum
+            && 2 * spectrum.frame_samples() > config.rf.buffer_samples
+        {
+            return Err(AppConfigError(format!(
+                "rf.buffer_samples must hold twice the {} samples of a spectrum frame",
+                spectrum.frame_samples()
+            )));
+        }
+        match config.acquisition.detector {
+            DetectorConfig::ChiSquare { pfa } | DetectorConfig::CaCfar { pfa, .. } if !(0.0 < pfa && pfa < 1.0) => {
+                return Err(AppConfigError(format!("acquisition.detector.pfa must be between 0 and 1, got {}", pfa)));
+            }
+            DetectorConfig::CaCfar { reference_cells: 0, .. } => {
+                return Err(AppConfigError("acquisition.detector.reference_cells must be above 0".to_string()));
+            }
+            DetectorConfig::PeakToSecondPeak { threshold, .. } if threshold <= 1.0 => {
+                return Err(AppConfigError(format!("acquisition.detector.threshold must be above 1, got {}", threshold)));
+            }
+            _ => {}
+        }
+        let acquisition = &config.acquisition;
+        if !(1..=10).contains(&acquisition.coherent_ms) || acquisition.non_coherent == 0 {
+            return Err(AppConfigError(format!(
+                "acquisition needs coherent_ms 1 to 10 and non_coherent > 0, got {} and {}",
+                acquisition.coherent_ms, acquisition.non_coherent
+            )));
+        }
+        if acquisition.half_bit_alternation
+            && (!20usize.is_multiple_of(acquisition.coherent_ms) || acquisition.non_coherent < 20 / acquisition.coherent_ms)
+        {
+            return Err(AppConfigError(format!(
+                "acquisition.half_bit_alternation needs coherent_ms dividing 20 and non_coherent >= 20 / coherent_ms, got {} and {}",
+                acquisition.coherent_ms, acquisition.non_coherent
+            )));
+        }
+        if acquisition.accumulation == Accumulation::DifferentialCoherent && acquisition.non_coherent < 2 {
+            return Err(AppConfigError("acquisition.accumulation = \"differential_coherent\" needs non_coherent >= 2".to_string()));
+        }
+        if let Some(assistance) = &acquisition.assistance {
+            if assistance.rinex_path.is_none() && assistance.almanac_path.is_none() {
+                return Err(AppConfigError("acquisition.assistance needs rinex_path or almanac_path".to_string()));
+            }
+            if !(-90.0..=90.0).contains(&assistance.latitude_deg) {
+                return Err(AppConfigError(format!("acquisition.assistance.latitude_deg must be within ±90, got {}", assistance.latitude_deg)));
+            }
+            if assistance.position_uncertainty_km < 0.0 || assistance.time_uncertainty_s < 0.0 || assistance.oscillator_ppm < 0.0 {
+                return Err(AppConfigError("acquisition.assistance uncertainties must not be negative".to_string()));
+            }
+        }
+        let search_samples = config.rf.output_sample_rate_hz as usize * acquisition.coherent_ms * acquisition.non_coherent / 1000;
+        if 2 * search_samples > config.rf.buffer_samples {
+            return Err(AppConfigError(format!(
+                "rf.buffer_samples must hold twice the {} samples of an acquisition search",
+                search_samples
+            )));
+        }
+        if config.device == "file" {
+            // The recording defines sample rate and IF, not the [sdr] section
+            let file = config.file.as_ref().ok_or(AppConfigError("A [file] section is required for device = \"file\"".to_string()))?;
+            config.sdr.sample_rate_hz = file.sample_rate_hz;
+            config.sdr.center_frequency_hz = GPS_L1_FREQ_HZ + file.freq_if_hz;
+            config.rf.freq_if_hz = Some(file.freq_if_hz);
+        } else if config.device == "sim" {
+            let sim = config.sim.as_ref().ok_or(AppConfigError("A [sim] section is required for device = \"sim\"".to_string()))?;
+            config.sdr.sample_rate_hz = sim.sample_rate_hz as f32;
+            config.sdr.center_frequency_hz = GPS_L1_FREQ_HZ + sim.freq_if_hz as f32;
+            config.rf.freq_if_hz = Some(sim.freq_if_hz as f32);
+        } else if config.device == "rtl_tcp" {
+            let rtl_tcp = config.rtl_tcp.as_ref().ok_or(AppConfigError("A [rtl_tcp] section is required for device = \"rtl_tcp\"".to_string()))?;
+            config.sdr.sample_rate_hz = rtl_tcp.sample_rate_hz;
+            config.sdr.center_frequency_hz = rtl_tcp.center_frequency_hz as f32;
+            config.rf.freq_if_hz = Some((rtl_tcp.center_frequency_hz - GPS_L1_FREQ_HZ as f64) as f32);
+        } else if config.device == "net" {
+            // The sender defines the stream
+            let net = config.net.as_ref().ok_or(AppConfigError("A [net] section is required for device = \"net\"".to_string()))?;
+            config.sdr.sample_rate_hz = net.sample_rate_hz;
+            config.sdr.center_frequency_hz = GPS_L1_FREQ_HZ + net.freq_if_hz;
+            config.rf.freq_if_hz = Some(net.freq_if_hz);
+        } else if config.device == "sigmf" {
+            // The metadata describes the recording, it is replayed by a file source
+            let playback = config.sigmf.as_ref().ok_or(AppConfigError("A [sigmf] section is required for device = \"sigmf\"".to_string()))?;
+            let meta = SigMfMeta::from_file(&playback.path).map_err(|e| AppConfigError(format!("{:?}", e)))?;
+            let file = meta.file_source_config(playback).map_err(|e| AppConfigError(format!("{:?}", e)))?;
+            if let Some(sdr) = meta.global.sdr_config {
+                config.sdr = sdr;
+            }
+            config.sdr.sample_rate_hz = file.sample_rate_hz;
+            config.sdr.center_frequency_hz = GPS_L1_FREQ_HZ + file.freq_if_hz;
+            config.rf.freq_if_hz = Some(file.freq_if_hz);
+            config.file = Some(file);
+        } else {
+            let f_if: f32 = config.sdr.center_frequency_hz - GPS_L1_FREQ_HZ;
+            config.rf.freq_if_hz = Some(f_if);
+        }
+        if let Some(file) = &config.file
+            && matches!(config.device.as_str(), "file" | "sigmf")
+            && file.format == SampleFormat::Int8Real
+        {
+            config.rf.real_input = true;
+        }
         Ok(config)
     }
 }
\ No newline at end of file
@@ -0,0 +1,420 @@
+use num_complex::Complex32;
+use serde::Deserialize;
+use std::f64::consts::PI;
+use std::simd::{f32x2, f32x8};
+
+/// Complex samples per SIMD vector of interleaved I/Q floats
+const SAMPLES_PER_VECTOR: usize = 4;
+
+/// Low-pass channel filter of the frontend, in Hz from the center of the complex baseband
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct FilterConfig {
+    pub passband_hz: f32,
+    pub stopband_hz: f32,
+    pub taps: Option<usize>, // Estimated from the transition width and `attenuation_db` when absent
+    #[serde(default = "default_attenuation_db")]
+    pub attenuation_db: f32,
+}
+
+fn default_attenuation_db() -> f32 {
+    60.0
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Window {
+    Rectangular,
+    Hamming,
+    Blackman,
+    Kaiser(f64), // Beta, see `kaiser_beta`
+}
+
+impl Window {
+    /// Weight of tap `n` of `len`
+    pub fn weight(&self, n: usize, len: usize) -> f64 {
+        if len < 2 {
+            return 1.0;
+        }
+        let x = n as f64 / (len - 1) as f64; // 0 to 1
+        match *self {
+            Window::Rectangular => 1.0,
+            Window::Hamming => 0.54 - 0.46 * (2.0 * PI * x).cos(),
+            Window::Blackman => 0.42 - 0.5 * (2.0 * PI * x).cos() + 0.08 * (4.0 * PI * x).cos(),
+            Window::Kaiser(beta) => bessel_i0(beta * (1.0 - (2.0 * x - 1.0).powi(2)).sqrt()) / bessel_i0(beta),
+        }
+    }
+}
+
+/// Modified Bessel function of the first kind, order 0
+fn bessel_i0(x: f64) -> f64 {
+    let (mut sum, mut term, mut k) = (1.0, 1.0, 1.0);
+    while term > 1e-12 * sum {
+        term *= (x / (2.0 * k)).powi(2);
+        sum += term;
+        k += 1.0;
+    }
+    sum
+}
+
+/// Kaiser window beta for a stopband attenuation in dB
+pub fn kaiser_beta(attenuation_db: f64) -> f64 {
+    if attenuation_db > 50.0 {
+        0.1102 * (attenuation_db - 8.7)
+    } else if attenuation_db >= 21.0 {
+        0.5842 * (attenuation_db - 21.0).powf(0.4) + 0.07886 * (attenuation_db - 21.0)
+    } else {
+        0.0
+    }
+}
+
+/// Kaiser's estimate of the taps needed for a transition width in cycles per sample, always odd
+pub fn kaiser_taps(transition: f64, attenuation_db: f64) -> usize {
+    let n = ((attenuation_db - 7.95) / (14.36 * transition)).ceil().max(2.0) as usize + 1;
+    n | 1
+}
+
+/// Windowed-sinc low-pass taps with unity gain at DC, `cutoff` in cycles per sample
+pub fn design_lowpass(cutoff: f64, n_taps: usize, window: Window) -> Vec<f32> {
+    let center = (n_taps - 1) as f64 / 2.0;
+    let taps: Vec<f64> = (0..n_taps)
+        .map(|n| {
+            let t = n as f64 - center;
+            let sinc = if t == 0.0 { 2.0 * cutoff } else { (2.0 * PI * cutoff * t).sin() / (PI * t) };
+            sinc * window.weight(n, n_taps)
+        })
+        .collect();
+    let sum: f64 = taps.iter().sum();
+    taps.iter().map(|h| (h / sum) as f32).collect()
+}
+
+/// Kaiser-windowed low-pass filter for `config` at `sample_rate_hz`
+pub fn design_channel_filter(config: &FilterConfig, sample_rate_hz: f32) -> FirFilterSimd {
+    let (pass, stop) = (config.passband_hz as f64 / sample_rate_hz as f64, config.stopband_hz as f64 / sample_rate_hz as f64);
+    let attenuation_db = config.attenuation_db as f64;
+    let n_taps = config.taps.unwrap_or_else(|| kaiser_taps(stop - pass, attenuation_db));
+    FirFilterSimd::new(design_lowpass((pass + stop) / 2.0, n_taps, Window::Kaiser(kaiser_beta(attenuation_db))))
+}
+
+/// FIR filter with real taps on complex samples. Runs on the interleaved I/Q floats,
+/// 4 outputs per f32x8
+pub struct FirFilterSimd {
+    taps: Vec<f32>,     // Reversed, to run forward over the samples
+    history: Vec<f32>, // The last `taps.len() - 1` inputs, interleaved, then the block being filtered
+}
+
+impl FirFilterSimd {
+    pub fn new(taps: Vec<f32>) -> Self {
+        assert!(!taps.is_empty(), "A FIR filter needs at least one tap");
+        let history = vec![0.0; 2 * (taps.len() - 1)];
+        Self { taps: taps.into_iter().rev().collect(), history }
+    }
+
+    pub fn num_taps(&self) -> usize {
+        self.taps.len()
+    }
+
+    /// Group delay in samples, for the symmetric taps designed here
+    pub fn delay(&self) -> f64 {
+        (self.taps.len() - 1) as f64 / 2.0
+    }
+
+    pub fn reset(&mut self) {
+        self.history.iter_mut().for_each(|x| *x = 0.0);
+    }
+
+    pub fn process(&mut self, block: &mut [Complex32]) {
+        // Complex32 is two f32, re then im
+        let floats = unsafe { std::slice::from_raw_parts_mut(block.as_mut_ptr() as *mut f32, block.len() * 2) };
+        self.process_interleaved(floats);
+    }
+
+    /// Filter interleaved I/Q floats in place, as `DigitalFrontend::process_block` gets them
+    pub fn process_interleaved(&mut self, block: &mut [f32]) {
+        let n_samples = block.len() / 2;
+        self.history.extend_from_slice(&block[..2 * n_samples]);
+
+        let vectors = n_samples / SAMPLES_PER_VECTOR;
+        for v in 0..vectors {
+            let start = 2 * v * SAMPLES_PER_VECTOR;
+            let mut acc = f32x8::splat(0.0);
+            for (j, h) in self.taps.iter().enumerate() {
+                acc += f32x8::splat(*h) * f32x8::from_slice(&self.history[start + 2 * j..start + 2 * j + 8]);
+            }
+            acc.copy_to_slice(&mut block[start..start + 8]);
+        }
+        for n in vectors * SAMPLES_PER_VECTOR..n_samples {
+            let mut acc = f32x2::splat(0.0);
+            for (j, h) in self.taps.iter().enumerate() {
+                acc += f32x2::splat(*h) * f32x2::from_slice(&self.history[2 * (n + j)..2 * (n + j) + 2]);
+            }
+            acc.copy_to_slice(&mut block[2 * n..2 * n + 2]);
+        }
+
+        self.history.drain(..2 * n_samples);
+    }
+}
+
+/// Decimation by 2 with a half-band filter. Every other tap but the center one is zero, so the
+/// even input samples go through a FIR of the non zero taps and the odd ones are only delayed.
+pub struct HalfBandDecimator {
+    even_filter: FirFilterSimd,
+    odd_delay: Vec<Complex32>, // The odd samples in the center tap delay, then the block's
+    center_tap: f32,
+    pending: Option<Complex32>, // Even sample left from a block of odd length
+    even: Vec<Complex32>,
+    output: Vec<Complex32>,
+}
+
+impl HalfBandDecimator {
+    /// `half_len` taps on each side of the center are non zero, 4 * `half_len` - 1 taps in all
+    pub fn new(half_len: usize, window: Window) -> Self {
+        assert!(half_len > 0, "A half-band filter needs at least one tap on each side");
+        // Center at odd index 2 * half_len - 1, the non zero side taps at even indices
+        let taps = design_lowpass(0.25, 4 * half_len - 1, window);
+        let center = 2 * half_len - 1;
+        let even_taps: Vec<f32> = taps.iter().step_by(2).copied().collect();
+        Self {
+            even_filter: FirFilterSimd::new(even_taps),
+            odd_delay: vec![Complex32::new(0.0, 0.0); half_len],
+            center_tap: taps[center],
+            pending: None,
+            even: Vec::new(),
+            output: Vec::new(),
+        }
+    }
+
+    /// Group delay in input samples
+    pub fn delay(&self) -> f64 {
+        2.0 * self.even_filter.delay()
+    }
+
+    /// Number of outputs of the next block taken before its input sample `input_offset`
+    pub fn output_offset(&self, input_offset: usize) -> usize {
+        (input_offset + self.pending.is_some() as usize).div_ceil(2)
+    }
+
+    /// Input position of the (possibly fractional) output `output_offset` of the next block,
+    /// in input samples relative to the block start, accounting for the filter delay
+    pub fn input_offset(&self, output_offset: f64) -> f64 {
+        2.0 * output_offset - self.pending.is_some() as usize as f64 - self.delay()
+    }
+
+    /// Decimate the next block of the stream, the outputs are valid until the next call
+    pub fn process(&mut self, input: &[Complex32]) -> &mut [Complex32] {
+        self.even.clear();
+        let mut samples = self.pending.take().into_iter().chain(input.iter().copied());
+        while let Some(even) = samples.next() {
+            match samples.next() {
+                Some(odd) => {
+                    self.even.push(even);
+                    self.odd_delay.push(odd);
+                }
+                None => self.pending = Some(even),
+            }
+        }
+
+        self.even_filter.process(&mut self.even);
+        self.output.clear();
+        self.output.extend(self.even.iter().zip(&self.odd_delay).map(|(e, o)| e + o * self.center_tap));
+        self.odd_delay.drain(..self.even.len());
+        &mut self.output
+    }
+}
+
+/// Second order IIR section with real coefficients, a0 normalized to 1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BiquadCoefficients {
+    pub b0: f32,
+    pub b1: f32,
+    pub b2: f32,
+    pub a1: f32,
+    pub a2: f32,
+}
+
+impl BiquadCoefficients {
+    fn normalized(b: [f64; 3], a: [f64; 3]) -> Self {
+        Self {
+            b0: (b[0] / a[0]) as f32,
+            b1: (b[1] / a[0]) as f32,
+            b2: (b[2] / a[0]) as f32,
+            a1: (a[1] / a[0]) as f32,
+            a2: (a[2] / a[0]) as f32,
+        }
+    }
+
+    /// RBJ cookbook low-pass, `cutoff` in cycles per sample
+    pub fn lowpass(cutoff: f64, q: f64) -> Self {
+        let w = 2.0 * PI * cutoff;
+        let alpha = w.sin() / (2.0 * q);
+        let c = w.cos();
+        Self::normalized([(1.0 - c) / 2.0, 1.0 - c, (1.0 - c) / 2.0], [1.0 + alpha, -2.0 * c, 1.0 - alpha])
+    }
+
+    /// RBJ cookbook high-pass, `cutoff` in cycles per sample
+    pub fn highpass(cutoff: f64, q: f64) -> Self {
+        let w = 2.0 * PI * cutoff;
+        let alpha = w.sin() / (2.0 * q);
+        let c = w.cos();
+        Self::normalized([(1.0 + c) / 2.0, -(1.0 + c), (1.0 + c) / 2.0], [1.0 + alpha, -2.0 * c, 1.0 - alpha])
+    }
+
+    /// RBJ cookbook notch at ±`freq` in cycles per sample
+    pub fn notch(freq: f64, q: f64) -> Self {
+        let w = 2.0 * PI * freq;
+        let alpha = w.sin() / (2.0 * q);
+        let c = w.cos();
+        Self::normalized([1.0, -2.0 * c, 1.0], [1.0 + alpha, -2.0 * c, 1.0 - alpha])
+    }
+}
+
+/// Biquad sections in series, I and Q filtered together as one f32x2
+pub struct BiquadCascade {
+    sections: Vec<BiquadCoefficients>,
+    state: Vec<[f32x2; 2]>, // Transposed direct form II, per section
+}
+
+impl BiquadCascade {
+    pub fn new(sections: Vec<BiquadCoefficients>) -> Self {
+        let state = vec![[f32x2::splat(0.0); 2]; sections.len()];
+        Self { sections, state }
+    }
+
+    /// Butterworth low-pass of even `order`, `cutoff` in cycles per sample
+    pub fn butterworth_lowpass(order: usize, cutoff: f64) -> Self {
+        assert!(order >= 2 && order.is_multiple_of(2), "Only even orders are made of biquads");
+        let sections = (0..order / 2)
+            .map(|k| {
+                let q = 1.0 / (2.0 * (PI * (2 * k + 1) as f64 / (2 * order) as f64).sin());
+                BiquadCoefficients::lowpass(cutoff, q)
+            })
+            .collect();
+        Self::new(sections)
+    }
+
+    pub fn reset(&mut self) {
+        self.state.iter_mut().for_each(|s| *s = [f32x2::splat(0.0); 2]);
+    }
+
+    pub fn process(&mut self, block: &mut [Complex32]) {
+        for sample in block.iter_mut() {
+            let mut x = f32x2::from_array([sample.re, sample.im]);
+            for (c, s) in self.sections.iter().zip(self.state.iter_mut()) {
+                let y = f32x2::splat(c.b0) * x + s[0];
+                s[0] = f32x2::splat(c.b1) * x - f32x2::splat(c.a1) * y + s[1];
+                s[1] = f32x2::splat(c.b2) * x - f32x2::splat(c.a2) * y;
+                x = y;
+            }
+            let [re, im] = x.to_array();
+            *sample = Complex32::new(re, im);
+        }
+    }
+}
+
+/// Power gain in dB of `taps` at `freq` in cycles per sample
+pub fn fir_response_db(taps: &[f32], freq: f64) -> f64 {
+    let (re, im) = taps.iter().enumerate().fold((0.0, 0.0), |(re, im), (n, h)| {
+        let phase = -2.0 * PI * freq * n as f64;
+        (re + *h as f64 * phase.cos(), im + *h as f64 * phase.sin())
+    });
+    10.0 * (re * re + im * im).log10()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mean_power(samples: &[Complex32]) -> f32 {
+        samples.iter().map(|s| s.norm_sqr()).sum::<f32>() / samples.len() as f32
+    }
+
+    fn tone(freq: f64, n: usize) -> Vec<Complex32> {
+        (0..n)
+            .map(|i| {
+                let phase = 2.0 * PI * freq * i as f64;
+                Complex32::new(phase.cos() as f32, phase.sin() as f32)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_channel_filter() {
+        // 10 MS/s capture, keep ±1.2 MHz
+        let config = FilterConfig { passband_hz: 1.2e6, stopband_hz: 1.6e6, taps: None, attenuation_db: 60.0 };
+        let mut filter = design_channel_filter(&config, 10e6);
+        assert_eq!(filter.num_taps() % 2, 1);
+        let taps: Vec<f32> = filter.taps.iter().rev().copied().collect();
+        assert!(fir_response_db(&taps, 0.1).abs() < 0.1);
+        assert!(fir_response_db(&taps, 0.2) < -55.0);
+
+        // Block by block, with blocks not a multiple of the vector width
+        let mut passband = tone(1e6 / 10e6, 8190);
+        let mut stopband = tone(-3e6 / 10e6, 8190);
+        for block in passband.chunks_mut(2047) {
+            filter.process(block);
+        }
+        filter.reset();
+        for block in stopband.chunks_mut(2047) {
+            filter.process(block);
+        }
+        let settled = filter.num_taps()..8190;
+        assert!((mean_power(&passband[settled.clone()]) - 1.0).abs() < 0.01);
+        assert!(10.0 * mean_power(&stopband[settled]).log10() < -55.0);
+    }
+
+    #[test]
+    fn test_fir_matches_direct_convolution() {
+        let taps = vec![0.1, -0.2, 0.5, 0.3, 0.05];
+        let input: Vec<Complex32> = (0..37).map(|n| Complex32::new(n as f32, (n * n % 7) as f32)).collect();
+        let mut output = input.clone();
+        let mut filter = FirFilterSimd::new(taps.clone());
+        for block in output.chunks_mut(10) {
+            filter.process(block);
+        }
+        for (n, y) in output.iter().enumerate() {
+            let expected: Complex32 = (0..taps.len()).filter(|k| *k <= n).map(|k| input[n - k] * taps[k]).sum();
+            assert!((y - expected).norm() < 1e-4, "output {}: {} vs {}", n, y, expected);
+        }
+    }
+
+    #[test]
+    fn test_half_band_decimator() {
+        let mut decimator = HalfBandDecimator::new(8, Window::Blackman);
+        let delay = decimator.delay();
+        // 0.05 is kept, 0.4 is out of the output band and must not alias to -0.2
+        let input = tone(0.05, 4001);
+        let mut output = Vec::new();
+        for (i, block) in input.chunks(333).enumerate() {
+            // Blocks of odd length leave an even sample pending every other block
+            let n_in = i * 333;
+            assert_eq!(decimator.output_offset(0), n_in.div_ceil(2) - output.len());
+            assert_eq!(decimator.input_offset(0.0), (2 * output.len()) as f64 - n_in as f64 - delay);
+            output.extend_from_slice(decimator.process(block));
+        }
+        assert_eq!(output.len(), 2000);
+        for (m, y) in output.iter().enumerate().skip(40) {
+            let phase = 2.0 * PI * 0.05 * (2.0 * m as f64 - delay);
+            let expected = Complex32::new(phase.cos() as f32, phase.sin() as f32);
+            assert!((y - expected).norm() < 1e-2, "output {}: {} vs {}", m, y, expected);
+        }
+
+        let mut decimator = HalfBandDecimator::new(8, Window::Blackman);
+        let alias = decimator.process(&tone(0.4, 4000)).to_vec();
+        assert!(10.0 * mean_power(&alias[40..]).log10() < -60.0);
+    }
+
+    #[test]
+    fn test_butterworth_lowpass() {
+        let mut filter = BiquadCascade::butterworth_lowpass(4, 0.1);
+        let mut dc = vec![Complex32::new(1.0, -1.0); 2000];
+        filter.process(&mut dc);
+        assert!((dc[1999] - Complex32::new(1.0, -1.0)).norm() < 1e-3);
+
+        // -3 dB at the cutoff, 4th order roll-off above it
+        for (freq, expected_db) in [(0.1, -3.01), (0.3, -50.2)] {
+            filter.reset();
+            let mut samples = tone(freq, 4000);
+            filter.process(&mut samples);
+            let gain_db = 10.0 * mean_power(&samples[1000..]).log10();
+            assert!((gain_db - expected_db).abs() < 1.0, "{}: {} dB", freq, gain_db);
+        }
+    }
+}
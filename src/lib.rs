@@ -3,12 +3,17 @@
 
 pub mod rf;
 pub mod fft;
+pub mod filter;
 pub use crate::fft::{FFT, RealFFT};
 pub mod utilities;
 #[cfg(test)]
 pub mod sdr_mock;
 pub mod sdr_store;
+pub mod simulator;
 pub mod config;
 pub mod acquisition;
 pub mod tracking;
-pub mod constants;
\ No newline at end of file
+pub mod receiver;
+pub mod spectrum;
+pub mod constants;
+pub mod rinex;
\ No newline at end of file
@@ -0,0 +1,414 @@
+use crate::acquisition::do_acquisition;
+use crate::acquisition::do_acquisition::AcquisitionResult;
+use crate::config::app_config::AppConfig;
+use crate::constants::gps_property_constants::GPS_L1_FREQ_HZ;
+use crate::rf::agc::AgcTelemetry;
+use crate::rf::frontend::baseband_sample_rate;
+use crate::rf::interference::InterferenceStats;
+use crate::rf::resampler::actual_output_rate;
+use crate::rf::rf_thread::rf_thread;
+use crate::rf::samples_buffer::{BUFFER_SIZE, SampleComplex, create_samples_ring_buffer};
+use crate::sdr_store::sdr_thread::sdr_thread_monitored;
+use crate::sdr_store::sdr_wrapper::{SdrDeviceWrapper, SdrError, start_device};
+use crate::sdr_store::sigmf::{SigMfGlobal, SigMfRecorder};
+use crate::sdr_store::stream_monitor::{StreamMonitor, StreamStats};
+use crate::spectrum::{self, SpectrumFrame};
+use crate::tracking::do_tracking;
+use crate::tracking::do_tracking::TrackingMessage;
+use crate::utilities::multicast_ring_buffer::{BufferFormat, MulticastRingBuffer, ReaderStats, RingSample};
+use crossbeam_channel::Sender;
+use num_complex::{Complex, Complex32};
+use ringbuf::HeapProd;
+use std::any::Any;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+
+/// Spectrum frames waiting for the subscriber, newer ones are dropped while it is full
+const SPECTRUM_FRAMES_QUEUED: usize = 16;
+
+/// How a pipeline stage ended
+#[derive(Debug, Clone, PartialEq)]
+pub enum StageStatus {
+    Completed,      // Ran out of input, e.g. at the end of a recording
+    Stopped,        // Stopped by `Receiver::stop_flag` or by another stage failing
+    Failed(String), // Returned an error, the other stages are stopped
+    Panicked(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct StageExit {
+    pub name: &'static str,
+    pub status: StageStatus,
+}
+
+/// What the receiver reports once all its stages have ended
+pub struct ReceiverSummary {
+    pub stages: Vec<StageExit>,
+    pub stream_stats: Arc<StreamStats>,
+    pub agc: Option<Arc<AgcTelemetry>>, // None when the AGC is disabled
+    pub interference: Option<Arc<InterferenceStats>>, // None without blanking and notching
+    pub readers: Arc<ReaderStats>, // Slack of the readers of the frontend output
+}
+
+impl ReceiverSummary {
+    pub fn is_ok(&self) -> bool {
+        self.stages
+            .iter()
+            .all(|s| matches!(s.status, StageStatus::Completed | StageStatus::Stopped))
+    }
+}
+
+impl Display for ReceiverSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        for stage in &self.stages {
+            writeln!(f, "{}: {:?}", stage.name, stage.status)?;
+        }
+        write!(f, "Sample stream: {}", self.stream_stats)?;
+        if let Some(agc) = &self.agc {
+            write!(f, "\nAGC gain: {:.1} dB, input power: {:.1} dB", agc.gain_db(), agc.input_power_db())?;
+        }
+        if let Some(interference) = &self.interference {
+            write!(f, "\nInterference: {}", interference)?;
+        }
+        write!(f, "\nRing buffer: {}", self.readers)?;
+        Ok(())
+    }
+}
+
+/// Tells the next stage its input is done when the stage ends, even by panicking,
+/// and stops the whole pipeline when it panicked
+struct StageDone {
+    done: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Drop for StageDone {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.stop.store(true, Ordering::SeqCst);
+        }
+        self.done.store(true, Ordering::SeqCst);
+    }
+}
+
+type StageHandle = (&'static str, JoinHandle<Result<(), String>>);
+
+/// Runs `stage` on its own thread and adds it to `handles`. When the thread cannot be spawned,
+/// the stages already in `handles` are stopped and joined before returning the error.
+fn spawn_stage<F>(
+    handles: &mut Vec<StageHandle>,
+    name: &'static str,
+    done: &Arc<AtomicBool>,
+    stop: &Arc<AtomicBool>,
+    stage: F,
+) -> Result<(), SdrError>
+where
+    F: FnOnce() -> Result<(), String> + Send + 'static,
+{
+    let guard = StageDone { done: Arc::clone(done), stop: Arc::clone(stop) };
+    let stage_stop = Arc::clone(stop);
+    let spawned = thread::Builder::new().name(name.to_string()).spawn(move || {
+        let _guard = guard;
+        let result = stage();
+        if result.is_err() {
+            stage_stop.store(true, Ordering::SeqCst);
+        }
+        result
+    });
+    match spawned {
+        Ok(handle) => {
+            handles.push((name, handle));
+            Ok(())
+        }
+        Err(e) => {
+            stop.store(true, Ordering::SeqCst);
+            for (_, handle) in handles.drain(..) {
+                let _ = handle.join();
+            }
+            Err(SdrError::OtherError(format!("Failed to spawn the {} thread: {}", name, e)))
+        }
+    }
+}
+
+/// Start the device stream, read it until it ends or `stop` is set, then stop it. The stream is
+/// started on the stage thread, so a stage that fails to spawn leaves the device idle.
+fn sdr_stage(
+    sdr_dev: &mut (impl SdrDeviceWrapper + ?Sized),
+    producer: &mut HeapProd<SampleComplex>,
+    recorder: Option<&mut SigMfRecorder>,
+    monitor: &StreamMonitor,
+    stop: &AtomicBool,
+) -> Result<(), SdrError> {
+    sdr_dev.start_stream()?;
+    let read = sdr_thread_monitored(sdr_dev, producer, recorder, monitor, stop);
+    let stopped = sdr_dev.stop_stream();
+    read.and(stopped)
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+/// The receiver pipeline: SDR -> RF frontend -> acquisition and tracking, each stage on its own thread.
+///
+/// Setting the stop flag stops reading the device, then every stage finishes the samples
+/// already handed to it and ends, in pipeline order.
+pub struct Receiver {
+    pub app_config: AppConfig,
+    stop: Arc<AtomicBool>,
+    spectrum_tx: Option<Sender<SpectrumFrame>>,
+}
+
+impl Receiver {
+    pub fn new(app_config: AppConfig) -> Self {
+        Self { app_config, stop: Arc::new(AtomicBool::new(false)), spectrum_tx: None }
+    }
+
+    /// Set it to stop the receiver, e.g. from a Ctrl-C handler
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    /// Frames of the spectrum monitor, only sent when the config has a [spectrum] section
+    pub fn subscribe_spectrum(&mut self) -> crossbeam_channel::Receiver<SpectrumFrame> {
+        let (tx, rx) = crossbeam_channel::bounded(SPECTRUM_FRAMES_QUEUED);
+        self.spectrum_tx = Some(tx);
+        rx
+    }
+
+    /// Open and start the device, run all stages until they end and report how each did
+    pub fn run(self) -> Result<ReceiverSummary, SdrError> {
+        match self.app_config.rf.buffer_format {
+            BufferFormat::Complex32 => self.run_with::<Complex32>(),
+            BufferFormat::Int16Iq => self.run_with::<Complex<i16>>(),
+            BufferFormat::Int8Iq => self.run_with::<Complex<i8>>(),
+        }
+    }
+
+    /// `run` with the frontend output stored as `T`
+    fn run_with<T: RingSample>(self) -> Result<ReceiverSummary, SdrError> {
+        let app_config = self.app_config;
+        let mut sdr_dev = start_device(&app_config)?;
+        sdr_dev.config(app_config.sdr.device_config())?;
+
+        // What the frontend hands to acquisition and tracking
+        let input_sample_rate = app_config.sdr.sample_rate_hz;
+        let baseband_rate = baseband_sample_rate(input_sample_rate, app_config.rf.real_input);
+        let output_sample_rate =
+            actual_output_rate(baseband_rate as f64, app_config.rf.output_sample_rate_hz as f64) as f32;
+
+        // Optional SigMF recordings of the device stream and of the frontend output
+        let mut raw_recorder = None;
+        let mut frontend_recorder = None;
+        if let Some(record) = &app_config.record {
+            let (sdr_info, sdr_config) = (sdr_dev.get_info(), sdr_dev.get_config());
+            if record.raw {
+                let global = SigMfGlobal::new("raw", sdr_dev.sample_rate_hz(), &sdr_info, &sdr_config);
+                raw_recorder = Some(SigMfRecorder::create(&record.raw_path(), global, sdr_dev.center_frequency_hz())?);
+            }
+            if record.frontend {
+                // The frontend mixes the IF down to baseband and resamples
+                let global = SigMfGlobal::new("frontend", output_sample_rate as f64, &sdr_info, &sdr_config);
+                frontend_recorder =
+                    Some(SigMfRecorder::create(&record.frontend_path(), global, GPS_L1_FREQ_HZ as f64)?);
+            }
+        }
+
+        let raw_ring_buffer = create_samples_ring_buffer::<SampleComplex>(BUFFER_SIZE);
+        let (mut raw_producer, mut raw_consumer) = (raw_ring_buffer.producer, raw_ring_buffer.consumer);
+        // Counts lost samples and carries the stream discontinuities and times from the SDR to the RF thread
+        let stream_monitor = StreamMonitor::with_pps(sdr_dev.get_config().pps_enabled.unwrap_or(false));
+        // Only the RF thread writes, the acquisition and tracking threads read
+        let multicast_buffer = Arc::new(MulticastRingBuffer::<T>::with_size(app_config.rf.buffer_samples));
+        let (tx_acq, rx_acq) = crossbeam_channel::unbounded::<AcquisitionResult>();
+        let (tx_trk, rx_trk) = crossbeam_channel::unbounded::<TrackingMessage>();
+
+        // Each stage runs until the one before it is done
+        let stop = self.stop;
+        let sdr_done = Arc::new(AtomicBool::new(false));
+        let rf_done = Arc::new(AtomicBool::new(false));
+        let stage_done = Arc::new(AtomicBool::new(false)); // Nothing waits on the last stages
+
+        let mut handles = Vec::new();
+
+        let (sdr_stop, sdr_monitor) = (Arc::clone(&stop), stream_monitor.clone());
+        spawn_stage(&mut handles, "SDR", &sdr_done, &stop, move || {
+            sdr_stage(sdr_dev.as_mut(), &mut raw_producer, raw_recorder.as_mut(), &sdr_monitor, &sdr_stop)
+                .map_err(|e| e.to_string())
+        })?;
+
+        let rf_config = app_config.rf;
+        let (rf_buffer, rf_monitor, rf_stop) =
+            (Arc::clone(&multicast_buffer), stream_monitor.clone(), Arc::clone(&sdr_done));
+        spawn_stage(&mut handles, "RF", &rf_done, &stop, move || {
+            rf_thread(
+                &rf_config,
+                input_sample_rate,
+                &mut raw_consumer,
+                rf_buffer,
+                frontend_recorder.as_mut(),
+                &rf_monitor,
+                &rf_stop,
+            );
+            Ok(())
+        })?;
+
+        // The frontend has already mixed the IF down to baseband
+        let (acq_buffer, acq_stop, acq_config) =
+            (Arc::clone(&multicast_buffer), Arc::clone(&rf_done), app_config.acquisition.clone());
+        spawn_stage(&mut handles, "Acquisition", &stage_done, &stop, move || {
+            do_acquisition::run(acq_buffer, output_sample_rate, 0.0, tx_acq, rx_trk, &acq_config, &acq_stop)
+                .map_err(|e| e.to_string())
+        })?;
+
+        let (trk_buffer, trk_stop) = (Arc::clone(&multicast_buffer), Arc::clone(&rf_done));
+        spawn_stage(&mut handles, "Tracking", &stage_done, &stop, move || {
+            do_tracking::run(trk_buffer, rx_acq, tx_trk, output_sample_rate, &trk_stop).map_err(|e| e.to_string())
+        })?;
+
+        if let Some(spectrum_config) = app_config.spectrum.clone() {
+            let (spec_buffer, spec_stop, spec_tx) = (Arc::clone(&multicast_buffer), Arc::clone(&rf_done), self.spectrum_tx);
+            spawn_stage(&mut handles, "Spectrum", &stage_done, &stop, move || {
+                spectrum::run(spec_buffer, output_sample_rate, spectrum_config, spec_tx, &spec_stop).map_err(|e| e.to_string())
+            })?;
+        }
+
+        let stages = handles
+            .into_iter()
+            .map(|(name, handle)| {
+                let status = match handle.join() {
+                    Ok(Ok(())) if stop.load(Ordering::SeqCst) => StageStatus::Stopped,
+                    Ok(Ok(())) => StageStatus::Completed,
+                    Ok(Err(e)) => StageStatus::Failed(e),
+                    Err(payload) => StageStatus::Panicked(panic_message(payload)),
+                };
+                StageExit { name, status }
+            })
+            .collect();
+
+        Ok(ReceiverSummary {
+            stages,
+            stream_stats: Arc::clone(&stream_monitor.stats),
+            agc: app_config.rf.enable_agc.then(|| Arc::clone(&stream_monitor.agc)),
+            interference: (app_config.rf.blanking.is_some() || app_config.rf.notch.is_some())
+                .then(|| Arc::clone(&stream_monitor.interference)),
+            readers: Arc::clone(&multicast_buffer.reader_stats),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIM_CONFIG: &str = r#"
+        device = "sim"
+
+        [sdr]
+        center_frequency_hz = 1575420000
+        sample_rate_hz = 2048000
+        bandwidth_hz = 2048000
+        gain_db = 0.0
+
+        [rf]
+        output_sample_rate_hz = 2048000
+        enable_agc = false
+
+        [pvt]
+        enable = false
+
+        [output]
+        file_type = "json"
+
+        [sim]
+        sample_rate_hz = 2048000
+        freq_if_hz = 0
+        noise_sigma = 1.0
+        seed = 1
+    "#;
+
+    #[test]
+    fn test_receiver_runs_to_the_end_of_the_stream() {
+        let config = format!("{}duration_s = 0.05\n", SIM_CONFIG);
+        let receiver = Receiver::new(AppConfig::from_toml_str(&config).unwrap());
+        let summary = receiver.run().expect("Failed to run the receiver");
+
+        let names: Vec<&str> = summary.stages.iter().map(|s| s.name).collect();
+        assert_eq!(names, vec!["SDR", "RF", "Acquisition", "Tracking"]);
+        assert!(summary.stages.iter().all(|s| s.status == StageStatus::Completed), "{}", summary);
+        assert_eq!(summary.stream_stats.samples_read.load(Ordering::Relaxed), 102400);
+    }
+
+    #[test]
+    fn test_receiver_publishes_spectrum() {
+        let config = format!("{}duration_s = 0.05\n\n[spectrum]\nfft_size = 256\nframe_interval_ms = 10.0\n", SIM_CONFIG);
+        let mut receiver = Receiver::new(AppConfig::from_toml_str(&config).unwrap());
+        let frames = receiver.subscribe_spectrum();
+        let summary = receiver.run().expect("Failed to run the receiver");
+
+        assert_eq!(summary.stages.last().map(|s| s.name), Some("Spectrum"));
+        assert!(summary.stages.iter().all(|s| s.status == StageStatus::Completed), "{}", summary);
+        // 102400 samples, a frame every 20480 at most, fewer when the monitor skips ahead
+        let frames: Vec<SpectrumFrame> = frames.try_iter().collect();
+        assert!((1..=5).contains(&frames.len()), "{} frames", frames.len());
+        assert!(frames.iter().all(|f| f.psd_db.len() == 256 && f.sample_rate_hz == 2_048_000.0));
+        assert!(frames.windows(2).all(|w| w[1].sample_index >= w[0].sample_index + 20480));
+        assert!(frames.iter().all(|f| f.sample_index + 256 + 15 * 128 <= 102400));
+
+        // Frames are read from the ring buffer, twice their span must fit
+        let config = config.replace("enable_agc = false", "enable_agc = false\nbuffer_samples = 65536");
+        assert!(AppConfig::from_toml_str(&config).is_ok());
+        assert!(AppConfig::from_toml_str(&config.replace("fft_size = 256", "fft_size = 4096")).is_err());
+    }
+
+    #[test]
+    fn test_receiver_with_int8_buffer() {
+        let config = SIM_CONFIG.replace("enable_agc = false", "enable_agc = true\nbuffer_format = \"int8_iq\"");
+        let app_config = AppConfig::from_toml_str(&format!("{}duration_s = 0.05\n", config)).unwrap();
+        assert_eq!(app_config.rf.buffer_format, BufferFormat::Int8Iq);
+        let summary = Receiver::new(app_config).run().expect("Failed to run the receiver");
+        assert!(summary.stages.iter().all(|s| s.status == StageStatus::Completed), "{}", summary);
+
+        // The integer types are scaled for the AGC output
+        let config = SIM_CONFIG.replace("enable_agc = false", "enable_agc = false\nbuffer_format = \"int16_iq\"");
+        assert!(AppConfig::from_toml_str(&config).is_err());
+    }
+
+    #[test]
+    fn test_receiver_stops_on_request() {
+        // No duration, the simulator streams until stopped
+        let receiver = Receiver::new(AppConfig::from_toml_str(SIM_CONFIG).unwrap());
+        let stop = receiver.stop_flag();
+        let stopper = thread::spawn(move || {
+            thread::sleep(std::time::Duration::from_millis(200));
+            stop.store(true, Ordering::SeqCst);
+        });
+        let summary = receiver.run().expect("Failed to run the receiver");
+        stopper.join().unwrap();
+
+        assert!(summary.is_ok(), "{}", summary);
+        assert!(summary.stages.iter().all(|s| s.status == StageStatus::Stopped), "{}", summary);
+        assert!(summary.stream_stats.samples_read.load(Ordering::Relaxed) > 0);
+    }
+
+    #[test]
+    fn test_sdr_stage_stops_the_stream() {
+        use crate::sdr_mock::device_mock::MockDevice;
+        use crate::sdr_store::profiled_sdr::ProfiledSdr;
+        use crate::sdr_store::rtl_sdr::RTL_SDR_PROFILE;
+
+        let mut device = ProfiledSdr::<MockDevice>::new("driver=rtlsdr", &RTL_SDR_PROFILE).unwrap();
+        let mut producer = create_samples_ring_buffer::<SampleComplex>(BUFFER_SIZE).producer;
+        let monitor = StreamMonitor::new();
+        // Not started until the stage runs, e.g. when its thread fails to spawn
+        assert!(!device.backend.rx_active);
+        sdr_stage(&mut device, &mut producer, None, &monitor, &AtomicBool::new(true)).unwrap();
+        assert!(!device.backend.rx_active);
+    }
+}
@@ -0,0 +1,166 @@
+use num_complex::Complex32;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Uniform quantizer step for unit variance Gaussian samples, for 1 to 4 bits per component.
+/// One bit keeps the sign, at +/-1.
+const QUANTIZER_STEPS: [f32; 4] = [2.0, 0.9957, 0.5860, 0.3352];
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct AgcConfig {
+    #[serde(default = "default_target_power")]
+    pub target_power: f32, // Mean |x|^2 of the frontend output
+    #[serde(default = "default_time_constant_ms")]
+    pub time_constant_ms: f32, // Smoothing of the power estimate
+    pub requantize_bits: Option<u8>, // 1 to 4 bits per I/Q component, like a GNSS front-end ADC
+}
+
+fn default_target_power() -> f32 {
+    1.0
+}
+
+fn default_time_constant_ms() -> f32 {
+    10.0
+}
+
+impl Default for AgcConfig {
+    fn default() -> Self {
+        Self {
+            target_power: default_target_power(),
+            time_constant_ms: default_time_constant_ms(),
+            requantize_bits: None,
+        }
+    }
+}
+
+/// AGC state readable from other threads, the f32 values are stored as bits
+#[derive(Debug, Default)]
+pub struct AgcTelemetry {
+    gain_db: AtomicU32,
+    input_power_db: AtomicU32,
+}
+
+impl AgcTelemetry {
+    pub fn gain_db(&self) -> f32 {
+        f32::from_bits(self.gain_db.load(Ordering::Relaxed))
+    }
+
+    /// Mean power of the samples entering the AGC, in dB of full scale squared
+    pub fn input_power_db(&self) -> f32 {
+        f32::from_bits(self.input_power_db.load(Ordering::Relaxed))
+    }
+
+    fn update(&self, gain_db: f32, input_power_db: f32) {
+        self.gain_db.store(gain_db.to_bits(), Ordering::Relaxed);
+        self.input_power_db.store(input_power_db.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Scales each block to the target power, from a smoothed power estimate, then optionally
+/// requantizes I and Q to a few bits
+pub struct BlockAgc {
+    pub config: AgcConfig,
+    sample_rate_hz: f32,
+    power: Option<f32>, // Smoothed input power, None before the first block
+    pub gain: f32,
+    telemetry: Option<Arc<AgcTelemetry>>,
+}
+
+impl BlockAgc {
+    pub fn new(config: AgcConfig, sample_rate_hz: f32) -> Self {
+        Self { config, sample_rate_hz, power: None, gain: 1.0, telemetry: None }
+    }
+
+    /// Publish the gain and input power after every block
+    pub fn with_telemetry(mut self, telemetry: Arc<AgcTelemetry>) -> Self {
+        self.telemetry = Some(telemetry);
+        self
+    }
+
+    pub fn input_power(&self) -> Option<f32> {
+        self.power
+    }
+
+    pub fn process_block(&mut self, block: &mut [Complex32]) {
+        if block.is_empty() {
+            return;
+        }
+        let block_power = block.iter().map(|s| s.norm_sqr()).sum::<f32>() / block.len() as f32;
+        let alpha = 1.0 - (-(block.len() as f32) / (self.config.time_constant_ms * 1e-3 * self.sample_rate_hz)).exp();
+        let power = match self.power {
+            Some(power) => power + alpha * (block_power - power),
+            None => block_power,
+        };
+        self.power = Some(power);
+        // A silent input keeps the last gain
+        if power > f32::MIN_POSITIVE {
+            self.gain = (self.config.target_power / power).sqrt();
+        }
+
+        match self.config.requantize_bits {
+            Some(bits) => {
+                let sigma = (self.config.target_power / 2.0).sqrt(); // Per component
+                let step = QUANTIZER_STEPS[(bits.clamp(1, 4) - 1) as usize] * sigma;
+                let max_level = (1i32 << (bits.clamp(1, 4) - 1)) as f32;
+                let quantize = |x: f32| ((x * self.gain / step).floor().clamp(-max_level, max_level - 1.0) + 0.5) * step;
+                for s in block.iter_mut() {
+                    *s = Complex32::new(quantize(s.re), quantize(s.im));
+                }
+            }
+            None => block.iter_mut().for_each(|s| *s *= self.gain),
+        }
+
+        if let Some(telemetry) = &self.telemetry {
+            telemetry.update(20.0 * self.gain.log10(), 10.0 * power.log10());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::signal_generator::{SignalGenerator, SimConfig};
+    use std::collections::HashSet;
+
+    fn noise(sigma: f64, n: usize) -> Vec<Complex32> {
+        let mut config = SimConfig::new(2_048_000.0, 0.0, 11);
+        config.noise_sigma = sigma;
+        let mut samples = vec![Complex32::new(0.0, 0.0); n];
+        SignalGenerator::new(config).generate(&mut samples);
+        samples
+    }
+
+    #[test]
+    fn test_agc_normalizes_power() {
+        let telemetry = Arc::new(AgcTelemetry::default());
+        let mut agc = BlockAgc::new(AgcConfig::default(), 2_048_000.0).with_telemetry(Arc::clone(&telemetry));
+        // 40 dB more power than the target, from a per component sigma of 70.7
+        let mut samples = noise(70.7, 2048 * 20);
+        for block in samples.chunks_mut(2048) {
+            agc.process_block(block);
+        }
+        let tail = &samples[2048 * 10..];
+        let power = tail.iter().map(|s| s.norm_sqr()).sum::<f32>() / tail.len() as f32;
+        assert!((power - 1.0).abs() < 0.05, "output power {}", power);
+        assert!((telemetry.gain_db() + 40.0).abs() < 0.2, "gain {} dB", telemetry.gain_db());
+        assert!((telemetry.input_power_db() - 40.0).abs() < 0.2);
+    }
+
+    #[test]
+    fn test_requantization() {
+        for bits in 1..=4u8 {
+            let config = AgcConfig { requantize_bits: Some(bits), ..AgcConfig::default() };
+            let mut agc = BlockAgc::new(config, 2_048_000.0);
+            let mut samples = noise(3.0, 2048 * 4);
+            for block in samples.chunks_mut(2048) {
+                agc.process_block(block);
+            }
+            let levels: HashSet<u32> = samples.iter().flat_map(|s| [s.re.to_bits(), s.im.to_bits()]).collect();
+            assert_eq!(levels.len(), 1 << bits, "{} bits", bits);
+            // The levels are set for the target power
+            let power = samples.iter().map(|s| s.norm_sqr()).sum::<f32>() / samples.len() as f32;
+            assert!((power - 1.0).abs() < 0.2, "{} bits: power {}", bits, power);
+        }
+    }
+}
@@ -1,33 +1,175 @@
 use std::simd::f32x8;
-use std::simd::usizex8;
+use num_complex::Complex32;
+use crate::filter::{FirFilterSimd, HalfBandDecimator, Window};
+use crate::rf::agc::BlockAgc;
 use crate::rf::dc_remove::DcRemoverSimd;
-use crate::rf::nco_lut::{mix_simd, NcoLut, LUT_SIZE};
+use crate::rf::interference::InterferenceMitigation;
+use crate::rf::nco_lut::{mix_simd, NcoLut};
+use crate::rf::resampler::PolyphaseResampler;
+
+/// Non zero taps on each side of the center of the half-band filter of real input
+const REAL_INPUT_HALF_BAND_LEN: usize = 12;
+
+/// Rate of the complex baseband before resampling, real input is decimated by 2
+pub fn baseband_sample_rate(fs_in: f32, real_input: bool) -> f32 {
+    if real_input { fs_in / 2.0 } else { fs_in }
+}
 
 pub struct DigitalFrontend {
     // NCO for frequency shifting
     nco: NcoLut,
     // DC offset removal
     dc_remove: DcRemoverSimd,
-    // Resampling
-    #[allow(dead_code)]
+    // Pulse blanking and CW notching, at the input sample rate
+    interference: Option<InterferenceMitigation>,
+    // Low-pass keeping the channel of interest, before resampling
+    channel_filter: Option<FirFilterSimd>,
+    // Real IF input: the image left by the mixing is removed while decimating by 2
+    real_input: bool,
+    decimator: Option<HalfBandDecimator>,
+    // Resampling, None when the output rate is the baseband rate
+    resampler: Option<PolyphaseResampler>,
     input_sample_rate: f32,
-    #[allow(dead_code)]
-    output_sample_rate: f32,
+    output_sample_rate: f32, // Actual rate, the requested one may need a too long filter bank
+    // Power normalization and requantization of the output
+    agc: Option<BlockAgc>,
 }
 
 impl DigitalFrontend {
+    /// Complex IQ input
     pub fn new(f_if: f32, fs_in: f32, fs_out: f32) -> Self {
+        Self::build(f_if, fs_in, fs_out, false)
+    }
+
+    /// Real samples at `f_if`, e.g. int8 IF recordings, the Q channel is ignored
+    pub fn new_real_if(f_if: f32, fs_in: f32, fs_out: f32) -> Self {
+        let image_hz = (-2.0 * f_if).rem_euclid(fs_in);
+        let image_hz = if image_hz > fs_in / 2.0 { image_hz - fs_in } else { image_hz };
+        if image_hz.abs() < fs_in / 4.0 {
+            println!(
+                "Warning: The image of the {} Hz IF at {} Hz is not removed by the decimation, an IF near {} Hz is best",
+                f_if, image_hz, fs_in / 4.0
+            );
+        }
+        Self::build(f_if, fs_in, fs_out, true)
+    }
+
+    fn build(f_if: f32, fs_in: f32, fs_out: f32, real_input: bool) -> Self {
         let nco = NcoLut::new(f_if, fs_in as f32);
         let dc_remove = DcRemoverSimd::new(0.001);
+        let decimator = real_input.then(|| HalfBandDecimator::new(REAL_INPUT_HALF_BAND_LEN, Window::Blackman));
+        let fs_baseband = baseband_sample_rate(fs_in, real_input);
+        let resampler =
+            (fs_out != fs_baseband).then(|| PolyphaseResampler::new(fs_baseband as f64, fs_out as f64));
+        let output_sample_rate = match &resampler {
+            Some(r) => r.output_sample_rate(fs_baseband as f64) as f32,
+            None => fs_baseband,
+        };
+        if output_sample_rate != fs_out {
+            println!(
+                "Warning: Resampling to {} Hz instead of {} Hz",
+                output_sample_rate, fs_out
+            );
+        }
 
         DigitalFrontend {
             nco,
             dc_remove,
+            interference: None,
+            channel_filter: None,
+            real_input,
+            decimator,
+            resampler,
             input_sample_rate: fs_in,
-            output_sample_rate: fs_out,
+            output_sample_rate,
+            agc: None,
         }
     }
 
+    /// Level the output with `agc`, running at the output sample rate
+    pub fn with_agc(mut self, agc: BlockAgc) -> Self {
+        self.agc = Some(agc);
+        self
+    }
+
+    pub fn agc(&self) -> Option<&BlockAgc> {
+        self.agc.as_ref()
+    }
+
+    /// Mitigate interference after mixing to baseband, before resampling
+    pub fn with_interference(mut self, interference: InterferenceMitigation) -> Self {
+        self.interference = Some(interference);
+        self
+    }
+
+    pub fn interference(&self) -> Option<&InterferenceMitigation> {
+        self.interference.as_ref()
+    }
+
+    /// Filter the baseband at the input sample rate, e.g. to drop the rest of a wide capture
+    pub fn with_channel_filter(mut self, filter: FirFilterSimd) -> Self {
+        self.channel_filter = Some(filter);
+        self
+    }
+
+    pub fn input_sample_rate(&self) -> f32 {
+        self.input_sample_rate
+    }
+
+    pub fn output_sample_rate(&self) -> f32 {
+        self.output_sample_rate
+    }
+
+    /// Interpolate the NCO between its LUT points
+    pub fn with_nco_interpolation(mut self) -> Self {
+        self.nco.interpolate = true;
+        self
+    }
+
+    pub fn nco(&self) -> &NcoLut {
+        &self.nco
+    }
+
+    /// Move the IF mixed down to baseband, without a phase jump
+    pub fn retune(&mut self, f_if: f32) {
+        self.nco.retune(f_if as f64);
+    }
+
+    pub fn real_input(&self) -> bool {
+        self.real_input
+    }
+
+    /// Decimate real input, resample and level a block processed by `process_block`,
+    /// the result is valid until the next call
+    pub fn output_block<'a>(&'a mut self, block: &'a mut [Complex32]) -> &'a [Complex32] {
+        let baseband = match &mut self.decimator {
+            Some(decimator) => decimator.process(block),
+            None => block,
+        };
+        let output = match &mut self.resampler {
+            Some(resampler) => resampler.process(baseband),
+            None => baseband,
+        };
+        if let Some(agc) = &mut self.agc {
+            agc.process_block(output);
+        }
+        output
+    }
+
+    /// Number of outputs of the next block before its input sample `input_offset`
+    pub fn output_offset(&self, input_offset: usize) -> usize {
+        let baseband_offset = self.decimator.as_ref().map_or(input_offset, |d| d.output_offset(input_offset));
+        self.resampler.as_ref().map_or(baseband_offset, |r| r.output_offset(baseband_offset))
+    }
+
+    /// Input position of output `output_offset` of the next block, relative to the block start
+    pub fn input_offset(&self, output_offset: usize) -> f64 {
+        let baseband_offset = self.resampler.as_ref().map_or(output_offset as f64, |r| r.input_offset(output_offset));
+        let filtered_offset = self.decimator.as_ref().map_or(baseband_offset, |d| d.input_offset(baseband_offset));
+        // The channel filter runs first, at the input rate
+        filtered_offset - self.channel_filter.as_ref().map_or(0.0, |f| f.delay())
+    }
+
     /// Process a block of samples in-place, using SIMD for performance, the input samples are in size 4096
     pub fn process_block(&mut self, raw_floats: &mut [f32]) {
         // Process 16 samples at a time using SIMD
@@ -36,33 +178,98 @@ impl DigitalFrontend {
             let b = f32x8::from_slice(&chunk[8..16]);
 
             let (mut re_v, mut im_v) = a.deinterleave(b);  // Now re_v and im_v contain the I and Q components of 8 samples separately
+            if self.real_input {
+                im_v = f32x8::splat(0.0);
+            }
  
             // DC offset removal
             let (dc_removed_re, dc_removed_im) = self.dc_remove.process_block(re_v, im_v);
             re_v = dc_removed_re;
             im_v = dc_removed_im;
 
-            // Generate 8 indices for the LUT based on the current phase
-            let mut indices = [0usize; 8];
-            for i in 0..8 {
-                indices[i] = self.nco.phase_accumulator as usize % LUT_SIZE;
-                self.nco.phase_accumulator =
-                    (self.nco.phase_accumulator + self.nco.phase_step) % LUT_SIZE as f32;
+            // cos and -sin of the next 8 NCO phases
+            let (cos_v, sin_v) = self.nco.next_simd();
+            let (mut res_re, mut res_im) = mix_simd(re_v, im_v, cos_v, sin_v);
+
+            // Pulse blanking and CW notching
+            if let Some(interference) = &mut self.interference {
+                (res_re, res_im) = interference.process_block(res_re, res_im);
             }
-            // A bit slower than gather, but hardware support for gather is not good, and this can be optimized by compiler to use SIMD load
-            // let cos_v = f32x8::from_array([self.lut_re[indices[0]], self.lut_re[indices[1]], self.lut_re[indices[2]], self.lut_re[indices[3]], self.lut_re[indices[4]], self.lut_re[indices[5]], self.lut_re[indices[6]], self.lut_re[indices[7]]]);
-            // let sin_v = f32x8::from_array([self.lut_im[indices[0]], self.lut_im[indices[1]], self.lut_im[indices[2]], self.lut_im[indices[3]], self.lut_im[indices[4]], self.lut_im[indices[5]], self.lut_im[indices[6]], self.lut_im[indices[7]]]);
-            let idx_v = usizex8::from_array(indices);
-            let cos_v = f32x8::gather_or_default(&self.nco.lut_re, idx_v);
-            let sin_v = f32x8::gather_or_default(&self.nco.lut_im, idx_v);
-            let (res_re, res_im) = mix_simd(re_v, im_v, cos_v, sin_v);
             let (out_a, out_b) = res_re.interleave(res_im);
             out_a.copy_to_slice(&mut chunk[0..8]);
             out_b.copy_to_slice(&mut chunk[8..16]);
         }
 
-        // Pulse blanking, e.g., based on amplitude threshold)
+        if let Some(interference) = &mut self.interference {
+            interference.update_stats();
+        }
+
+        if let Some(filter) = &mut self.channel_filter {
+            filter.process_interleaved(raw_floats);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acquisition::do_acquisition::AcquisitionWorker;
+    use crate::acquisition::doppler_shift::DopplerShiftTable;
+    use crate::simulator::signal_generator::{SignalGenerator, SimConfig, SimSatellite};
+
+    #[test]
+    fn test_real_if_to_baseband() {
+        // Real samples at a 2.1 MHz IF, decimated to 4.092 MS/s complex baseband
+        const FS_IN: f32 = 8_184_000.0;
+        const F_IF: f32 = 2_100_000.0;
+        const FS_OUT: f32 = 4_092_000.0;
+        const NUM_INTEGRATIONS: usize = 10;
+        let fft_size = (FS_OUT / 1000.0) as usize;
+
+        // PRN 6 code starts at input sample 1600, output sample 800
+        let mut config = SimConfig::new(FS_IN as f64, F_IF as f64, 3);
+        config.satellites.push(SimSatellite::new(6, 823.0, 2000.0, 45.0));
+        let mut samples = SignalGenerator::new(config).generate_samples(2048 * 48);
+        samples.iter_mut().for_each(|s| s.im = 0.0);
+
+        let mut frontend = DigitalFrontend::new_real_if(F_IF, FS_IN, FS_OUT);
+        assert_eq!(frontend.output_sample_rate(), FS_OUT);
+        assert_eq!(frontend.output_offset(2048), 1024);
+        let delay = -frontend.input_offset(0) / 2.0; // In output samples
+        let mut output = Vec::new();
+        for block in samples.chunks_exact_mut(2048) {
+            let floats = unsafe { std::slice::from_raw_parts_mut(block.as_mut_ptr() as *mut f32, 4096) };
+            frontend.process_block(floats);
+            output.extend_from_slice(frontend.output_block(block));
+        }
+        assert_eq!(output.len(), 1024 * 48);
+
+        // Once the DC remover has settled, the satellite is at its Doppler, not at the IF or its image
+        let doppler_tables: Vec<DopplerShiftTable> = (0..29)
+            .map(|i| DopplerShiftTable::new(0.0, -7000.0 + 500.0 * i as f32, FS_OUT, fft_size))
+            .collect();
+        let mut worker = AcquisitionWorker::new(6, fft_size, FS_OUT);
+        let acq = worker
+            .search_satellite(&output[fft_size..(NUM_INTEGRATIONS + 1) * fft_size], &doppler_tables, 0, NUM_INTEGRATIONS)
+            .expect("PRN 6 not acquired");
+        assert!((acq.code_phase_samples as f64 - (800.0 + delay)).abs() <= 1.0, "{} samples", acq.code_phase_samples);
+        assert!((acq.carrier_freq - 2000.0).abs() <= 500.0, "{} Hz", acq.carrier_freq);
+    }
+
+    #[test]
+    fn test_channel_filter_delay() {
+        use crate::filter::design_lowpass;
 
-        // Resampling
+        // An impulse comes out of the 31 taps filter 15 samples later
+        let mut frontend = DigitalFrontend::new(0.0, 4e6, 4e6)
+            .with_channel_filter(FirFilterSimd::new(design_lowpass(0.25, 31, Window::Blackman)));
+        assert_eq!(frontend.input_offset(0), -15.0);
+        let mut block = vec![Complex32::new(0.0, 0.0); 2048];
+        block[100] = Complex32::new(1.0, 0.0);
+        let floats = unsafe { std::slice::from_raw_parts_mut(block.as_mut_ptr() as *mut f32, 4096) };
+        frontend.process_block(floats);
+        let output = frontend.output_block(&mut block);
+        let peak = (0..output.len()).fold(0, |best, i| if output[i].norm() > output[best].norm() { i } else { best });
+        assert_eq!(frontend.input_offset(peak), 100.0);
     }
 }
@@ -0,0 +1,400 @@
+use num_complex::Complex32;
+use rustfft::{Fft, FftPlanner};
+use serde::Deserialize;
+use std::f32::consts::PI;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::simd::Select;
+use std::simd::cmp::SimdPartialOrd;
+use std::simd::f32x8;
+use std::simd::num::SimdFloat;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Samples per spectrum of the CW detector
+const DETECT_FFT_SIZE: usize = 1024;
+
+/// Weight of the newest spectrum in the averaged one
+const SPECTRUM_AVERAGING: f32 = 0.2;
+
+/// Amplitude-threshold blanking of pulsed interference, e.g. DME/TACAN
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct BlankingConfig {
+    #[serde(default = "default_blanking_threshold")]
+    pub threshold: f32, // Blank samples above this many times the RMS amplitude
+    #[serde(default = "default_blanking_alpha")]
+    pub alpha: f32, // Smoothing of the power estimate, like the DC remover
+}
+
+fn default_blanking_threshold() -> f32 {
+    4.0
+}
+
+fn default_blanking_alpha() -> f32 {
+    0.001
+}
+
+/// Notches following the CW interferers found in the spectrum
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct NotchConfig {
+    #[serde(default = "default_notch_threshold_db")]
+    pub threshold_db: f32, // Spectral lines this far above the median are notched
+    #[serde(default = "default_max_notches")]
+    pub max_notches: usize,
+    #[serde(default = "default_pole_radius")]
+    pub pole_radius: f32, // Closer to 1 for a narrower notch
+}
+
+fn default_notch_threshold_db() -> f32 {
+    15.0
+}
+
+fn default_max_notches() -> usize {
+    4
+}
+
+fn default_pole_radius() -> f32 {
+    0.99
+}
+
+/// Counters of the interference mitigation, updated by the RF thread
+#[derive(Debug, Default)]
+pub struct InterferenceStats {
+    pub samples: AtomicU64,
+    pub blanked_samples: AtomicU64,
+    pub active_notches: AtomicUsize,
+}
+
+impl InterferenceStats {
+    pub fn blanked_fraction(&self) -> f64 {
+        let samples = self.samples.load(Ordering::Relaxed);
+        if samples == 0 {
+            return 0.0;
+        }
+        self.blanked_samples.load(Ordering::Relaxed) as f64 / samples as f64
+    }
+}
+
+pub struct PulseBlankerSimd {
+    threshold_sq: f32x8,
+    alpha: f32x8,
+    con: f32x8,
+    power: Option<f32x8>, // Mean |x|^2 of the samples kept, per lane
+    pub blanked: u64,
+}
+
+impl PulseBlankerSimd {
+    pub fn new(config: BlankingConfig) -> Self {
+        let alpha = f32x8::splat(config.alpha);
+        Self {
+            threshold_sq: f32x8::splat(config.threshold * config.threshold),
+            alpha,
+            con: f32x8::splat(1.0) - alpha,
+            power: None,
+            blanked: 0,
+        }
+    }
+
+    #[inline(always)]
+    pub fn process_block(&mut self, input_re: f32x8, input_im: f32x8) -> (f32x8, f32x8) {
+        let p = input_re * input_re + input_im * input_im;
+        let power = *self.power.get_or_insert(f32x8::splat(p.reduce_sum() / 8.0));
+        let pulse = p.simd_gt(power * self.threshold_sq);
+        self.blanked += pulse.to_bitmask().count_ones() as u64;
+
+        // The pulses don't raise the threshold
+        self.power = Some(pulse.select(power, power * self.con + p * self.alpha));
+        let zero = f32x8::splat(0.0);
+        (pulse.select(zero, input_re), pulse.select(zero, input_im))
+    }
+}
+
+/// y[n] = x[n] - z x[n-1] + p y[n-1], with z on the unit circle at the interferer frequency
+/// and p just inside it
+struct NotchFilter {
+    freq_hz: f32,
+    zero: Complex32,
+    pole: Complex32,
+    x_prev: Complex32,
+    y_prev: Complex32,
+}
+
+impl NotchFilter {
+    fn new(freq_hz: f32, sample_rate_hz: f32, pole_radius: f32) -> Self {
+        let mut notch = Self {
+            freq_hz,
+            zero: Complex32::new(1.0, 0.0),
+            pole: Complex32::new(pole_radius, 0.0),
+            x_prev: Complex32::new(0.0, 0.0),
+            y_prev: Complex32::new(0.0, 0.0),
+        };
+        notch.retune(freq_hz, sample_rate_hz, pole_radius);
+        notch
+    }
+
+    fn retune(&mut self, freq_hz: f32, sample_rate_hz: f32, pole_radius: f32) {
+        self.freq_hz = freq_hz;
+        self.zero = Complex32::from_polar(1.0, 2.0 * PI * freq_hz / sample_rate_hz);
+        self.pole = self.zero * pole_radius;
+    }
+
+    #[inline(always)]
+    fn filter(&mut self, x: Complex32) -> Complex32 {
+        let y = x - self.zero * self.x_prev + self.pole * self.y_prev;
+        self.x_prev = x;
+        self.y_prev = y;
+        y
+    }
+}
+
+/// Finds CW interferers in an averaged spectrum of the stream and removes them with
+/// narrow notches, retuned after every spectrum
+pub struct AdaptiveNotch {
+    config: NotchConfig,
+    sample_rate_hz: f32,
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    detect_buf: Vec<Complex32>,
+    spectrum: Option<Vec<f32>>, // Averaged power spectrum
+    prev_bins: Option<Vec<Complex32>>, // Spectrum of the previous buffer, for the phase of the lines
+    notches: Vec<NotchFilter>,
+}
+
+impl AdaptiveNotch {
+    pub fn new(config: NotchConfig, sample_rate_hz: f32) -> Self {
+        let window = (0..DETECT_FFT_SIZE)
+            .map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / DETECT_FFT_SIZE as f32).cos())
+            .collect();
+        Self {
+            config,
+            sample_rate_hz,
+            fft: FftPlanner::new().plan_fft_forward(DETECT_FFT_SIZE),
+            window,
+            detect_buf: Vec::with_capacity(DETECT_FFT_SIZE),
+            spectrum: None,
+            prev_bins: None,
+            notches: Vec::new(),
+        }
+    }
+
+    /// Frequencies being notched, in Hz
+    pub fn notch_frequencies(&self) -> Vec<f32> {
+        self.notches.iter().map(|n| n.freq_hz).collect()
+    }
+
+    #[inline(always)]
+    pub fn process_block(&mut self, input_re: f32x8, input_im: f32x8) -> (f32x8, f32x8) {
+        let (mut re, mut im) = (input_re.to_array(), input_im.to_array());
+        for i in 0..8 {
+            // The detector sees the interferers before they are notched
+            let mut x = Complex32::new(re[i], im[i]);
+            self.detect_buf.push(x);
+            for notch in self.notches.iter_mut() {
+                x = notch.filter(x);
+            }
+            (re[i], im[i]) = (x.re, x.im);
+        }
+        if self.detect_buf.len() == DETECT_FFT_SIZE {
+            self.update_notches();
+            self.detect_buf.clear();
+        }
+        (f32x8::from_array(re), f32x8::from_array(im))
+    }
+
+    fn update_notches(&mut self) {
+        let mut bins: Vec<Complex32> =
+            self.detect_buf.iter().zip(&self.window).map(|(x, w)| x * w).collect();
+        self.fft.process(&mut bins);
+        let spectrum = match self.spectrum.take() {
+            Some(mut avg) => {
+                for (a, b) in avg.iter_mut().zip(&bins) {
+                    *a += SPECTRUM_AVERAGING * (b.norm_sqr() - *a);
+                }
+                avg
+            }
+            None => bins.iter().map(|b| b.norm_sqr()).collect(),
+        };
+
+        let mut sorted = spectrum.clone();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let threshold = sorted[DETECT_FFT_SIZE / 2] * 10f32.powf(self.config.threshold_db / 10.0);
+
+        // Local maxima above the threshold, strongest first
+        let n = DETECT_FFT_SIZE;
+        let mut peaks: Vec<usize> = (0..n)
+            .filter(|&k| {
+                let (prev, next) = (spectrum[(k + n - 1) % n], spectrum[(k + 1) % n]);
+                spectrum[k] > threshold && spectrum[k] >= prev && spectrum[k] > next
+            })
+            .collect();
+        peaks.sort_by(|&a, &b| spectrum[b].total_cmp(&spectrum[a]));
+        peaks.truncate(self.config.max_notches);
+
+        let bin_hz = self.sample_rate_hz / n as f32;
+        let mut notches = Vec::with_capacity(peaks.len());
+        for k in peaks {
+            // A line advances by its offset from the bin center over the buffer, in cycles.
+            // The first time, parabolic interpolation of the log spectrum around the peak
+            let delta = match &self.prev_bins {
+                Some(prev) => (bins[k] * prev[k].conj()).arg() / (2.0 * PI),
+                None => {
+                    let (a, b, c) = (
+                        spectrum[(k + n - 1) % n].max(f32::MIN_POSITIVE).ln(),
+                        spectrum[k].ln(),
+                        spectrum[(k + 1) % n].max(f32::MIN_POSITIVE).ln(),
+                    );
+                    if a - 2.0 * b + c != 0.0 { 0.5 * (a - c) / (a - 2.0 * b + c) } else { 0.0 }
+                }
+            };
+            let bin = if k < n / 2 { k as f32 } else { k as f32 - n as f32 };
+            let freq_hz = (bin + delta) * bin_hz;
+
+            // A notch already near the line keeps its state
+            match self.notches.iter().position(|f| (f.freq_hz - freq_hz).abs() < 2.0 * bin_hz) {
+                Some(i) => {
+                    let mut notch = self.notches.swap_remove(i);
+                    notch.retune(freq_hz, self.sample_rate_hz, self.config.pole_radius);
+                    notches.push(notch);
+                }
+                None => notches.push(NotchFilter::new(freq_hz, self.sample_rate_hz, self.config.pole_radius)),
+            }
+        }
+        self.notches = notches;
+        self.spectrum = Some(spectrum);
+        self.prev_bins = Some(bins);
+    }
+}
+
+impl Display for InterferenceStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "blanked samples: {} ({:.3}%), active notches: {}",
+            self.blanked_samples.load(Ordering::Relaxed),
+            100.0 * self.blanked_fraction(),
+            self.active_notches.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// Pulse blanking then CW notching, each when configured
+pub struct InterferenceMitigation {
+    pub blanker: Option<PulseBlankerSimd>,
+    pub notch: Option<AdaptiveNotch>,
+    stats: Arc<InterferenceStats>,
+    samples: u64,
+}
+
+impl InterferenceMitigation {
+    pub fn new(
+        blanking: Option<BlankingConfig>,
+        notch: Option<NotchConfig>,
+        sample_rate_hz: f32,
+        stats: Arc<InterferenceStats>,
+    ) -> Self {
+        Self {
+            blanker: blanking.map(PulseBlankerSimd::new),
+            notch: notch.map(|config| AdaptiveNotch::new(config, sample_rate_hz)),
+            stats,
+            samples: 0,
+        }
+    }
+
+    #[inline(always)]
+    pub fn process_block(&mut self, mut re: f32x8, mut im: f32x8) -> (f32x8, f32x8) {
+        if let Some(blanker) = &mut self.blanker {
+            (re, im) = blanker.process_block(re, im);
+        }
+        if let Some(notch) = &mut self.notch {
+            (re, im) = notch.process_block(re, im);
+        }
+        self.samples += 8;
+        (re, im)
+    }
+
+    /// Publish the counters, once per block
+    pub fn update_stats(&mut self) {
+        self.stats.samples.store(self.samples, Ordering::Relaxed);
+        if let Some(blanker) = &self.blanker {
+            self.stats.blanked_samples.store(blanker.blanked, Ordering::Relaxed);
+        }
+        if let Some(notch) = &self.notch {
+            self.stats.active_notches.store(notch.notches.len(), Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::signal_generator::{SignalGenerator, SimConfig};
+
+    fn noise(n: usize) -> Vec<Complex32> {
+        let mut samples = vec![Complex32::new(0.0, 0.0); n];
+        SignalGenerator::new(SimConfig::new(2_048_000.0, 0.0, 5)).generate(&mut samples);
+        samples
+    }
+
+    fn run(mitigation: &mut InterferenceMitigation, samples: &mut [Complex32]) {
+        for chunk in samples.chunks_exact_mut(8) {
+            let re = f32x8::from_array(std::array::from_fn(|i| chunk[i].re));
+            let im = f32x8::from_array(std::array::from_fn(|i| chunk[i].im));
+            let (re, im) = mitigation.process_block(re, im);
+            for (i, s) in chunk.iter_mut().enumerate() {
+                *s = Complex32::new(re[i], im[i]);
+            }
+        }
+        mitigation.update_stats();
+    }
+
+    #[test]
+    fn test_pulse_blanking() {
+        let blanking = BlankingConfig { threshold: default_blanking_threshold(), alpha: default_blanking_alpha() };
+        let stats = Arc::new(InterferenceStats::default());
+        let mut mitigation = InterferenceMitigation::new(Some(blanking), None, 2_048_000.0, Arc::clone(&stats));
+
+        // Pulses of 24 samples at 30 times the noise amplitude, every 2048 samples
+        let mut samples = noise(2048 * 40);
+        for start in (1000..samples.len()).step_by(2048) {
+            for s in &mut samples[start..start + 24] {
+                *s += Complex32::new(30.0, -30.0);
+            }
+        }
+        run(&mut mitigation, &mut samples);
+
+        for start in (1000..samples.len()).step_by(2048) {
+            assert!(samples[start..start + 24].iter().all(|s| s.norm() == 0.0));
+        }
+        // The pulses and the few noise samples above 4 times the RMS amplitude
+        let fraction = stats.blanked_fraction();
+        assert!((24.0 / 2048.0..24.0 / 2048.0 + 1e-3).contains(&fraction), "blanked {}", fraction);
+    }
+
+    #[test]
+    fn test_adaptive_notch() {
+        let (fs, f_cw) = (2_048_000.0f32, 312_345.0f32);
+        let notch = NotchConfig {
+            threshold_db: default_notch_threshold_db(),
+            max_notches: default_max_notches(),
+            pole_radius: default_pole_radius(),
+        };
+        let stats = Arc::new(InterferenceStats::default());
+        let mut mitigation = InterferenceMitigation::new(None, Some(notch), fs, Arc::clone(&stats));
+
+        let cw = |n: usize| Complex32::from_polar(10.0, 2.0 * PI * (f_cw as f64 * n as f64 / fs as f64).fract() as f32);
+        let mut samples = noise(2048 * 40);
+        for (n, s) in samples.iter_mut().enumerate() {
+            *s += cw(n);
+        }
+        run(&mut mitigation, &mut samples);
+
+        assert_eq!(stats.active_notches.load(Ordering::Relaxed), 1);
+        let freqs = mitigation.notch.as_ref().unwrap().notch_frequencies();
+        assert!((freqs[0] - f_cw).abs() < 10.0, "notch at {} Hz", freqs[0]);
+        // The CW is 40 dB down, the noise is kept
+        let tail = 2048 * 20..samples.len();
+        let cw_left = tail.clone().map(|n| samples[n] * cw(n).conj()).sum::<Complex32>().norm() / tail.len() as f32;
+        assert!(cw_left < 0.1, "CW amplitude left {}", cw_left);
+        let power = tail.clone().map(|n| samples[n].norm_sqr()).sum::<f32>() / tail.len() as f32;
+        assert!((power - 2.0).abs() < 0.2, "noise power {}", power);
+    }
+}
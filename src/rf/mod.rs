@@ -3,4 +3,7 @@ pub mod rf_thread;
 pub mod samples_block;
 pub mod frontend;
 pub mod dc_remove;
-pub mod nco_lut;
\ No newline at end of file
+pub mod nco_lut;
+pub mod resampler;
+pub mod agc;
+pub mod interference;
\ No newline at end of file
@@ -1,24 +1,43 @@
+use num_complex::Complex32;
+use rustfft::FftPlanner;
 use std::f32::consts::PI;
 use std::simd::f32x8;
 
-pub const LUT_SIZE: usize = 2048;
+pub const LUT_BITS: u32 = 11;
+pub const LUT_SIZE: usize = 1 << LUT_BITS;
 pub const LUT_MASK: usize = LUT_SIZE - 1;
 
+/// The phase is a fraction of a cycle in 1/2^64, its top bits index the LUT
+const INDEX_SHIFT: u32 = 64 - LUT_BITS;
+/// Bits below the index used for the interpolation
+const FRACTION_BITS: u32 = 24;
+const FRACTION_MASK: u64 = (1 << FRACTION_BITS) - 1;
+const FRACTION_SCALE: f32 = 1.0 / (1u64 << FRACTION_BITS) as f32;
+
 #[inline(always)]
 pub fn mix_simd(samples_i: f32x8, samples_q: f32x8, lut_cos:f32x8, lut_sin:f32x8) -> (f32x8, f32x8) {
-    // Complex multiplication: (I + jQ) * (cos - jsin)
+    // Complex multiplication: (I + jQ) * (cos - jsin), the LUT holds cos and -sin
     // Real part: I*cos + Q*sin
     // Imaginary part: Q*cos - I*sin
-    let mixed_i = samples_i * lut_cos + samples_q * lut_sin;
-    let mixed_q = samples_i * lut_sin - samples_q * lut_cos;
+    let mixed_i = samples_i * lut_cos - samples_q * lut_sin;
+    let mixed_q = samples_q * lut_cos + samples_i * lut_sin;
     (mixed_i, mixed_q)
 }
 
+/// Phase of `freq` per sample at `sample_rate`, in 1/2^64 of a cycle
+fn phase_step(freq: f64, sample_rate: f64) -> u64 {
+    ((freq / sample_rate).rem_euclid(1.0) * 2f64.powi(64)) as u64
+}
+
+/// Numerically controlled oscillator generating e^(-j phase) for downconversion. The integer
+/// phase accumulator wraps exactly, so the phase doesn't drift however long it runs.
 pub struct NcoLut {
     pub lut_re: [f32; LUT_SIZE],
     pub lut_im: [f32; LUT_SIZE],
-    pub phase_accumulator: f32,
-    pub phase_step: f32,
+    pub phase: u64, // In 1/2^64 of a cycle
+    pub phase_step: u64,
+    pub interpolate: bool, // Linear interpolation between the LUT points, else the phase is truncated
+    sample_rate: f64,
 }
 
 impl NcoLut {
@@ -31,26 +50,155 @@ impl NcoLut {
             lut_im[i] = -angle.sin(); // Negative for downconversion
         }
 
-        let phase_step = (freq / sample_rate) * LUT_SIZE as f32;
-
         Self {
             lut_re,
             lut_im,
-            phase_accumulator: 0.0,
-            phase_step,
+            phase: 0,
+            phase_step: phase_step(freq as f64, sample_rate as f64),
+            interpolate: false,
+            sample_rate: sample_rate as f64,
         }
     }
 
-    // /// Linear Interpolation, If 1024 points isn't enough, do interpolation between points
-    // #[inline(always)]
-    // pub fn mix(&mut self, sample: Complex<f32>) -> Complex<f32> {
-    //     let index = self.phase_accumulator as usize & LUT_MASK;
-    //     let nco_value = Complex::new(self.lut_re[index], self.lut_im[index]);
-    //     self.phase_accumulator += self.phase_step;
-    //     if self.phase_accumulator >= LUT_SIZE as f32 {
-    //         self.phase_accumulator -= LUT_SIZE as f32;
-    //     }
-    //     sample * nco_value
-    // }
+    /// Interpolate between the LUT points, for spurs well below the f32 noise floor
+    pub fn with_interpolation(mut self) -> Self {
+        self.interpolate = true;
+        self
+    }
+
+    /// Frequency actually generated, within sample_rate / 2^64 of the requested one
+    pub fn frequency_hz(&self) -> f64 {
+        let cycles = self.phase_step as f64 / 2f64.powi(64);
+        if cycles >= 0.5 { (cycles - 1.0) * self.sample_rate } else { cycles * self.sample_rate }
+    }
+
+    /// Current phase in cycles, 0 to 1
+    pub fn phase_cycles(&self) -> f64 {
+        self.phase as f64 / 2f64.powi(64)
+    }
 
-}
\ No newline at end of file
+    /// Change the frequency from the next sample on, the phase carries on without a jump
+    pub fn retune(&mut self, freq: f64) {
+        self.phase_step = phase_step(freq, self.sample_rate);
+    }
+
+    /// cos and -sin of the current phase, then advance by one sample
+    #[inline(always)]
+    pub fn next_value(&mut self) -> (f32, f32) {
+        let index = (self.phase >> INDEX_SHIFT) as usize;
+        let value = if self.interpolate {
+            let frac = ((self.phase >> (INDEX_SHIFT - FRACTION_BITS)) & FRACTION_MASK) as f32 * FRACTION_SCALE;
+            let next = (index + 1) & LUT_MASK;
+            (
+                self.lut_re[index] + frac * (self.lut_re[next] - self.lut_re[index]),
+                self.lut_im[index] + frac * (self.lut_im[next] - self.lut_im[index]),
+            )
+        } else {
+            (self.lut_re[index], self.lut_im[index])
+        };
+        self.phase = self.phase.wrapping_add(self.phase_step);
+        value
+    }
+
+    /// cos and -sin of the next 8 samples, for `mix_simd`
+    #[inline(always)]
+    pub fn next_simd(&mut self) -> (f32x8, f32x8) {
+        let mut cos = [0.0f32; 8];
+        let mut sin = [0.0f32; 8];
+        for (c, s) in cos.iter_mut().zip(sin.iter_mut()) {
+            (*c, *s) = self.next_value();
+        }
+        (f32x8::from_array(cos), f32x8::from_array(sin))
+    }
+
+    /// Fill `out` with the next samples of e^(-j phase)
+    pub fn fill(&mut self, out: &mut [Complex32]) {
+        for value in out.iter_mut() {
+            let (re, im) = self.next_value();
+            *value = Complex32::new(re, im);
+        }
+    }
+}
+
+/// Spurious-free dynamic range of `n` samples of `nco`, in dB: the carrier over the strongest
+/// other spectral line. No window is applied, so the NCO frequency should be a multiple of
+/// sample_rate / `n` for the leakage not to hide the spurs.
+pub fn measure_sfdr_db(nco: &mut NcoLut, n: usize) -> f32 {
+    let mut spectrum = vec![Complex32::new(0.0, 0.0); n];
+    nco.fill(&mut spectrum);
+    FftPlanner::new().plan_fft_forward(n).process(&mut spectrum);
+    let power: Vec<f32> = spectrum.iter().map(|x| x.norm_sqr()).collect();
+    let (carrier, carrier_power) = power
+        .iter()
+        .enumerate()
+        .fold((0, 0.0f32), |best, (k, p)| if *p > best.1 { (k, *p) } else { best });
+    let spur_power = power
+        .iter()
+        .enumerate()
+        .filter(|(k, _)| *k != carrier)
+        .fold(f32::MIN_POSITIVE, |max, (_, p)| max.max(*p));
+    10.0 * (carrier_power / spur_power).log10()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 4099 cycles in 65536 samples, 128.09375 LUT points per sample
+    const FS: f32 = 16_384_000.0;
+    const F_BIN: f32 = 4099.0 * FS / 65536.0;
+
+    #[test]
+    fn test_sfdr() {
+        let truncated = measure_sfdr_db(&mut NcoLut::new(F_BIN, FS), 65536);
+        let interpolated = measure_sfdr_db(&mut NcoLut::new(F_BIN, FS).with_interpolation(), 65536);
+        // About 6 dB per LUT index bit when truncating
+        assert!(truncated > 60.0 && truncated < 75.0, "truncated: {} dB", truncated);
+        assert!(interpolated > 100.0, "interpolated: {} dB", interpolated);
+    }
+
+    #[test]
+    fn test_no_drift_and_continuous_retune() {
+        // One second at 16.3676 MHz of the 4.1304 MHz IF is a whole number of cycles
+        let mut nco = NcoLut::new(4_130_400.0, 16_367_600.0);
+        assert!((nco.frequency_hz() - 4_130_400.0).abs() < 1e-6);
+        for _ in 0..16_367_600 {
+            nco.next_value();
+        }
+        let phase = nco.phase_cycles();
+        assert!(phase.min(1.0 - phase) < 1e-6, "phase {} cycles", phase);
+
+        // The phase steps by the new frequency from the sample after the retune on
+        let mut nco = NcoLut::new(1000.0, 1e6).with_interpolation();
+        let mut values = vec![Complex32::new(0.0, 0.0); 200];
+        nco.fill(&mut values[..100]);
+        nco.retune(-250_000.0);
+        nco.fill(&mut values[100..]);
+        let step = |n: usize| (values[n] * values[n - 1].conj()).arg() / (2.0 * PI);
+        assert!((step(100) + 1000.0 / 1e6).abs() < 1e-5);
+        assert!((step(101) - 0.25).abs() < 1e-5);
+        assert!((nco.frequency_hz() + 250_000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_mix_to_baseband() {
+        // A complex tone at the NCO frequency comes out at DC, keeping its phase
+        let mut nco = NcoLut::new(1_234_567.0, 8_184_000.0).with_interpolation();
+        for k in 0..100 {
+            let (cos, sin) = nco.next_simd();
+            let tone: [Complex32; 8] = std::array::from_fn(|i| {
+                let phase = 2.0 * std::f64::consts::PI * 1_234_567.0 * (8 * k + i) as f64 / 8_184_000.0 + 0.5;
+                Complex32::new(phase.cos() as f32, phase.sin() as f32)
+            });
+            let (re, im) = mix_simd(
+                f32x8::from_array(tone.map(|x| x.re)),
+                f32x8::from_array(tone.map(|x| x.im)),
+                cos,
+                sin,
+            );
+            for i in 0..8 {
+                assert!((Complex32::new(re[i], im[i]) - Complex32::from_polar(1.0, 0.5)).norm() < 1e-4);
+            }
+        }
+    }
+}
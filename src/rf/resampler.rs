@@ -0,0 +1,247 @@
+use num_complex::Complex32;
+use std::f64::consts::PI;
+
+/// Most filter phases of the bank, rates whose exact ratio needs more are approximated
+pub const MAX_PHASES: usize = 16384;
+
+/// Zero crossings of the prototype sinc on each side of its center, at the lower of the two rates
+const ZERO_CROSSINGS: usize = 8;
+
+/// Cutoff of the anti-alias filter relative to the lower Nyquist frequency
+const PASSBAND_RATIO: f64 = 0.9;
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// Closest fraction to `x` with a denominator of at most `max_den`, as (numerator, denominator)
+fn limit_denominator(x: f64, max_den: u64) -> (u64, u64) {
+    let (mut p0, mut q0, mut p1, mut q1) = (0u64, 1u64, 1u64, 0u64);
+    let mut v = x;
+    loop {
+        let a = v.floor() as u64;
+        let q2 = q0 + a * q1;
+        if q2 > max_den {
+            break;
+        }
+        (p0, q0, p1, q1) = (p1, q1, p0 + a * p1, q2);
+        let f = v - a as f64;
+        if f < 1e-12 {
+            return (p1, q1);
+        }
+        v = 1.0 / f;
+    }
+    // The last convergent or the best semiconvergent before it
+    let k = (max_den - q0) / q1;
+    let (sn, sd) = (p0 + k * p1, q0 + k * q1);
+    if (sn as f64 / sd as f64 - x).abs() < (p1 as f64 / q1 as f64 - x).abs() {
+        (sn, sd)
+    } else {
+        (p1, q1)
+    }
+}
+
+/// Interpolation and decimation factors (L, M) taking `fs_in` to `fs_out` = `fs_in` * L / M,
+/// exact for rates in whole Hz with L <= `MAX_PHASES`, else the closest ratio with such an L
+pub fn resampling_ratio(fs_in: f64, fs_out: f64) -> (usize, usize) {
+    let (rate_in, rate_out) = (fs_in.round() as u64, fs_out.round() as u64);
+    if rate_in as f64 == fs_in && rate_out as f64 == fs_out && rate_in > 0 && rate_out > 0 {
+        let g = gcd(rate_in, rate_out);
+        let (l, m) = (rate_out / g, rate_in / g);
+        if l <= MAX_PHASES as u64 {
+            return (l as usize, m as usize);
+        }
+    }
+    let (m, l) = limit_denominator(fs_in / fs_out, MAX_PHASES as u64);
+    (l as usize, m as usize)
+}
+
+/// Sample rate actually produced when resampling from `fs_in` to `fs_out`
+pub fn actual_output_rate(fs_in: f64, fs_out: f64) -> f64 {
+    let (l, m) = resampling_ratio(fs_in, fs_out);
+    fs_in * l as f64 / m as f64
+}
+
+/// Rational L/M resampler: a windowed-sinc anti-alias filter at L times the input rate,
+/// split in L phases so only the outputs kept are computed
+pub struct PolyphaseResampler {
+    pub interpolation: usize, // L
+    pub decimation: usize,    // M
+    pub taps_per_phase: usize,
+    bank: Vec<f32>, // Phase major, the taps of each phase reversed to run forward over the samples
+    history: Vec<Complex32>, // The last `taps_per_phase - 1` inputs, then the block being resampled
+    next_input: usize, // Newest input of the next output, relative to the next block
+    next_phase: usize, // Phase of the next output, in 1/L input samples after `next_input`
+    output: Vec<Complex32>,
+}
+
+impl PolyphaseResampler {
+    pub fn new(fs_in: f64, fs_out: f64) -> Self {
+        let (l, m) = resampling_ratio(fs_in, fs_out);
+        let taps_per_phase = (2 * ZERO_CROSSINGS * m).div_ceil(l).max(2 * ZERO_CROSSINGS);
+        let n_taps = taps_per_phase * l;
+
+        // Prototype at L * fs_in, cut below the lower of the two Nyquist frequencies
+        let cutoff = PASSBAND_RATIO * 0.5 / l.max(m) as f64; // Cycles per upsampled sample
+        let center = (n_taps - 1) as f64 / 2.0;
+        let mut prototype: Vec<f64> = (0..n_taps)
+            .map(|n| {
+                let t = n as f64 - center;
+                let sinc = if t == 0.0 { 1.0 } else { (2.0 * PI * cutoff * t).sin() / (2.0 * PI * cutoff * t) };
+                // Blackman window
+                let w = 2.0 * PI * n as f64 / (n_taps - 1) as f64;
+                2.0 * cutoff * sinc * (0.42 - 0.5 * w.cos() + 0.08 * (2.0 * w).cos())
+            })
+            .collect();
+        // Unity gain at DC for every phase on average
+        let sum: f64 = prototype.iter().sum();
+        prototype.iter_mut().for_each(|h| *h *= l as f64 / sum);
+
+        let mut bank = Vec::with_capacity(n_taps);
+        for phase in 0..l {
+            for k in (0..taps_per_phase).rev() {
+                bank.push(prototype[phase + k * l] as f32);
+            }
+        }
+
+        Self {
+            interpolation: l,
+            decimation: m,
+            taps_per_phase,
+            bank,
+            history: vec![Complex32::new(0.0, 0.0); taps_per_phase - 1],
+            next_input: 0,
+            next_phase: 0,
+            output: Vec::new(),
+        }
+    }
+
+    pub fn output_sample_rate(&self, fs_in: f64) -> f64 {
+        fs_in * self.interpolation as f64 / self.decimation as f64
+    }
+
+    /// Group delay of the filter in input samples
+    pub fn delay(&self) -> f64 {
+        (self.taps_per_phase * self.interpolation - 1) as f64 / (2.0 * self.interpolation as f64)
+    }
+
+    /// Number of outputs of the next block taken before its input sample `input_offset`
+    pub fn output_offset(&self, input_offset: usize) -> usize {
+        let (l, m) = (self.interpolation, self.decimation);
+        let first = self.next_input * l + self.next_phase; // In 1/L input samples
+        (input_offset * l).saturating_sub(first).div_ceil(m)
+    }
+
+    /// Input position of output `output_offset` of the next block, in input samples relative
+    /// to the block start, accounting for the filter delay
+    pub fn input_offset(&self, output_offset: usize) -> f64 {
+        let (l, m) = (self.interpolation as f64, self.decimation as f64);
+        self.next_input as f64 + (self.next_phase as f64 + output_offset as f64 * m) / l - self.delay()
+    }
+
+    /// Resample the next block of the stream, the outputs are valid until the next call
+    pub fn process(&mut self, input: &[Complex32]) -> &mut [Complex32] {
+        let (l, m, k) = (self.interpolation, self.decimation, self.taps_per_phase);
+        self.history.extend_from_slice(input);
+        self.output.clear();
+
+        while self.next_input < input.len() {
+            let taps = &self.bank[self.next_phase * k..(self.next_phase + 1) * k];
+            let samples = &self.history[self.next_input..self.next_input + k];
+            let (mut re, mut im) = (0.0f32, 0.0f32);
+            for (h, s) in taps.iter().zip(samples) {
+                re += h * s.re;
+                im += h * s.im;
+            }
+            self.output.push(Complex32::new(re, im));
+
+            self.next_phase += m;
+            self.next_input += self.next_phase / l;
+            self.next_phase %= l;
+        }
+
+        self.next_input -= input.len();
+        self.history.drain(..input.len());
+        &mut self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resampling_ratio() {
+        assert_eq!(resampling_ratio(10e6, 4.092e6), (1023, 2500));
+        assert_eq!(resampling_ratio(2.048e6, 2.048e6), (1, 1));
+        assert_eq!(resampling_ratio(16.3676e6, 4.092e6), (10230, 40919));
+        // 4000001 phases would be needed, the closest ratio is off by less than a ppm
+        let (l, m) = resampling_ratio(16.3676e6, 4_000_001.0);
+        assert!(l <= MAX_PHASES);
+        let fs_out = actual_output_rate(16.3676e6, 4_000_001.0);
+        assert!((fs_out - 4_000_001.0).abs() / 4e6 < 1e-6, "{} ({}/{})", fs_out, l, m);
+    }
+
+    #[test]
+    fn test_decimate_tone() {
+        let (fs_in, fs_out, f_tone) = (10e6, 4.092e6, 500e3);
+        let mut resampler = PolyphaseResampler::new(fs_in, fs_out);
+        let delay = resampler.delay();
+        let tone = |t: f64| {
+            let phase = 2.0 * PI * f_tone * t;
+            Complex32::new(phase.cos() as f32, phase.sin() as f32)
+        };
+
+        let mut n_in: usize = 0;
+        let mut n_out = 0;
+        for _ in 0..50 {
+            let block: Vec<Complex32> = (n_in..n_in + 2048).map(|n| tone(n as f64 / fs_in)).collect();
+            let first_out = n_out;
+            let input_start = resampler.input_offset(0);
+            let outputs = resampler.process(&block).to_vec();
+            assert!((input_start - (first_out as f64 * fs_in / fs_out - n_in as f64 - delay)).abs() < 1e-6);
+            for (j, y) in outputs.iter().enumerate() {
+                let n = first_out + j;
+                // Once the filter is filled, the tone comes out delayed
+                if n > 100 {
+                    let expected = tone(n as f64 / fs_out - delay / fs_in);
+                    assert!((y - expected).norm() < 1e-2, "output {}: {} vs {}", n, y, expected);
+                }
+            }
+            n_in += 2048;
+            n_out += outputs.len();
+        }
+        assert_eq!(n_out, (n_in * 1023).div_ceil(2500));
+    }
+
+    #[test]
+    fn test_rejects_alias() {
+        // 3 MHz is above the 2.046 MHz output Nyquist frequency
+        let (fs_in, fs_out) = (10e6, 4.092e6);
+        let mut resampler = PolyphaseResampler::new(fs_in, fs_out);
+        let block: Vec<Complex32> = (0..20480)
+            .map(|n| {
+                let phase = 2.0 * PI * 3e6 * n as f64 / fs_in;
+                Complex32::new(phase.cos() as f32, phase.sin() as f32)
+            })
+            .collect();
+        let outputs = resampler.process(&block);
+        let power: f32 = outputs[100..].iter().map(|y| y.norm_sqr()).sum::<f32>() / (outputs.len() - 100) as f32;
+        assert!(power < 1e-4, "alias power {}", power);
+    }
+
+    #[test]
+    fn test_output_offset() {
+        let mut resampler = PolyphaseResampler::new(10e6, 4e6); // L = 2, M = 5
+        assert_eq!((resampler.interpolation, resampler.decimation), (2, 5));
+        // Outputs at inputs 0, 2.5, 5, 7.5 ...
+        assert_eq!(resampler.output_offset(0), 0);
+        assert_eq!(resampler.output_offset(1), 1);
+        assert_eq!(resampler.output_offset(3), 2);
+        assert_eq!(resampler.output_offset(5), 2);
+        assert_eq!(resampler.process(&[Complex32::new(0.0, 0.0); 6]).len(), 3);
+        // Next ones at 7.5 and 10, 1.5 and 4 in the next block
+        assert_eq!(resampler.output_offset(1), 0);
+        assert_eq!(resampler.output_offset(2), 1);
+    }
+}
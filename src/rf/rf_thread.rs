@@ -1,60 +1,143 @@
 use crate::config::app_config::RfConfig;
+use crate::filter::design_channel_filter;
+use crate::rf::agc::BlockAgc;
 use crate::rf::frontend::DigitalFrontend;
+use crate::rf::interference::InterferenceMitigation;
 use crate::rf::samples_buffer::SampleComplex;
-use crate::utilities::multicast_ring_buffer::MulticastRingBuffer;
+use crate::sdr_store::sigmf::SigMfRecorder;
+use crate::sdr_store::sample_time::SampleTime;
+use crate::sdr_store::stream_monitor::{Discontinuity, StreamMonitor};
+use crate::utilities::multicast_ring_buffer::{MulticastRingBuffer, RingSample};
 use num_complex::Complex32;
 use ringbuf::traits::{Observer, Consumer};
 use ringbuf::HeapCons;
+use std::collections::VecDeque;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 static BLOCK_SIZE: usize = 2048;
 
-pub fn rf_thread(
+/// Runs the frontend on the raw samples until `stop` is set and the whole blocks left are processed
+pub fn rf_thread<T: RingSample>(
     rf_config: &RfConfig,
     input_sample_rate: f32,
     sdr_consumer: &mut HeapCons<SampleComplex>,
-    shared_ring_buffer: Arc<MulticastRingBuffer>,
+    shared_ring_buffer: Arc<MulticastRingBuffer<T>>,
+    mut recorder: Option<&mut SigMfRecorder>, // Records the frontend output
+    monitor: &StreamMonitor,
+    stop: &AtomicBool,
 ) {
     // let mut buf = create_samples_ring_buffer::<SampleComplex>(8 * BLOCK_SIZE);
     let mut block = [SampleComplex::new(0.0, 0.0); BLOCK_SIZE];
-    let mut frontend = DigitalFrontend::new(
-        rf_config.freq_if_hz.unwrap_or(0.0),
-        input_sample_rate,
-        rf_config.output_sample_rate_hz,
-    );
+    let f_if = rf_config.freq_if_hz.unwrap_or(0.0);
+    let mut frontend = if rf_config.real_input {
+        DigitalFrontend::new_real_if(f_if, input_sample_rate, rf_config.output_sample_rate_hz)
+    } else {
+        DigitalFrontend::new(f_if, input_sample_rate, rf_config.output_sample_rate_hz)
+    };
+    if rf_config.nco_interpolation {
+        frontend = frontend.with_nco_interpolation();
+    }
+    if rf_config.enable_agc {
+        let agc = BlockAgc::new(rf_config.agc, frontend.output_sample_rate()).with_telemetry(Arc::clone(&monitor.agc));
+        frontend = frontend.with_agc(agc);
+    }
+    if rf_config.blanking.is_some() || rf_config.notch.is_some() {
+        let interference = InterferenceMitigation::new(
+            rf_config.blanking,
+            rf_config.notch,
+            input_sample_rate,
+            Arc::clone(&monitor.interference),
+        );
+        frontend = frontend.with_interference(interference);
+    }
+    if let Some(filter_config) = &rf_config.channel_filter {
+        if filter_config.stopband_hz > input_sample_rate / 2.0 {
+            println!(
+                "Warning: Channel filter stopband at {} Hz is above the input Nyquist frequency",
+                filter_config.stopband_hz
+            );
+        }
+        frontend = frontend.with_channel_filter(design_channel_filter(filter_config, input_sample_rate));
+    }
+    let mut raw_index: usize = 0; // Index of the next raw sample, as counted by the SDR thread
+    let mut pending: VecDeque<Discontinuity> = VecDeque::new();
+    let mut pending_times: VecDeque<SampleTime> = VecDeque::new();
+    let mut raw_time: Option<SampleTime> = None; // Latest time reference of the raw stream
     loop {
         if sdr_consumer.occupied_len() < BLOCK_SIZE {
+            // The SDR thread is done, the last partial block is dropped
+            if stop.load(Ordering::Relaxed) {
+                if let Some(rec) = recorder
+                    && let Err(e) = rec.flush()
+                {
+                    println!("Warning: Frontend recording not flushed: {:?}", e);
+                }
+                return;
+            }
             // Not enought samples, wait a bit
+            monitor.stats.rf_underruns.fetch_add(1, Ordering::Relaxed);
             std::thread::sleep(std::time::Duration::from_millis(5));
             continue;
         }
 
-        let n_samples = sdr_consumer.pop_slice(&mut block);
-        if n_samples == 0 {
-            // Empty buffer
-            std::thread::sleep(std::time::Duration::from_millis(5));
-            continue;
-        } else {
-            if n_samples < BLOCK_SIZE {  // Hit the end of the buffer
-                sdr_consumer.pop_slice(&mut block[n_samples..]); // Then, fill it again
+        // Only this thread consumes, so the whole block is there
+        sdr_consumer.pop_slice(&mut block);
+
+        // Carry the breaks of the raw stream found in this block over to the output stream,
+        // before the samples after them are visible to the readers
+        pending.extend(monitor.take_discontinuities());
+        while let Some(d) = pending.front().copied() {
+            if d.sample_index >= raw_index + BLOCK_SIZE {
+                break;
+            }
+            pending.pop_front();
+            let output_index =
+                shared_ring_buffer.get_head() + frontend.output_offset(d.sample_index.saturating_sub(raw_index));
+            let _ = shared_ring_buffer.mark_discontinuity(Discontinuity { sample_index: output_index, ..d });
+        }
 
-                //Is it costly to do prepare_block and post_process_block? Can we improve it?
-                
-                let mut block_planar = prepare_block(&mut block, BLOCK_SIZE); // size: 2 * BLOCK_SIZE
-                frontend.process_block(&mut block_planar);
-                let block_complex = post_process_block(&mut block_planar, BLOCK_SIZE * 2);
-                let _ = shared_ring_buffer.write_samples(&block_complex);
-                // let mut written = 0;
-                // while written < BLOCK_SIZE * 2 {
-                //     let n = rf_prod.push_slice(&block_planar[written..BLOCK_SIZE * 2]);
-                //     written += n;
-                //     if n == 0 {
-                //         // Output buffer is full, wait a bit
-                //         std::thread::sleep(std::time::Duration::from_millis(5));
-                //     }
-                // }
+        // Every block gets the time of its first sample, references starting within it are added
+        pending_times.extend(monitor.take_times());
+        let mut block_times = Vec::new();
+        while let Some(time) = pending_times.front().copied()
+            && time.sample_index < raw_index + BLOCK_SIZE
+        {
+            pending_times.pop_front();
+            if time.sample_index <= raw_index {
+                raw_time = Some(time);
+            } else {
+                block_times.push(time);
             }
         }
+        let head = shared_ring_buffer.get_head();
+        for time in raw_time.map(|t| t.at(raw_index)).into_iter().chain(block_times) {
+            // The first output at or after the reference, shifted by the resampling filter delay
+            let input_offset = time.sample_index - raw_index;
+            let output_offset = frontend.output_offset(input_offset);
+            let shift = frontend.input_offset(output_offset) - input_offset as f64;
+            let _ = shared_ring_buffer.mark_time(SampleTime {
+                sample_index: head + output_offset,
+                time_ns: time.time_ns + (shift * 1e9 / time.sample_rate_hz).round() as i64,
+                sample_rate_hz: frontend.output_sample_rate() as f64,
+                ..time
+            });
+            raw_time = Some(time);
+        }
+        raw_index += BLOCK_SIZE;
+
+        //Is it costly to do prepare_block and post_process_block? Can we improve it?
+        let block_planar = prepare_block(&mut block, BLOCK_SIZE); // size: 2 * BLOCK_SIZE
+        frontend.process_block(block_planar);
+        let block_complex = post_process_block(block_planar, BLOCK_SIZE * 2);
+        let output = frontend.output_block(block_complex);
+        let _ = shared_ring_buffer.write_samples(output);
+        if let Some(rec) = recorder.as_deref_mut()
+            && let Err(e) = rec.write(output)
+        {
+            println!("Warning: Frontend recording stopped: {:?}", e);
+            recorder = None;
+        }
     }
 }
 
@@ -50,10 +50,10 @@ impl GnssRinexNavHeader {
 
 #[derive(Debug, Clone, Copy)]
 pub struct BroadCastOrbit1 {
-    iode: f64,
-    crs: f64,
-    delta_n: f64,
-    m0: f64,
+    pub iode: f64,
+    pub crs: f64,
+    pub delta_n: f64,
+    pub m0: f64,
 }
 
 impl BroadCastOrbit1 {
@@ -69,10 +69,10 @@ impl BroadCastOrbit1 {
 
 #[derive(Debug, Clone, Copy)]
 pub struct BroadCastOrbit2 {
-    cuc: f64,
-    e_eccentricity: f64,
-    cus: f64,
-    sqrt_a: f64,
+    pub cuc: f64,
+    pub e_eccentricity: f64,
+    pub cus: f64,
+    pub sqrt_a: f64,
 }
 
 impl BroadCastOrbit2 {
@@ -88,10 +88,10 @@ impl BroadCastOrbit2 {
 
 #[derive(Debug, Clone, Copy)]
 pub struct BroadCastOrbit3 {
-    toe: f64,
-    cic: f64,
-    omega0: f64,
-    cis: f64,
+    pub toe: f64,
+    pub cic: f64,
+    pub omega0: f64,
+    pub cis: f64,
 }
 
 impl BroadCastOrbit3 {
@@ -107,10 +107,10 @@ impl BroadCastOrbit3 {
 
 #[derive(Debug, Clone, Copy)]
 pub struct BroadCastOrbit4 {
-    i0: f64,
-    crc: f64,
-    omega: f64,
-    omega_dot: f64,
+    pub i0: f64,
+    pub crc: f64,
+    pub omega: f64,
+    pub omega_dot: f64,
 }
 
 impl BroadCastOrbit4 {
@@ -126,10 +126,10 @@ impl BroadCastOrbit4 {
 
 #[derive(Debug, Clone, Copy)]
 pub struct BroadCastOrbit5 {
-    idot: f64,
-    code_on_l2: f64,
-    gps_week: f64,
-    l2_p_flag: f64,
+    pub idot: f64,
+    pub code_on_l2: f64,
+    pub gps_week: f64,
+    pub l2_p_flag: f64,
 }
 
 impl BroadCastOrbit5 {
@@ -145,10 +145,10 @@ impl BroadCastOrbit5 {
 
 #[derive(Debug, Clone, Copy)]
 pub struct BroadCastOrbit6 {
-    sv_accuracy: f64,
-    sv_health: f64,
-    tgd: f64,
-    iodc: f64,
+    pub sv_accuracy: f64,
+    pub sv_health: f64,
+    pub tgd: f64,
+    pub iodc: f64,
 }
 
 impl BroadCastOrbit6 {
@@ -164,8 +164,8 @@ impl BroadCastOrbit6 {
 
 #[derive(Debug, Clone, Copy)]
 pub struct BroadCastOrbit7 {
-    t_transmission_message: f64,
-    fit_interval_hours: f64,
+    pub t_transmission_message: f64,
+    pub fit_interval_hours: f64,
 }
 
 impl BroadCastOrbit7 {
@@ -179,18 +179,18 @@ impl BroadCastOrbit7 {
 
 #[derive(Debug, Clone)]
 pub struct GnssRinexNavRecord {
-    satellite_sys_num: String,
-    time: DateTime<Utc>,
-    sv_clock_bias: f64,
-    sv_clock_drift: f64,
-    sv_clocl_drift_rate: f64,
-    orbit1: BroadCastOrbit1,
-    orbit2: BroadCastOrbit2,
-    orbit3: BroadCastOrbit3,
-    orbit4: BroadCastOrbit4,
-    orbit5: BroadCastOrbit5,
-    orbit6: BroadCastOrbit6,
-    orbit7: BroadCastOrbit7,
+    pub satellite_sys_num: String,
+    pub time: DateTime<Utc>, // Toc, in GPS time
+    pub sv_clock_bias: f64,
+    pub sv_clock_drift: f64,
+    pub sv_clocl_drift_rate: f64,
+    pub orbit1: BroadCastOrbit1,
+    pub orbit2: BroadCastOrbit2,
+    pub orbit3: BroadCastOrbit3,
+    pub orbit4: BroadCastOrbit4,
+    pub orbit5: BroadCastOrbit5,
+    pub orbit6: BroadCastOrbit6,
+    pub orbit7: BroadCastOrbit7,
 }
 
 impl GnssRinexNavRecord {
@@ -211,20 +211,6 @@ impl GnssRinexNavRecord {
         }
     }
 }
-pub struct GpsRinexNavData {
-    rinex_header: GnssRinexNavHeader,
-    rinex_data_record: Vec<GnssRinexNavRecord>,
-}
-
-impl GpsRinexNavData {
-    fn new() -> Self {
-        Self {
-            rinex_header: GnssRinexNavHeader::new(),
-            rinex_data_record: Vec::new(),
-        }
-    }
-}
-
 pub fn get_sats_from_rinex(
     file_name: &str,
     t_now_utc: DateTime<Utc>,
@@ -266,11 +252,11 @@ pub fn get_sats_from_rinex(
                 "The GNSS RINEX navigation data is expected".into(),
             )));
         }
-        if let Some(content) = check_header_option_fields(&line.trim(), &mut rinex_header) {
-            if content == "END OF HEADER" {
-                line.clear();
-                break;
-            }
+        if let Some(content) = check_header_option_fields(line.trim(), &mut rinex_header)
+            && content == "END OF HEADER"
+        {
+            line.clear();
+            break;
         }
         line.clear();
     }
@@ -278,10 +264,8 @@ pub fn get_sats_from_rinex(
     let mut n = 0;
     let ephemeris_valid_period = Duration::seconds(4 * 3600);
     let mut t_diff: HashMap<String, Duration> = HashMap::new();
-    //let mut rinex_nav_data = GpsRinexNavData::new();
     let mut rinex_record = GnssRinexNavRecord::new();
     let mut sats_record: HashMap<String, GnssRinexNavRecord> = HashMap::new();
-    //rinex_nav_data.rinex_header = rinex_header;
 
     loop {
         line_len = reader.read_line(&mut line)?;
@@ -294,9 +278,7 @@ pub fn get_sats_from_rinex(
         let _ = get_rinex_nav_record(n % 8, &line, &mut rinex_record);
 
         if n % 8 == 7 {
-            //rinex_nav_data.rinex_data_record.push(rinex_record.clone());
-
-            let td = (rinex_record.time.time() - t_now_utc.time()).abs();
+            let td = (rinex_record.time - t_now_utc).abs();
             if td < ephemeris_valid_period
                 && (!t_diff.contains_key(&rinex_record.satellite_sys_num)
                     || td < t_diff[&rinex_record.satellite_sys_num])
@@ -349,7 +331,7 @@ fn get_rinex_nav_record<'a>(
     content: &'a str,
     r_record: &mut GnssRinexNavRecord,
 ) -> Option<&'a str> {
-    let mut c = content;
+    let c = content;
     let parse_from_str = NaiveDateTime::parse_from_str;
     match n {
         0 => {
@@ -1,70 +1,189 @@
-use soapysdr::{Args, Device};
-use serde_json::Value;
 use rustfft::num_complex::Complex32;
-use crate::sdr_store::sdr_wrapper::{SdrInfo, SdrConfig, SdrDeviceWrapper, SdrError};
-use crate::sdr_store::rtl_sdr::RtlSdr;
+use crate::sdr_store::backend::SdrBackend;
+use crate::sdr_store::sample_source::StreamEvent;
+use crate::sdr_store::sdr_wrapper::{SdrError, SdrInfo};
+use crate::sdr_store::device_config::DeviceProfile;
+use crate::sdr_store::profiled_sdr::ProfiledSdr;
+use std::collections::{HashMap, VecDeque};
 
+pub const MOCK_MTU: usize = 1024;
+
+/// In-memory stand-in for a SoapySDR device, it remembers the settings and streams zeros
 pub struct MockDevice {
-    pub device: Option<Device>,
-    pub sdr_info: SdrInfo,
-    pub sdr_config: SdrConfig,
+    pub driver: String,
+    pub frequency_hz: f64,
+    pub sample_rate_hz: f64,
+    pub bandwidth_hz: f64,
+    pub gain_db: f64,
+    pub automatic_gain: bool,
+    pub gain_list: Vec<String>,
+    pub gain_elements: HashMap<String, f64>,
+    pub antenna_list: Vec<String>,
+    pub antenna: Option<String>,
+    pub settings: HashMap<String, String>,
+    pub clock_source: Option<String>,
+    pub rx_active: bool,
+    pub events: VecDeque<StreamEvent>,
+    pub next_time_ns: Option<i64>, // Hardware time of the next sample read, None without hardware time
+    pub rx_time_ns: Option<i64>,
 }
 
-impl SdrDeviceWrapper for MockDevice {
-    fn device(&self) -> Result<&Device, SdrError> {
-        self.device.as_ref().ok_or(SdrError::DeviceNotFound("Device not found".into()))
+impl SdrBackend for MockDevice {
+    fn driver_key(&self) -> Result<String, SdrError> {
+        Ok(self.driver.clone())
+    }
+
+    fn frequency(&self, _channel: usize) -> Result<f64, SdrError> {
+        Ok(self.frequency_hz)
+    }
+
+    fn set_frequency(&mut self, _channel: usize, frequency_hz: f64) -> Result<(), SdrError> {
+        self.frequency_hz = frequency_hz;
+        Ok(())
+    }
+
+    fn sample_rate(&self, _channel: usize) -> Result<f64, SdrError> {
+        Ok(self.sample_rate_hz)
+    }
+
+    fn set_sample_rate(&mut self, _channel: usize, sample_rate_hz: f64) -> Result<(), SdrError> {
+        self.sample_rate_hz = sample_rate_hz;
+        Ok(())
+    }
+
+    fn set_bandwidth(&mut self, _channel: usize, bandwidth_hz: f64) -> Result<(), SdrError> {
+        self.bandwidth_hz = bandwidth_hz;
+        Ok(())
+    }
+
+    fn gain(&self, _channel: usize) -> Result<f64, SdrError> {
+        Ok(self.gain_db)
+    }
+
+    fn set_gain(&mut self, _channel: usize, gain_db: f64) -> Result<(), SdrError> {
+        self.gain_db = gain_db;
+        Ok(())
+    }
+
+    fn set_gain_mode(&mut self, _channel: usize, automatic: bool) -> Result<(), SdrError> {
+        self.automatic_gain = automatic;
+        Ok(())
+    }
+
+    fn list_gains(&self, _channel: usize) -> Result<Vec<String>, SdrError> {
+        Ok(self.gain_list.clone())
+    }
+
+    fn set_gain_element(&mut self, _channel: usize, name: &str, gain_db: f64) -> Result<(), SdrError> {
+        if !self.gain_list.iter().any(|g| g == name) {
+            return Err(SdrError::ConfigError(format!("Unknown gain element: {}", name)));
+        }
+        self.gain_elements.insert(name.to_string(), gain_db);
+        self.gain_db = self.gain_elements.values().sum();
+        Ok(())
+    }
+
+    fn write_setting(&mut self, key: &str, value: &str) -> Result<(), SdrError> {
+        self.settings.insert(key.to_string(), value.to_string());
+        Ok(())
     }
 
-    fn device_mut(&mut self) -> Result<&mut Device, SdrError> {
-        self.device.as_mut().ok_or(SdrError::DeviceNotFound("Device not found".into()))
+    fn set_clock_source(&mut self, source: &str) -> Result<(), SdrError> {
+        self.clock_source = Some(source.to_string());
+        Ok(())
     }
 
-    fn get_config(&self) -> SdrConfig {
-        self.sdr_config.clone()
+    fn antennas(&self, _channel: usize) -> Result<Vec<String>, SdrError> {
+        Ok(self.antenna_list.clone())
     }
 
-    #[allow(unused_variables)]
-    fn config(&mut self, config: Value) -> Result<(), SdrError> {
+    fn set_antenna(&mut self, _channel: usize, antenna: &str) -> Result<(), SdrError> {
+        if !self.antenna_list.iter().any(|a| a == antenna) {
+            return Err(SdrError::ConfigError(format!("Unknown antenna: {}", antenna)));
+        }
+        self.antenna = Some(antenna.to_string());
         Ok(())
     }
 
-    #[allow(unused_variables)]
-    fn read_samples(&mut self, buf: &mut [&mut [Complex32]], timeout_us: i64) -> Result<usize, SdrError> {
-        Ok(buf.len())
+    fn activate_rx(&mut self, _channels: &[usize], _time_ns: Option<i64>) -> Result<(), SdrError> {
+        self.rx_active = true;
+        Ok(())
     }
 
-    #[allow(unused_variables)]
-    fn transmit_samples(&self, buf: &mut [&mut [Complex32]]) -> Result<(), SdrError> {
+    fn deactivate_rx(&mut self, _time_ns: Option<i64>) -> Result<(), SdrError> {
+        self.rx_active = false;
         Ok(())
     }
 
-    fn get_rx_stream_mute(&mut self) -> Option<&mut soapysdr::RxStream<Complex32>> {
-        None
+    fn rx_mtu(&self) -> Result<usize, SdrError> {
+        if self.rx_active {
+            Ok(MOCK_MTU)
+        } else {
+            Err(SdrError::StreamError("RX stream not initialized".to_string()))
+        }
+    }
+
+    fn read_rx(&mut self, buf: &mut [Complex32], _timeout_us: i64) -> Result<usize, SdrError> {
+        if !self.rx_active {
+            return Err(SdrError::StreamError("RX stream not initialized".to_string()));
+        }
+        let n = buf.len().min(MOCK_MTU);
+        buf[..n].fill(Complex32::new(0.0, 0.0));
+        self.rx_time_ns = self.next_time_ns;
+        if let Some(time_ns) = &mut self.next_time_ns {
+            *time_ns += (n as f64 * 1e9 / self.sample_rate_hz).round() as i64;
+        }
+        Ok(n)
+    }
+
+    fn rx_time_ns(&self) -> Option<i64> {
+        self.rx_time_ns
+    }
+
+    fn poll_event(&mut self) -> Option<StreamEvent> {
+        self.events.pop_front()
     }
 }
 
 impl MockDevice {
-    fn new(args: Args) -> Result<Self, SdrError> {
-        let dev = Device::new(Args::from("")).map_err(|e| SdrError::DeviceError(e.to_string()))?;
-        let info = Self::map_args_to_info(args)?;
-        Ok(Self {
-            device: Some(dev),
-            sdr_info: info,
-            sdr_config: SdrConfig::default(),
-        })
+    /// Gain stages and antennas follow what the SoapySDR module of `driver` reports
+    pub fn new(driver: &str) -> Self {
+        let (gains, antennas): (&[&str], &[&str]) = match driver {
+            "rtlsdr" => (&["TUNER"], &["RX"]),
+            "hackrf" => (&["AMP", "LNA", "VGA"], &["TX/RX"]),
+            "airspy" => (&["LNA", "MIX", "VGA"], &["RX"]),
+            "lime" => (&["LNA", "TIA", "PGA"], &["NONE", "LNAH", "LNAL", "LNAW"]),
+            "plutosdr" => (&["PGA"], &["A_BALANCED"]),
+            "bladerf" => (&["full"], &["RX1", "RX2"]),
+            "uhd" => (&["PGA0"], &["TX/RX", "RX2"]),
+            _ => (&[], &["RX"]),
+        };
+        Self {
+            driver: driver.to_string(),
+            frequency_hz: 0.0,
+            sample_rate_hz: 0.0,
+            bandwidth_hz: 0.0,
+            gain_db: 0.0,
+            automatic_gain: false,
+            gain_list: gains.iter().map(|g| g.to_string()).collect(),
+            gain_elements: HashMap::new(),
+            antenna_list: antennas.iter().map(|a| a.to_string()).collect(),
+            antenna: None,
+            settings: HashMap::new(),
+            clock_source: None,
+            rx_active: false,
+            events: VecDeque::new(),
+            next_time_ns: None,
+            rx_time_ns: None,
+        }
     }
 }
 
-impl RtlSdr<MockDevice> {
-    pub fn new(args: Args) -> Result<Self, SdrError> {
-        let dev = MockDevice::new(Args::from("")).map_err(|e| SdrError::DeviceError(e.to_string()))?;
-        let info = MockDevice::map_args_to_info(args)?;
-        Ok(Self {
-            device: dev,
-            rx_stream: None,
-            tx_stream: None,
-            sdr_info: info,
-            sdr_config: SdrConfig::default(),
-        })
-    }
-}
\ No newline at end of file
+impl ProfiledSdr<MockDevice> {
+    /// The mocked driver is the `driver` of `args`
+    pub fn new(args: &str, profile: &'static DeviceProfile) -> Result<Self, SdrError> {
+        let info = SdrInfo::from_args_str(args);
+        let backend = MockDevice::new(info.driver.as_deref().unwrap_or_default());
+        Ok(Self::with_backend(backend, profile, info))
+    }
+}
@@ -0,0 +1,47 @@
+use crate::sdr_store::device_config::{DeviceProfile, GainStageLimits};
+
+/// Airspy R2 / Mini: LNA, MIX and VGA stages, each one a 0 to 15 gain index.
+/// The bias-tee is the `biastee` setting, the reference clock is fixed.
+pub const AIRSPY_PROFILE: DeviceProfile = DeviceProfile {
+    name: "Airspy",
+    bias_tee_setting: Some("biastee"),
+    default_antenna: None,
+    gain_limits: &[
+        GainStageLimits { name: "LNA", min_db: 0.0, max_db: 15.0 },
+        GainStageLimits { name: "MIX", min_db: 0.0, max_db: 15.0 },
+        GainStageLimits { name: "VGA", min_db: 0.0, max_db: 15.0 },
+    ],
+    clock_sources: &[],
+    validate: None,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdr_mock::device_mock::MockDevice;
+    use crate::sdr_store::profiled_sdr::ProfiledSdr;
+    use crate::sdr_store::sdr_wrapper::{SdrDeviceWrapper, SdrError};
+
+    #[test]
+    fn test_airspy_gain_stages_and_bias_tee() {
+        let mut airspy = ProfiledSdr::<MockDevice>::new("driver=airspy, label=AirSpy [644064dc2b3b8a5f], serial=644064dc2b3b8a5f", &AIRSPY_PROFILE)
+            .expect("Failed to mock an Airspy device");
+        assert_eq!(airspy.sdr_info.serial_number, Some("644064dc2b3b8a5f".to_string()));
+
+        airspy
+            .config(serde_json::json!({
+                "center_frequency": 1575.42e6,
+                "sample_rate": 6e6,
+                "bandwidth": 6e6,
+                "gain_stages": { "LNA": 14.0, "MIX": 12.0, "VGA": 10.0 },
+                "bias_tee": true,
+            }))
+            .expect("Failed to configure the mocked Airspy device");
+        assert_eq!(airspy.backend.gain_elements["MIX"], 12.0);
+        assert_eq!(airspy.backend.settings["biastee"], "true");
+        assert_eq!(airspy.sdr_config.bias_tee, Some(true));
+
+        let config = serde_json::json!({ "bandwidth": 6e6, "gain_stages": { "VGA": 16.0 } });
+        assert!(matches!(airspy.config(config), Err(SdrError::ConfigError(_))));
+    }
+}
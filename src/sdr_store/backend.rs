@@ -0,0 +1,71 @@
+use crate::sdr_store::sample_source::StreamEvent;
+use crate::sdr_store::sdr_wrapper::SdrError;
+use num_complex::Complex32;
+
+/// The receive side of a SoapySDR-like device API, which the drivers in `sdr_store` are built on.
+/// `SoapyBackend` talks to real hardware, the tests use an in-memory stub.
+pub trait SdrBackend {
+    fn driver_key(&self) -> Result<String, SdrError>;
+
+    /// Returns the down-conversion frequency in Hz.
+    fn frequency(&self, channel: usize) -> Result<f64, SdrError>;
+
+    /// Set the down-conversion frequency in Hz.
+    fn set_frequency(&mut self, channel: usize, frequency_hz: f64) -> Result<(), SdrError>;
+
+    /// Get the baseband sample rate of the chain in samples per second.
+    fn sample_rate(&self, channel: usize) -> Result<f64, SdrError>;
+
+    /// Set the baseband sample rate of the chain in samples per second.
+    fn set_sample_rate(&mut self, channel: usize, sample_rate_hz: f64) -> Result<(), SdrError>;
+
+    /// Set the baseband filter width of the chain in Hz
+    fn set_bandwidth(&mut self, channel: usize, bandwidth_hz: f64) -> Result<(), SdrError>;
+
+    /// Get the overall value of the gain elements in a chain in dB.
+    fn gain(&self, channel: usize) -> Result<f64, SdrError>;
+
+    /// Set the overall amplification in a chain.
+    fn set_gain(&mut self, channel: usize, gain_db: f64) -> Result<(), SdrError>;
+
+    /// Enable or disable automatic gain control.
+    fn set_gain_mode(&mut self, channel: usize, automatic: bool) -> Result<(), SdrError>;
+
+    /// List the names of the amplification elements in a chain, e.g. LNA, VGA
+    fn list_gains(&self, channel: usize) -> Result<Vec<String>, SdrError>;
+
+    /// Set the value of an individual amplification element in a chain in dB
+    fn set_gain_element(&mut self, channel: usize, name: &str, gain_db: f64) -> Result<(), SdrError>;
+
+    /// Write a driver specific setting, e.g. the bias-tee
+    fn write_setting(&mut self, key: &str, value: &str) -> Result<(), SdrError>;
+
+    /// Select the reference clock, e.g. "internal", "external"
+    fn set_clock_source(&mut self, source: &str) -> Result<(), SdrError>;
+
+    /// List available antennas for the channel.
+    fn antennas(&self, channel: usize) -> Result<Vec<String>, SdrError>;
+
+    /// Set the selected antenna for the channel.
+    fn set_antenna(&mut self, channel: usize, antenna: &str) -> Result<(), SdrError>;
+
+    /// Create and activate the RX stream
+    fn activate_rx(&mut self, channels: &[usize], time_ns: Option<i64>) -> Result<(), SdrError>;
+
+    /// Deactivate and drop the RX stream
+    fn deactivate_rx(&mut self, time_ns: Option<i64>) -> Result<(), SdrError>;
+
+    fn rx_mtu(&self) -> Result<usize, SdrError>;
+
+    /// Read samples of the first RX channel, a timeout returns zero samples
+    fn read_rx(&mut self, buf: &mut [Complex32], timeout_us: i64) -> Result<usize, SdrError>;
+
+    /// Device time of the first sample of the last `read_rx`, None without hardware time
+    fn rx_time_ns(&self) -> Option<i64> {
+        None
+    }
+
+    fn poll_event(&mut self) -> Option<StreamEvent> {
+        None
+    }
+}
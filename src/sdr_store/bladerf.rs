@@ -0,0 +1,46 @@
+use crate::sdr_store::device_config::{DeviceProfile, GainStageLimits};
+
+/// bladeRF: LNA, VGA1 and VGA2 on the bladeRF 1, a single "full" stage on the bladeRF 2.0,
+/// the stages are taken from the device. The bladeRF 2.0 bias-tee is `biastee_rx`.
+pub const BLADERF_PROFILE: DeviceProfile = DeviceProfile {
+    name: "bladeRF",
+    bias_tee_setting: Some("biastee_rx"),
+    default_antenna: None,
+    gain_limits: &[
+        GainStageLimits { name: "LNA", min_db: 0.0, max_db: 6.0 },
+        GainStageLimits { name: "VGA1", min_db: 5.0, max_db: 30.0 },
+        GainStageLimits { name: "VGA2", min_db: 0.0, max_db: 30.0 },
+        GainStageLimits { name: "full", min_db: -15.0, max_db: 60.0 },
+    ],
+    clock_sources: &["internal", "external"],
+    validate: None,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdr_mock::device_mock::MockDevice;
+    use crate::sdr_store::profiled_sdr::ProfiledSdr;
+    use crate::sdr_store::sdr_wrapper::SdrDeviceWrapper;
+
+    #[test]
+    fn test_bladerf_config() {
+        let mut bladerf = ProfiledSdr::<MockDevice>::new("driver=bladerf, backend=libusb, device=2-3, instance=0, label=BladeRF #0 [ANY], serial=0abc", &BLADERF_PROFILE)
+            .expect("Failed to mock a bladeRF device");
+
+        bladerf
+            .config(serde_json::json!({
+                "center_frequency": 1575.42e6,
+                "sample_rate": 4e6,
+                "bandwidth": 2.5e6,
+                "gain_stages": { "full": 45.0 },
+                "bias_tee": true,
+                "clock_source": "external",
+            }))
+            .expect("Failed to configure the mocked bladeRF device");
+        assert_eq!(bladerf.backend.settings["biastee_rx"], "true");
+        assert_eq!(bladerf.backend.clock_source, Some("external".to_string()));
+        assert_eq!(bladerf.sdr_config.clock_source, Some("external".to_string()));
+        assert_eq!(bladerf.sdr_config.antennas, Some(vec!["RX1".to_string()]));
+    }
+}
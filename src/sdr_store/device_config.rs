@@ -0,0 +1,175 @@
+use crate::sdr_store::backend::SdrBackend;
+use crate::sdr_store::sdr_wrapper::{SdrConfig, SdrError};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::thread;
+use std::time;
+
+/// Time given to the hardware to settle after each setting
+const SETTLE_TIME_MS: u64 = 100;
+
+/// Gain range of a named gain stage in dB
+pub struct GainStageLimits {
+    pub name: &'static str,
+    pub min_db: f64,
+    pub max_db: f64,
+}
+
+/// Device specific checks of a config, before anything is applied
+pub type ConfigValidator = fn(&Value) -> Result<(), SdrError>;
+
+/// What differs between the SoapySDR modules of the supported devices
+pub struct DeviceProfile {
+    pub name: &'static str,
+    pub bias_tee_setting: Option<&'static str>, // Soapy setting key powering the antenna port
+    pub default_antenna: Option<&'static str>, // Antenna used when none is configured, else the first listed
+    pub gain_limits: &'static [GainStageLimits],
+    pub clock_sources: &'static [&'static str], // Empty when the reference clock can't be selected
+    pub validate: Option<ConfigValidator>,
+}
+
+fn settle() {
+    thread::sleep(time::Duration::from_millis(SETTLE_TIME_MS));
+}
+
+/// Tuning, sample rate, overall gain and gain mode, common to all devices
+pub fn apply_stream_config<B: SdrBackend>(
+    backend: &mut B,
+    sdr_config: &mut SdrConfig,
+    config: &Value,
+) -> Result<(), SdrError> {
+    if let Some(c_freq) = config.get("center_frequency").and_then(Value::as_f64) {
+        backend.set_frequency(0, c_freq)?;
+        sdr_config.center_frequency_hz = c_freq as f32;
+        settle();
+    }
+
+    if let Some(s_rate) = config.get("sample_rate").and_then(Value::as_f64) {
+        backend.set_sample_rate(0, s_rate)?;
+        sdr_config.sample_rate_hz = s_rate as f32;
+        settle();
+    }
+
+    if let Some(gain) = config.get("gain").and_then(Value::as_f64) {
+        backend.set_gain(0, gain)?;
+        sdr_config.gain_db = gain as f32;
+        settle();
+    }
+
+    sdr_config.frequency_correction = Some(
+        config
+            .get("frequency_correction")
+            .and_then(Value::as_f64)
+            .unwrap_or_default() as f32,
+    );
+
+    if let Some(bandwidth) = config.get("bandwidth").and_then(Value::as_f64) {
+        backend.set_bandwidth(0, bandwidth)?;
+        sdr_config.bandwidth_hz = bandwidth as f32;
+        settle();
+    } else {
+        return Err(SdrError::OtherError(
+            "Bandwidth must be specified for SDR device".to_string(),
+        ));
+    }
+
+    let gain_mode = config
+        .get("gain_mode")
+        .and_then(Value::as_str)
+        .unwrap_or("manual");
+    backend.set_gain_mode(0, gain_mode == "agc")?;
+    sdr_config.gain_mode = Some(gain_mode.to_string());
+    settle();
+
+    if let Some(pps_enabled) = config.get("pps_enabled").and_then(Value::as_bool) {
+        sdr_config.pps_enabled = Some(pps_enabled);
+    }
+
+    Ok(())
+}
+
+/// Antenna port, per-stage gains, bias-tee and reference clock, checked against the device profile
+pub fn apply_frontend_config<B: SdrBackend>(
+    backend: &mut B,
+    sdr_config: &mut SdrConfig,
+    profile: &DeviceProfile,
+    config: &Value,
+) -> Result<(), SdrError> {
+    let available = backend.antennas(0).unwrap_or_default();
+    let requested = config
+        .get("antennas")
+        .and_then(|a| a.get(0))
+        .and_then(Value::as_str)
+        .or(profile.default_antenna)
+        .or(available.first().map(String::as_str))
+        .map(str::to_string);
+    if let Some(antenna) = requested {
+        if !available.contains(&antenna) {
+            return Err(SdrError::ConfigError(format!(
+                "{} has no antenna {}, available: {:?}",
+                profile.name, antenna, available
+            )));
+        }
+        backend.set_antenna(0, &antenna)?;
+        sdr_config.antennas = Some(vec![antenna]);
+        settle();
+    }
+
+    if let Some(stages) = config.get("gain_stages").and_then(Value::as_object) {
+        let stage_names = backend.list_gains(0)?;
+        let mut gain_stages = HashMap::new();
+        for (name, gain) in stages {
+            let gain = gain.as_f64().ok_or(SdrError::ConfigError(format!(
+                "Gain of stage {} must be a number",
+                name
+            )))?;
+            if !stage_names.contains(name) {
+                return Err(SdrError::ConfigError(format!(
+                    "{} has no gain stage {}, available: {:?}",
+                    profile.name, name, stage_names
+                )));
+            }
+            if let Some(limits) = profile.gain_limits.iter().find(|l| l.name == name)
+                && (gain < limits.min_db || gain > limits.max_db)
+            {
+                return Err(SdrError::ConfigError(format!(
+                    "{} gain {} dB out of range [{}, {}]",
+                    name, gain, limits.min_db, limits.max_db
+                )));
+            }
+            backend.set_gain_element(0, name, gain)?;
+            gain_stages.insert(name.clone(), gain as f32);
+            settle();
+        }
+        sdr_config.gain_db = backend.gain(0)? as f32;
+        sdr_config.gain_stages = Some(gain_stages);
+    }
+
+    if let Some(bias_tee) = config.get("bias_tee").and_then(Value::as_bool) {
+        match profile.bias_tee_setting {
+            Some(key) => {
+                backend.write_setting(key, if bias_tee { "true" } else { "false" })?;
+                settle();
+            }
+            None if bias_tee => {
+                return Err(SdrError::ConfigError(format!("{} has no bias-tee", profile.name)));
+            }
+            None => {}
+        }
+        sdr_config.bias_tee = Some(bias_tee);
+    }
+
+    if let Some(clock_source) = config.get("clock_source").and_then(Value::as_str) {
+        if !profile.clock_sources.contains(&clock_source) {
+            return Err(SdrError::ConfigError(format!(
+                "{} can't use clock source {}, available: {:?}",
+                profile.name, clock_source, profile.clock_sources
+            )));
+        }
+        backend.set_clock_source(clock_source)?;
+        sdr_config.clock_source = Some(clock_source.to_string());
+        settle();
+    }
+
+    Ok(())
+}
@@ -0,0 +1,260 @@
+use crate::sdr_store::sdr_wrapper::{SdrError, SdrInfo};
+
+/// Which of the enumerated devices of a driver to open
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceSelector {
+    First,                        // "rtlsdr", the only or first device
+    Index(usize),                 // "rtlsdr:index=1", position in the enumeration, i.e. the USB index
+    Serial(String),               // "rtlsdr:serial=00000001"
+    Label(String),                // "rtlsdr:label=Generic RTL2832U", part of the label
+    Args(Vec<(String, String)>),  // "driver=rtlsdr, manufacturer=Realtek", every pair must match
+    Text(String),                 // "rtlsdr:1", a serial number, else an index, else part of the label
+}
+
+/// `AppConfig.device` split into the device name and the selector
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceSpec {
+    pub device_name: String,
+    pub selector: DeviceSelector,
+}
+
+/// Split a SoapySDR args string into trimmed key/value pairs
+pub fn parse_args_pairs(args: &str) -> Vec<(String, String)> {
+    args.split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+        .collect()
+}
+
+impl DeviceSpec {
+    /// Accepted forms: "rtlsdr", "rtlsdr:index=1", "rtlsdr:serial=00000001", "rtlsdr:label=...",
+    /// "rtlsdr:<serial, index or label>" and raw args "driver=rtlsdr, serial=00000001"
+    pub fn parse(device: &str) -> Result<Self, SdrError> {
+        let device = device.trim();
+        // Raw args may have colons in their values, e.g. "driver=plutosdr, uri=ip:192.168.2.1"
+        let is_args = device
+            .find('=')
+            .is_some_and(|eq| device.find(':').is_none_or(|colon| eq < colon));
+        if is_args {
+            let pairs = parse_args_pairs(device);
+            let device_name = pairs
+                .iter()
+                .find(|(k, _)| k == "driver")
+                .map(|(_, v)| v.clone())
+                .ok_or(SdrError::ConfigError(format!(
+                    "Device args \"{}\" have no driver",
+                    device
+                )))?;
+            let pairs = pairs.into_iter().filter(|(k, _)| k != "driver").collect();
+            return Ok(Self { device_name, selector: DeviceSelector::Args(pairs) });
+        }
+
+        let (device_name, rest) = match device.split_once(':') {
+            Some((name, rest)) => (name.trim().to_string(), rest.trim()),
+            None => (device.to_string(), ""),
+        };
+
+        let selector = if rest.is_empty() {
+            DeviceSelector::First
+        } else if let Some(index) = rest.strip_prefix("index=") {
+            DeviceSelector::Index(index.trim().parse().map_err(|_| {
+                SdrError::ConfigError(format!("Invalid device index in \"{}\"", device))
+            })?)
+        } else if let Some(serial) = rest.strip_prefix("serial=") {
+            DeviceSelector::Serial(serial.trim().to_string())
+        } else if let Some(label) = rest.strip_prefix("label=") {
+            DeviceSelector::Label(label.trim().to_string())
+        } else if rest.contains('=') {
+            DeviceSelector::Args(parse_args_pairs(rest))
+        } else {
+            DeviceSelector::Text(rest.to_string())
+        };
+
+        Ok(Self { device_name, selector })
+    }
+
+    fn matches(&self, info: &SdrInfo) -> bool {
+        match &self.selector {
+            DeviceSelector::First | DeviceSelector::Index(_) => true,
+            DeviceSelector::Serial(serial) => info.serial_number.as_ref() == Some(serial),
+            DeviceSelector::Label(label) | DeviceSelector::Text(label) => {
+                info.label.as_ref().is_some_and(|l| l.contains(label.as_str()))
+            }
+            DeviceSelector::Args(pairs) => {
+                let device_pairs = parse_args_pairs(info.long_args.as_deref().unwrap_or(""));
+                pairs.iter().all(|pair| device_pairs.contains(pair))
+            }
+        }
+    }
+
+    /// Pick one of the enumerated devices, returns its position in `candidates`
+    pub fn select(&self, candidates: &[SdrInfo]) -> Result<usize, SdrError> {
+        let index = match &self.selector {
+            DeviceSelector::Index(index) => Some(*index),
+            DeviceSelector::Text(text) => {
+                // RTL-SDR serials are numbers too, so they win over the index
+                if let Some(i) = candidates
+                    .iter()
+                    .position(|info| info.serial_number.as_ref() == Some(text))
+                {
+                    return Ok(i);
+                }
+                text.parse::<usize>().ok()
+            }
+            _ => None,
+        };
+        if let Some(index) = index {
+            if index < candidates.len() {
+                return Ok(index);
+            }
+            return Err(self.not_found(candidates));
+        }
+
+        let matching: Vec<usize> = candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, info)| self.matches(info))
+            .map(|(i, _)| i)
+            .collect();
+
+        match matching.as_slice() {
+            [] => Err(self.not_found(candidates)),
+            [index] => Ok(*index),
+            [first, ..] if self.selector == DeviceSelector::First => {
+                println!(
+                    "Warning: Multiple devices found for driver: {}. Using the first one.",
+                    self.device_name
+                );
+                Ok(*first)
+            }
+            _ => Err(SdrError::DeviceNotFound(format!(
+                "{:?} is ambiguous for {}, candidates:\n{}",
+                self.selector,
+                self.device_name,
+                describe_candidates(&matching.iter().map(|&i| &candidates[i]).collect::<Vec<_>>())
+            ))),
+        }
+    }
+
+    fn not_found(&self, candidates: &[SdrInfo]) -> SdrError {
+        if candidates.is_empty() {
+            return SdrError::DeviceNotFound(format!("No device found for driver: {}", self.device_name));
+        }
+        SdrError::DeviceNotFound(format!(
+            "No {} device matches {:?}, candidates:\n{}",
+            self.device_name,
+            self.selector,
+            describe_candidates(&candidates.iter().collect::<Vec<_>>())
+        ))
+    }
+}
+
+fn describe_candidates(candidates: &[&SdrInfo]) -> String {
+    candidates
+        .iter()
+        .enumerate()
+        .map(|(i, info)| {
+            format!(
+                "  [{}] label={}, serial={}",
+                i,
+                info.label.as_deref().unwrap_or("?"),
+                info.serial_number.as_deref().unwrap_or("?")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rtl_dongles() -> Vec<SdrInfo> {
+        [
+            "driver=rtlsdr, label=Generic RTL2832U OEM :: 00000001, manufacturer=Realtek, serial=00000001, tuner=Rafael Micro R820T",
+            "driver=rtlsdr, label=Generic RTL2832U OEM :: 00000002, manufacturer=Realtek, serial=00000002, tuner=Rafael Micro R820T",
+            "driver=rtlsdr, label=RTL-SDR Blog V4 :: 00000104, manufacturer=RTLSDRBlog, serial=00000104, tuner=Rafael Micro R828D",
+        ]
+        .iter()
+        .map(|args| SdrInfo::from_args_str(args))
+        .collect()
+    }
+
+    #[test]
+    fn test_parse_device_spec() {
+        let spec = DeviceSpec::parse("rtlsdr").unwrap();
+        assert_eq!(spec.device_name, "rtlsdr");
+        assert_eq!(spec.selector, DeviceSelector::First);
+        assert_eq!(DeviceSpec::parse("rtlsdr:index=2").unwrap().selector, DeviceSelector::Index(2));
+        assert_eq!(DeviceSpec::parse("rtlsdr:2").unwrap().selector, DeviceSelector::Text("2".to_string()));
+        assert!(DeviceSpec::parse("rtlsdr:index=two").is_err());
+        assert_eq!(
+            DeviceSpec::parse("rtlsdr:serial=00000002").unwrap().selector,
+            DeviceSelector::Serial("00000002".to_string())
+        );
+        assert_eq!(
+            DeviceSpec::parse("hackrf: label=HackRF One").unwrap().selector,
+            DeviceSelector::Label("HackRF One".to_string())
+        );
+
+        let spec = DeviceSpec::parse("driver=rtlsdr, tuner=Rafael Micro R828D").unwrap();
+        assert_eq!(spec.device_name, "rtlsdr");
+        assert_eq!(
+            spec.selector,
+            DeviceSelector::Args(vec![("tuner".to_string(), "Rafael Micro R828D".to_string())])
+        );
+        assert!(DeviceSpec::parse("serial=00000001").is_err());
+
+        // A colon in a value is not the device name separator
+        let spec = DeviceSpec::parse("driver=plutosdr, uri=ip:192.168.2.1").unwrap();
+        assert_eq!(spec.device_name, "plutosdr");
+        assert_eq!(
+            spec.selector,
+            DeviceSelector::Args(vec![("uri".to_string(), "ip:192.168.2.1".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_select_device() {
+        let dongles = rtl_dongles();
+        assert_eq!(DeviceSpec::parse("rtlsdr").unwrap().select(&dongles).unwrap(), 0);
+        assert_eq!(DeviceSpec::parse("rtlsdr:1").unwrap().select(&dongles).unwrap(), 1);
+        assert_eq!(DeviceSpec::parse("rtlsdr:index=2").unwrap().select(&dongles).unwrap(), 2);
+        assert_eq!(DeviceSpec::parse("rtlsdr:serial=00000002").unwrap().select(&dongles).unwrap(), 1);
+        assert_eq!(DeviceSpec::parse("rtlsdr:00000104").unwrap().select(&dongles).unwrap(), 2);
+        assert_eq!(DeviceSpec::parse("rtlsdr:label=Blog V4").unwrap().select(&dongles).unwrap(), 2);
+        assert_eq!(DeviceSpec::parse("rtlsdr:Blog").unwrap().select(&dongles).unwrap(), 2);
+        assert_eq!(
+            DeviceSpec::parse("driver=rtlsdr, manufacturer=Realtek, serial=00000002")
+                .unwrap()
+                .select(&dongles)
+                .unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_select_device_errors_list_candidates() {
+        let dongles = rtl_dongles();
+        match DeviceSpec::parse("rtlsdr:serial=12345678").unwrap().select(&dongles) {
+            Err(SdrError::DeviceNotFound(msg)) => {
+                assert!(msg.contains("serial=00000001"));
+                assert!(msg.contains("serial=00000104"));
+            }
+            other => panic!("Expected DeviceNotFound, got {:?}", other),
+        }
+        assert!(matches!(
+            DeviceSpec::parse("rtlsdr:3").unwrap().select(&dongles),
+            Err(SdrError::DeviceNotFound(_))
+        ));
+        // Both generic dongles match
+        assert!(matches!(
+            DeviceSpec::parse("rtlsdr:label=Generic").unwrap().select(&dongles),
+            Err(SdrError::DeviceNotFound(_))
+        ));
+        assert!(matches!(
+            DeviceSpec::parse("rtlsdr").unwrap().select(&[]),
+            Err(SdrError::DeviceNotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,309 @@
+use crate::constants::gps_property_constants::GPS_L1_FREQ_HZ;
+use crate::sdr_store::sample_source::SampleSource;
+use crate::sdr_store::sdr_wrapper::{SdrConfig, SdrDeviceWrapper, SdrError, SdrInfo};
+use num_complex::Complex32;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::time::{Duration, Instant};
+
+/// Number of samples handed out per read, similar to the MTU of a USB SDR
+pub const FILE_SOURCE_MTU: usize = 16384;
+
+/// Layout of the samples stored in a recording
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SampleFormat {
+    Int8Real,   // One signed byte per real sample, e.g. IF recordings
+    Int8Iq,     // Interleaved signed bytes I, Q
+    Uint8Iq,    // Interleaved unsigned bytes I, Q centred on 127.5, e.g. rtl_sdr and rtl_tcp
+    Int16Iq,    // Interleaved little-endian i16 I, Q
+    Complex32,  // Interleaved little-endian f32 I, Q
+}
+
+impl SampleFormat {
+    pub fn bytes_per_sample(&self) -> usize {
+        match self {
+            SampleFormat::Int8Real => 1,
+            SampleFormat::Int8Iq | SampleFormat::Uint8Iq => 2,
+            SampleFormat::Int16Iq => 4,
+            SampleFormat::Complex32 => 8,
+        }
+    }
+
+    /// Convert raw bytes into complex samples, `bytes` must hold a whole number of samples
+    pub fn convert(&self, bytes: &[u8], out: &mut [Complex32]) {
+        let step = self.bytes_per_sample();
+        for (dst, src) in out.iter_mut().zip(bytes.chunks_exact(step)) {
+            *dst = match self {
+                SampleFormat::Int8Real => Complex32::new(src[0] as i8 as f32, 0.0),
+                SampleFormat::Int8Iq => Complex32::new(src[0] as i8 as f32, src[1] as i8 as f32),
+                SampleFormat::Uint8Iq => Complex32::new(src[0] as f32 - 127.5, src[1] as f32 - 127.5),
+                SampleFormat::Int16Iq => Complex32::new(
+                    i16::from_le_bytes([src[0], src[1]]) as f32,
+                    i16::from_le_bytes([src[2], src[3]]) as f32,
+                ),
+                SampleFormat::Complex32 => Complex32::new(
+                    f32::from_le_bytes([src[0], src[1], src[2], src[3]]),
+                    f32::from_le_bytes([src[4], src[5], src[6], src[7]]),
+                ),
+            };
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileSourceConfig {
+    pub path: String,
+    pub format: SampleFormat,
+    pub sample_rate_hz: f32,
+    pub freq_if_hz: f32,
+    pub playback_rate_hz: Option<f32>, // Samples per second, None to read as fast as possible
+    #[serde(default)]
+    pub repeat: bool, // Start again from the beginning at the end of the file
+}
+
+/// Replays recorded IQ samples from disk as if they came from an SDR
+pub struct FileSource {
+    reader: BufReader<File>,
+    byte_buf: Vec<u8>,
+    pub file_config: FileSourceConfig,
+    pub sdr_info: SdrInfo,
+    pub sdr_config: SdrConfig,
+    started_at: Option<Instant>,
+    samples_read: u64,
+}
+
+impl FileSource {
+    pub fn new(file_config: &FileSourceConfig) -> Result<Self, SdrError> {
+        let file = File::open(&file_config.path).map_err(|e| {
+            SdrError::DeviceNotFound(format!("Failed to open {}: {}", file_config.path, e))
+        })?;
+
+        let info = SdrInfo {
+            long_args: Some(format!("driver=file, path={}", file_config.path)),
+            driver: Some("file".to_string()),
+            label: Some(file_config.path.clone()),
+            description: Some(format!("{:?} recording", file_config.format)),
+            ..SdrInfo::default()
+        };
+
+        let sdr_config = SdrConfig {
+            center_frequency_hz: GPS_L1_FREQ_HZ + file_config.freq_if_hz,
+            sample_rate_hz: file_config.sample_rate_hz,
+            bandwidth_hz: file_config.sample_rate_hz,
+            ..SdrConfig::default()
+        };
+
+        Ok(Self {
+            reader: BufReader::new(file),
+            byte_buf: Vec::with_capacity(FILE_SOURCE_MTU * file_config.format.bytes_per_sample()),
+            file_config: file_config.clone(),
+            sdr_info: info,
+            sdr_config,
+            started_at: None,
+            samples_read: 0,
+        })
+    }
+
+    /// Fill `byte_buf` as much as possible, returns the number of bytes read
+    fn fill_bytes(&mut self, len: usize) -> Result<usize, SdrError> {
+        self.byte_buf.resize(len, 0);
+        let mut filled = 0;
+        while filled < len {
+            let n = self
+                .reader
+                .read(&mut self.byte_buf[filled..])
+                .map_err(|e| SdrError::SampleReadError(e.to_string()))?;
+            if n == 0 {
+                break;
+            }
+            filled += n;
+        }
+        Ok(filled)
+    }
+
+    /// Sleep until the wall clock catches up with the samples handed out so far
+    fn throttle(&mut self) {
+        if let Some(rate) = self.file_config.playback_rate_hz {
+            let started_at = *self.started_at.get_or_insert_with(Instant::now);
+            let expected = Duration::from_secs_f64(self.samples_read as f64 / rate as f64);
+            let elapsed = started_at.elapsed();
+            if expected > elapsed {
+                std::thread::sleep(expected - elapsed);
+            }
+        }
+    }
+}
+
+impl SampleSource for FileSource {
+    #[allow(unused_variables)]
+    fn read(&mut self, buf: &mut [Complex32], timeout_us: i64) -> Result<usize, SdrError> {
+        let step = self.file_config.format.bytes_per_sample();
+
+        let mut n_bytes = self.fill_bytes(buf.len() * step)?;
+        if n_bytes < step && self.file_config.repeat {
+            self.reader
+                .seek(SeekFrom::Start(0))
+                .map_err(|e| SdrError::SampleReadError(e.to_string()))?;
+            n_bytes = self.fill_bytes(buf.len() * step)?;
+        }
+        // A trailing partial sample is dropped
+        let n_samples = n_bytes / step;
+        if n_samples == 0 {
+            return Err(SdrError::EndOfStream(self.file_config.path.clone()));
+        }
+
+        self.file_config
+            .format
+            .convert(&self.byte_buf[..n_samples * step], &mut buf[..n_samples]);
+        self.samples_read += n_samples as u64;
+        self.throttle();
+
+        Ok(n_samples)
+    }
+
+    fn mtu(&self) -> Result<usize, SdrError> {
+        Ok(FILE_SOURCE_MTU)
+    }
+
+    fn sample_rate_hz(&self) -> f64 {
+        self.file_config.sample_rate_hz as f64
+    }
+
+    fn center_frequency_hz(&self) -> f64 {
+        self.sdr_config.center_frequency_hz as f64
+    }
+}
+
+impl SdrDeviceWrapper for FileSource {
+    fn get_config(&self) -> SdrConfig {
+        self.sdr_config.clone()
+    }
+
+    fn get_info(&self) -> SdrInfo {
+        self.sdr_info.clone()
+    }
+
+    /// The recording defines the stream, only the PPS flag is taken from the config
+    fn config(&mut self, config: Value) -> Result<(), SdrError> {
+        if let Some(pps_enabled) = config.get("pps_enabled").and_then(Value::as_bool) {
+            self.sdr_config.pps_enabled = Some(pps_enabled);
+        }
+        Ok(())
+    }
+
+    #[allow(unused_variables)]
+    fn transmit_samples(&self, buf: &mut [&mut [Complex32]]) -> Result<(), SdrError> {
+        Err(SdrError::TransmitError("File source is receive only".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+
+    fn write_temp_file(name: &str, bytes: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("gnss_sdr_rs_{}_{}", std::process::id(), name));
+        let mut file = File::create(&path).expect("Failed to create temp file");
+        file.write_all(bytes).expect("Failed to write temp file");
+        path
+    }
+
+    fn file_config(path: &Path, format: SampleFormat) -> FileSourceConfig {
+        FileSourceConfig {
+            path: path.to_string_lossy().to_string(),
+            format,
+            sample_rate_hz: 16_367_600.0,
+            freq_if_hz: 4_130_400.0,
+            playback_rate_hz: None,
+            repeat: false,
+        }
+    }
+
+    #[test]
+    fn test_sample_format_conversion() {
+        let mut out = [Complex32::new(0.0, 0.0); 2];
+
+        SampleFormat::Int8Real.convert(&[0xff, 0x05], &mut out);
+        assert_eq!(out, [Complex32::new(-1.0, 0.0), Complex32::new(5.0, 0.0)]);
+
+        SampleFormat::Int8Iq.convert(&[0x01, 0xfe, 0x80, 0x7f], &mut out);
+        assert_eq!(out, [Complex32::new(1.0, -2.0), Complex32::new(-128.0, 127.0)]);
+
+        SampleFormat::Uint8Iq.convert(&[0x00, 0xff, 0x7f, 0x80], &mut out);
+        assert_eq!(out, [Complex32::new(-127.5, 127.5), Complex32::new(-0.5, 0.5)]);
+
+        let mut bytes = Vec::new();
+        for v in [300i16, -300, 1, -1] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        SampleFormat::Int16Iq.convert(&bytes, &mut out);
+        assert_eq!(out, [Complex32::new(300.0, -300.0), Complex32::new(1.0, -1.0)]);
+
+        let mut bytes = Vec::new();
+        for v in [0.5f32, -0.25, 1.5, 2.0] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        SampleFormat::Complex32.convert(&bytes, &mut out);
+        assert_eq!(out, [Complex32::new(0.5, -0.25), Complex32::new(1.5, 2.0)]);
+    }
+
+    #[test]
+    fn test_file_source_reads_until_end_of_file() {
+        // 5 IQ samples plus a dangling byte which must be ignored
+        let bytes: Vec<u8> = (0..11).map(|x| x as u8).collect();
+        let path = write_temp_file("int8_iq.bin", &bytes);
+        let mut source = FileSource::new(&file_config(&path, SampleFormat::Int8Iq))
+            .expect("Failed to open file source");
+        assert_eq!(source.get_config().sample_rate_hz, 16_367_600.0);
+
+        let mut buf = [Complex32::new(0.0, 0.0); 4];
+        assert_eq!(source.read(&mut buf, 0).unwrap(), 4);
+        assert_eq!(buf[3], Complex32::new(6.0, 7.0));
+        assert_eq!(source.read(&mut buf, 0).unwrap(), 1);
+        assert_eq!(buf[0], Complex32::new(8.0, 9.0));
+        assert!(matches!(
+            source.read(&mut buf, 0),
+            Err(SdrError::EndOfStream(_))
+        ));
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_file_source_repeat() {
+        let path = write_temp_file("int8_real.bin", &[1, 2, 3]);
+        let mut config = file_config(&path, SampleFormat::Int8Real);
+        config.repeat = true;
+        let mut source = FileSource::new(&config).expect("Failed to open file source");
+
+        let mut buf = [Complex32::new(0.0, 0.0); 3];
+        for _ in 0..3 {
+            assert_eq!(source.read(&mut buf, 0).unwrap(), 3);
+            assert_eq!(buf[0], Complex32::new(1.0, 0.0));
+        }
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_file_source_playback_rate() {
+        let path = write_temp_file("int8_real_rate.bin", &[0u8; 1000]);
+        let mut config = file_config(&path, SampleFormat::Int8Real);
+        config.playback_rate_hz = Some(10_000.0);
+        let mut source = FileSource::new(&config).expect("Failed to open file source");
+
+        let mut buf = vec![Complex32::new(0.0, 0.0); 500];
+        let now = Instant::now();
+        source.read(&mut buf, 0).unwrap();
+        source.read(&mut buf, 0).unwrap();
+        // 1000 samples at 10 kS/s take 100 ms
+        assert!(now.elapsed() >= Duration::from_millis(100));
+
+        let _ = std::fs::remove_file(path);
+    }
+}
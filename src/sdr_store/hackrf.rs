@@ -0,0 +1,92 @@
+use crate::sdr_store::device_config::{DeviceProfile, GainStageLimits};
+use crate::sdr_store::sdr_wrapper::SdrError;
+use serde_json::Value;
+
+/// HackRF One: AMP (RF, 0 or 14 dB), LNA (IF, 8 dB steps) and VGA (baseband, 2 dB steps).
+/// The antenna port power is the `bias_tx` setting, a 10 MHz reference on CLKIN is detected
+/// by the hardware so there is no clock source to select.
+pub const HACKRF_PROFILE: DeviceProfile = DeviceProfile {
+    name: "HackRF",
+    bias_tee_setting: Some("bias_tx"),
+    default_antenna: None,
+    gain_limits: &[
+        GainStageLimits { name: "AMP", min_db: 0.0, max_db: 14.0 },
+        GainStageLimits { name: "LNA", min_db: 0.0, max_db: 40.0 },
+        GainStageLimits { name: "VGA", min_db: 0.0, max_db: 62.0 },
+    ],
+    clock_sources: &[],
+    validate: Some(validate_hackrf_config),
+};
+
+/// The RF amplifier is either bypassed or on
+fn validate_hackrf_config(config: &Value) -> Result<(), SdrError> {
+    if let Some(amp) = config
+        .get("gain_stages")
+        .and_then(|stages| stages.get("AMP"))
+        .and_then(Value::as_f64)
+        && amp != 0.0
+        && amp != 14.0
+    {
+        return Err(SdrError::ConfigError(format!(
+            "HackRF AMP gain must be 0 or 14 dB, got {}",
+            amp
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdr_mock::device_mock::MockDevice;
+    use crate::sdr_store::profiled_sdr::ProfiledSdr;
+    use crate::sdr_store::sample_source::SampleSource;
+    use crate::sdr_store::sdr_wrapper::{SdrDeviceWrapper, SdrError};
+    use num_complex::Complex32;
+
+    #[test]
+    fn test_hackrf_gain_stages_and_bias_tee() {
+        let mut hackrf = ProfiledSdr::<MockDevice>::new(
+            "driver=hackrf, label=HackRF One #0 457863c8234f6f5f, serial=0000000000000000457863c8234f6f5f, version=2021.03.1",
+            &HACKRF_PROFILE,
+        )
+        .expect("Failed to mock a HackRF device");
+        assert_eq!(hackrf.sdr_info.driver, Some("hackrf".to_string()));
+
+        hackrf
+            .config(serde_json::json!({
+                "center_frequency": 1575.42e6,
+                "sample_rate": 8e6,
+                "bandwidth": 5e6,
+                "gain_stages": { "AMP": 14.0, "LNA": 32.0, "VGA": 20.0 },
+                "bias_tee": true,
+            }))
+            .expect("Failed to configure the mocked HackRF device");
+        assert_eq!(hackrf.backend.gain_elements["LNA"], 32.0);
+        assert_eq!(hackrf.sdr_config.gain_db, 66.0);
+        assert_eq!(hackrf.backend.settings["bias_tx"], "true");
+        assert_eq!(hackrf.sdr_config.antennas, Some(vec!["TX/RX".to_string()]));
+
+        hackrf.start_sdr(&[0], None).expect("Failed to start the stream");
+        let mut buf = vec![Complex32::new(0.0, 0.0); hackrf.mtu().unwrap()];
+        assert_eq!(hackrf.read(&mut buf, 1000).unwrap(), buf.len());
+    }
+
+    #[test]
+    fn test_hackrf_rejects_invalid_config() {
+        let mut hackrf = ProfiledSdr::<MockDevice>::new("driver=hackrf", &HACKRF_PROFILE).unwrap();
+        let base = serde_json::json!({ "sample_rate": 8e6, "bandwidth": 5e6 });
+
+        let mut config = base.clone();
+        config["gain_stages"] = serde_json::json!({ "AMP": 7.0 });
+        assert!(matches!(hackrf.config(config), Err(SdrError::ConfigError(_))));
+
+        let mut config = base.clone();
+        config["gain_stages"] = serde_json::json!({ "MIX": 7.0 });
+        assert!(matches!(hackrf.config(config), Err(SdrError::ConfigError(_))));
+
+        let mut config = base;
+        config["clock_source"] = serde_json::json!("external");
+        assert!(matches!(hackrf.config(config), Err(SdrError::ConfigError(_))));
+    }
+}
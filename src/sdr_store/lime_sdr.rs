@@ -0,0 +1,49 @@
+use crate::sdr_store::device_config::{DeviceProfile, GainStageLimits};
+
+/// LimeSDR (LMS7002M): LNA, TIA and PGA stages. LNAW is the wide band input, LNAH covers
+/// 1.5 to 3.8 GHz and LNAL up to 2 GHz, so L1 works on any of them. There is no bias-tee.
+pub const LIME_SDR_PROFILE: DeviceProfile = DeviceProfile {
+    name: "LimeSDR",
+    bias_tee_setting: None,
+    default_antenna: Some("LNAW"),
+    gain_limits: &[
+        GainStageLimits { name: "LNA", min_db: 0.0, max_db: 30.0 },
+        GainStageLimits { name: "TIA", min_db: 0.0, max_db: 12.0 },
+        GainStageLimits { name: "PGA", min_db: -12.0, max_db: 19.0 },
+    ],
+    clock_sources: &["internal", "external"],
+    validate: None,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdr_mock::device_mock::MockDevice;
+    use crate::sdr_store::profiled_sdr::ProfiledSdr;
+    use crate::sdr_store::sdr_wrapper::{SdrDeviceWrapper, SdrError};
+
+    #[test]
+    fn test_lime_sdr_antenna_and_clock() {
+        let mut lime = ProfiledSdr::<MockDevice>::new("driver=lime, label=LimeSDR Mini [USB 3.0] 1D3AC8F4B0E6A1, serial=1D3AC8F4B0E6A1", &LIME_SDR_PROFILE)
+            .expect("Failed to mock a LimeSDR device");
+
+        lime.config(serde_json::json!({
+            "center_frequency": 1575.42e6,
+            "sample_rate": 4e6,
+            "bandwidth": 5e6,
+            "gain_stages": { "LNA": 30.0, "TIA": 12.0, "PGA": -3.0 },
+            "clock_source": "external",
+        }))
+        .expect("Failed to configure the mocked LimeSDR device");
+        assert_eq!(lime.backend.antenna, Some("LNAW".to_string()));
+        assert_eq!(lime.backend.clock_source, Some("external".to_string()));
+        assert_eq!(lime.sdr_config.gain_db, 39.0);
+
+        lime.config(serde_json::json!({ "bandwidth": 5e6, "antennas": ["LNAH"] }))
+            .expect("Failed to select LNAH");
+        assert_eq!(lime.backend.antenna, Some("LNAH".to_string()));
+
+        let config = serde_json::json!({ "bandwidth": 5e6, "bias_tee": true });
+        assert!(matches!(lime.config(config), Err(SdrError::ConfigError(_))));
+    }
+}
@@ -1,4 +1,24 @@
 pub mod sdr_wrapper;
+pub mod sample_source;
+pub mod backend;
+#[cfg(feature = "soapy")]
+pub mod soapy_backend;
+pub mod device_config;
+pub mod device_selector;
+pub mod profiled_sdr;
 pub mod rtl_sdr;
+pub mod hackrf;
+pub mod airspy;
+pub mod lime_sdr;
+pub mod pluto_sdr;
+pub mod bladerf;
+pub mod usrp;
 pub mod sdr_thread;
-pub mod utils;
\ No newline at end of file
+#[cfg(feature = "soapy")]
+pub mod utils;
+pub mod file_source;
+pub mod net_source;
+pub mod rtl_tcp;
+pub mod sigmf;
+pub mod sample_time;
+pub mod stream_monitor;
@@ -0,0 +1,279 @@
+use crate::constants::gps_property_constants::GPS_L1_FREQ_HZ;
+use crate::sdr_store::file_source::SampleFormat;
+use crate::sdr_store::sample_source::SampleSource;
+use crate::sdr_store::sdr_wrapper::{SdrConfig, SdrDeviceWrapper, SdrError, SdrInfo};
+use num_complex::Complex32;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{ErrorKind, Read};
+use std::net::{TcpStream, UdpSocket};
+use std::time::Duration;
+
+/// Number of samples handed out per read
+pub const NET_SOURCE_MTU: usize = 16384;
+
+/// Largest UDP payload
+const MAX_DATAGRAM_BYTES: usize = 65536;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NetProtocol {
+    Tcp, // Connect to a server streaming IQ, e.g. `nc -l` on the mast
+    Udp, // Bind locally and receive IQ datagrams
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetSourceConfig {
+    pub protocol: NetProtocol,
+    pub address: String, // Remote "host:port" for TCP, local "host:port" to bind for UDP
+    pub format: SampleFormat,
+    pub sample_rate_hz: f32,
+    pub freq_if_hz: f32,
+}
+
+/// Turns a byte stream into samples, keeping the bytes of a sample split across reads
+pub struct SampleAssembler {
+    pub format: SampleFormat,
+    pending: Vec<u8>,
+}
+
+impl SampleAssembler {
+    pub fn new(format: SampleFormat) -> Self {
+        Self { format, pending: Vec::with_capacity(MAX_DATAGRAM_BYTES) }
+    }
+
+    /// Number of whole samples waiting to be taken
+    pub fn available(&self) -> usize {
+        self.pending.len() / self.format.bytes_per_sample()
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.pending.extend_from_slice(bytes);
+    }
+
+    /// Convert as many pending samples as fit in `out`, returns the number of samples
+    pub fn take(&mut self, out: &mut [Complex32]) -> usize {
+        let step = self.format.bytes_per_sample();
+        let n_samples = self.available().min(out.len());
+        self.format.convert(&self.pending[..n_samples * step], &mut out[..n_samples]);
+        self.pending.drain(..n_samples * step);
+        n_samples
+    }
+}
+
+/// Map a socket timeout to zero samples, like a SoapySDR read timeout
+pub fn is_timeout(e: &std::io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+pub fn timeout_duration(timeout_us: i64) -> Option<Duration> {
+    // A zero duration is rejected by set_read_timeout
+    (timeout_us > 0).then(|| Duration::from_micros(timeout_us as u64))
+}
+
+enum NetSocket {
+    Tcp(TcpStream),
+    Udp(UdpSocket),
+}
+
+/// Interleaved IQ samples received over TCP or UDP, without any control channel
+pub struct NetSource {
+    socket: NetSocket,
+    assembler: SampleAssembler,
+    byte_buf: Vec<u8>,
+    pub net_config: NetSourceConfig,
+    pub sdr_info: SdrInfo,
+    pub sdr_config: SdrConfig,
+}
+
+impl NetSource {
+    pub fn new(net_config: &NetSourceConfig) -> Result<Self, SdrError> {
+        let socket = match net_config.protocol {
+            NetProtocol::Tcp => NetSocket::Tcp(TcpStream::connect(&net_config.address).map_err(|e| {
+                SdrError::DeviceNotFound(format!("Failed to connect to {}: {}", net_config.address, e))
+            })?),
+            NetProtocol::Udp => NetSocket::Udp(UdpSocket::bind(&net_config.address).map_err(|e| {
+                SdrError::DeviceNotFound(format!("Failed to bind {}: {}", net_config.address, e))
+            })?),
+        };
+
+        let info = SdrInfo {
+            long_args: Some(format!("driver=net, protocol={:?}, address={}", net_config.protocol, net_config.address)),
+            driver: Some("net".to_string()),
+            label: Some(net_config.address.clone()),
+            description: Some(format!("{:?} stream over {:?}", net_config.format, net_config.protocol)),
+            ..SdrInfo::default()
+        };
+
+        let sdr_config = SdrConfig {
+            center_frequency_hz: GPS_L1_FREQ_HZ + net_config.freq_if_hz,
+            sample_rate_hz: net_config.sample_rate_hz,
+            bandwidth_hz: net_config.sample_rate_hz,
+            ..SdrConfig::default()
+        };
+
+        Ok(Self {
+            socket,
+            assembler: SampleAssembler::new(net_config.format),
+            byte_buf: vec![0u8; MAX_DATAGRAM_BYTES],
+            net_config: net_config.clone(),
+            sdr_info: info,
+            sdr_config,
+        })
+    }
+
+    /// Receive more bytes into the assembler, returns false when the peer closed the stream
+    fn receive(&mut self, max_bytes: usize, timeout_us: i64) -> Result<bool, SdrError> {
+        let timeout = timeout_duration(timeout_us);
+        let received = match &mut self.socket {
+            NetSocket::Tcp(stream) => {
+                stream.set_read_timeout(timeout).map_err(|e| SdrError::StreamError(e.to_string()))?;
+                let len = max_bytes.min(self.byte_buf.len());
+                stream.read(&mut self.byte_buf[..len])
+            }
+            NetSocket::Udp(socket) => {
+                // A datagram must be read whole, the remainder stays in the assembler
+                socket.set_read_timeout(timeout).map_err(|e| SdrError::StreamError(e.to_string()))?;
+                socket.recv(&mut self.byte_buf)
+            }
+        };
+
+        match received {
+            Ok(0) if matches!(self.socket, NetSocket::Tcp(_)) => Ok(false),
+            Ok(n) => {
+                self.assembler.push(&self.byte_buf[..n]);
+                Ok(true)
+            }
+            Err(e) if is_timeout(&e) => Ok(true),
+            Err(e) => Err(SdrError::SampleReadError(e.to_string())),
+        }
+    }
+}
+
+impl SampleSource for NetSource {
+    fn read(&mut self, buf: &mut [Complex32], timeout_us: i64) -> Result<usize, SdrError> {
+        let buf_len = buf.len().min(NET_SOURCE_MTU);
+        if self.assembler.available() == 0 {
+            let max_bytes = buf_len * self.net_config.format.bytes_per_sample();
+            if !self.receive(max_bytes, timeout_us)? {
+                return Err(SdrError::EndOfStream(self.net_config.address.clone()));
+            }
+        }
+        Ok(self.assembler.take(&mut buf[..buf_len]))
+    }
+
+    fn mtu(&self) -> Result<usize, SdrError> {
+        Ok(NET_SOURCE_MTU)
+    }
+
+    fn sample_rate_hz(&self) -> f64 {
+        self.net_config.sample_rate_hz as f64
+    }
+
+    fn center_frequency_hz(&self) -> f64 {
+        self.sdr_config.center_frequency_hz as f64
+    }
+}
+
+impl SdrDeviceWrapper for NetSource {
+    fn get_config(&self) -> SdrConfig {
+        self.sdr_config.clone()
+    }
+
+    fn get_info(&self) -> SdrInfo {
+        self.sdr_info.clone()
+    }
+
+    /// The sender defines the stream, only the PPS flag is taken from the config
+    fn config(&mut self, config: Value) -> Result<(), SdrError> {
+        if let Some(pps_enabled) = config.get("pps_enabled").and_then(Value::as_bool) {
+            self.sdr_config.pps_enabled = Some(pps_enabled);
+        }
+        Ok(())
+    }
+
+    #[allow(unused_variables)]
+    fn transmit_samples(&self, buf: &mut [&mut [Complex32]]) -> Result<(), SdrError> {
+        Err(SdrError::TransmitError("Network source is receive only".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rf::samples_buffer::create_samples_ring_buffer;
+    use crate::sdr_store::sdr_thread::sdr_thread;
+    use ringbuf::traits::Consumer;
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn net_config(protocol: NetProtocol, address: String, format: SampleFormat) -> NetSourceConfig {
+        NetSourceConfig {
+            protocol,
+            address,
+            format,
+            sample_rate_hz: 2_048_000.0,
+            freq_if_hz: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_tcp_source_reassembles_split_samples() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut bytes = Vec::new();
+            for v in 0..1000i16 {
+                bytes.extend_from_slice(&v.to_le_bytes());
+                bytes.extend_from_slice(&(-v).to_le_bytes());
+            }
+            // Odd chunk sizes split the samples across TCP reads
+            for chunk in bytes.chunks(333) {
+                stream.write_all(chunk).unwrap();
+                stream.flush().unwrap();
+            }
+        });
+
+        let mut source = NetSource::new(&net_config(NetProtocol::Tcp, address, SampleFormat::Int16Iq))
+            .expect("Failed to connect to the loopback server");
+        let mut ring_buffer = create_samples_ring_buffer(4096);
+        sdr_thread(&mut source, &mut ring_buffer.producer).expect("sdr_thread failed");
+        server.join().unwrap();
+
+        let mut samples = vec![Complex32::new(0.0, 0.0); 4096];
+        assert_eq!(ring_buffer.consumer.pop_slice(&mut samples), 1000);
+        for (v, s) in samples[..1000].iter().enumerate() {
+            assert_eq!(*s, Complex32::new(v as f32, -(v as f32)));
+        }
+    }
+
+    #[test]
+    fn test_udp_source() {
+        let mut source = NetSource::new(&net_config(
+            NetProtocol::Udp,
+            "127.0.0.1:0".to_string(),
+            SampleFormat::Int8Iq,
+        ))
+        .expect("Failed to bind the UDP source");
+        let address = match &source.socket {
+            NetSocket::Udp(socket) => socket.local_addr().unwrap(),
+            NetSocket::Tcp(_) => unreachable!(),
+        };
+
+        let mut buf = vec![Complex32::new(0.0, 0.0); 8];
+        // Nothing sent yet, the read times out
+        assert_eq!(source.read(&mut buf, 1000).unwrap(), 0);
+
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let datagram: Vec<u8> = (0..24u8).collect();
+        sender.send_to(&datagram, address).unwrap();
+
+        // 12 samples in one datagram, 8 now and 4 on the next read
+        assert_eq!(source.read(&mut buf, 1_000_000).unwrap(), 8);
+        assert_eq!(buf[7], Complex32::new(14.0, 15.0));
+        assert_eq!(source.read(&mut buf, 1_000_000).unwrap(), 4);
+        assert_eq!(buf[3], Complex32::new(22.0, 23.0));
+    }
+}
@@ -0,0 +1,58 @@
+use crate::sdr_store::device_config::{DeviceProfile, GainStageLimits};
+use crate::sdr_store::sdr_wrapper::SdrError;
+use serde_json::Value;
+
+/// ADALM-Pluto (AD9363): a single PGA gain stage and the balanced A input, no bias-tee and
+/// no reference clock selection without hardware modifications.
+pub const PLUTO_SDR_PROFILE: DeviceProfile = DeviceProfile {
+    name: "PlutoSDR",
+    bias_tee_setting: None,
+    default_antenna: Some("A_BALANCED"),
+    gain_limits: &[GainStageLimits { name: "PGA", min_db: 0.0, max_db: 73.0 }],
+    clock_sources: &[],
+    validate: Some(validate_pluto_sdr_config),
+};
+
+/// Sample rate range of the AD9363 with the default FIR
+const PLUTO_MIN_SAMPLE_RATE_HZ: f64 = 520_834.0;
+const PLUTO_MAX_SAMPLE_RATE_HZ: f64 = 61_440_000.0;
+
+fn validate_pluto_sdr_config(config: &Value) -> Result<(), SdrError> {
+    if let Some(s_rate) = config.get("sample_rate").and_then(Value::as_f64)
+        && !(PLUTO_MIN_SAMPLE_RATE_HZ..=PLUTO_MAX_SAMPLE_RATE_HZ).contains(&s_rate)
+    {
+        return Err(SdrError::ConfigError(format!(
+            "PlutoSDR sample rate {} Hz out of range [{}, {}]",
+            s_rate, PLUTO_MIN_SAMPLE_RATE_HZ, PLUTO_MAX_SAMPLE_RATE_HZ
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdr_mock::device_mock::MockDevice;
+    use crate::sdr_store::profiled_sdr::ProfiledSdr;
+    use crate::sdr_store::sdr_wrapper::{SdrDeviceWrapper, SdrError};
+
+    #[test]
+    fn test_pluto_sdr_config() {
+        let mut pluto = ProfiledSdr::<MockDevice>::new("driver=plutosdr, label=PlutoSDR #1 usb:1.5.5, uri=usb:1.5.5", &PLUTO_SDR_PROFILE)
+            .expect("Failed to mock a PlutoSDR device");
+
+        pluto
+            .config(serde_json::json!({
+                "center_frequency": 1575.42e6,
+                "sample_rate": 4e6,
+                "bandwidth": 4e6,
+                "gain_stages": { "PGA": 60.0 },
+            }))
+            .expect("Failed to configure the mocked PlutoSDR device");
+        assert_eq!(pluto.backend.antenna, Some("A_BALANCED".to_string()));
+        assert_eq!(pluto.sdr_config.gain_db, 60.0);
+
+        let config = serde_json::json!({ "sample_rate": 250e3, "bandwidth": 250e3 });
+        assert!(matches!(pluto.config(config), Err(SdrError::ConfigError(_))));
+    }
+}
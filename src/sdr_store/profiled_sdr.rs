@@ -0,0 +1,105 @@
+use crate::sdr_store::backend::SdrBackend;
+use crate::sdr_store::device_config::{DeviceProfile, apply_frontend_config, apply_stream_config};
+use crate::sdr_store::sample_source::{SampleSource, StreamEvent};
+use crate::sdr_store::sdr_wrapper::{SdrConfig, SdrDeviceWrapper, SdrError, SdrInfo};
+#[cfg(feature = "soapy")]
+use crate::sdr_store::soapy_backend::{SoapyBackend, map_args_to_info};
+use rustfft::num_complex::Complex32;
+use serde_json::Value;
+#[cfg(feature = "soapy")]
+use soapysdr::Args;
+
+/// Receive-only SoapySDR device described by its `DeviceProfile`, e.g. `HACKRF_PROFILE`
+pub struct ProfiledSdr<B: SdrBackend> {
+    pub backend: B,
+    pub profile: &'static DeviceProfile,
+    pub sdr_info: SdrInfo,
+    pub sdr_config: SdrConfig,
+}
+
+impl<B: SdrBackend> SampleSource for ProfiledSdr<B> {
+    fn read(&mut self, buf: &mut [Complex32], timeout_us: i64) -> Result<usize, SdrError> {
+        self.backend.read_rx(buf, timeout_us)
+    }
+
+    fn mtu(&self) -> Result<usize, SdrError> {
+        self.backend.rx_mtu()
+    }
+
+    fn sample_rate_hz(&self) -> f64 {
+        self.sdr_config.sample_rate_hz as f64
+    }
+
+    fn center_frequency_hz(&self) -> f64 {
+        self.sdr_config.center_frequency_hz as f64
+    }
+
+    fn timestamp_ns(&self) -> Option<i64> {
+        self.backend.rx_time_ns()
+    }
+
+    fn poll_event(&mut self) -> Option<StreamEvent> {
+        self.backend.poll_event()
+    }
+}
+
+impl<B: SdrBackend> SdrDeviceWrapper for ProfiledSdr<B> {
+    fn get_config(&self) -> SdrConfig {
+        self.sdr_config.clone()
+    }
+
+    fn get_info(&self) -> SdrInfo {
+        self.sdr_info.clone()
+    }
+
+    fn config(&mut self, config: Value) -> Result<(), SdrError> {
+        if let Some(validate) = self.profile.validate {
+            validate(&config)?;
+        }
+        apply_stream_config(&mut self.backend, &mut self.sdr_config, &config)?;
+        apply_frontend_config(&mut self.backend, &mut self.sdr_config, self.profile, &config)
+    }
+
+    #[allow(unused_variables)]
+    fn transmit_samples(&self, buf: &mut [&mut [Complex32]]) -> Result<(), SdrError> {
+        Err(SdrError::TransmitError("Transmit is not supported".to_string()))
+    }
+
+    fn start_stream(&mut self) -> Result<(), SdrError> {
+        self.start_sdr(&[0], None)
+    }
+
+    fn stop_stream(&mut self) -> Result<(), SdrError> {
+        self.stop_sdr(&[0], None)
+    }
+}
+
+#[cfg(feature = "soapy")]
+impl ProfiledSdr<SoapyBackend> {
+    // The `args` are one of the entries returned by `soapysdr::enumerate()`
+    pub fn new(args: Args, profile: &'static DeviceProfile) -> Result<Self, SdrError> {
+        let info = map_args_to_info(&args);
+        let backend = SoapyBackend::new(args)?;
+        Ok(Self::with_backend(backend, profile, info))
+    }
+}
+
+impl<B: SdrBackend> ProfiledSdr<B> {
+    pub fn with_backend(backend: B, profile: &'static DeviceProfile, sdr_info: SdrInfo) -> Self {
+        Self {
+            backend,
+            profile,
+            sdr_info,
+            sdr_config: SdrConfig::default(),
+        }
+    }
+
+    pub fn start_sdr(&mut self, chnls: &[usize], time_ns: Option<i64>) -> Result<(), SdrError> {
+        self.backend.activate_rx(chnls, time_ns)
+    }
+
+    #[allow(unused_variables)]
+    pub fn stop_sdr(&mut self, chnls: &[usize], time_ns: Option<i64>) -> Result<(), SdrError> {
+        self.backend.deactivate_rx(time_ns)
+    }
+}
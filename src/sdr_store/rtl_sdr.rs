@@ -1,207 +1,28 @@
-use crate::sdr_store::sdr_wrapper::{SdrConfig, SdrDeviceWrapper, SdrError, SdrInfo};
-use crate::sdr_store::utils::hashmap_to_args;
-use rustfft::num_complex::Complex32;
-use serde_json::Value;
-use soapysdr::{Args, Device, Direction, RxStream, TxStream};
-use std::collections::HashMap;
-use std::thread;
-use std::time;
-
-pub struct RtlSdr<D: SdrDeviceWrapper> {
-    pub device: D,
-    pub rx_stream: Option<RxStream<Complex32>>,
-    pub tx_stream: Option<TxStream<Complex32>>,
-    pub sdr_info: SdrInfo,
-    pub sdr_config: SdrConfig,
-}
-
-impl SdrDeviceWrapper for RtlSdr<Device> {
-    fn device(&self) -> Result<&Device, SdrError> {
-        Ok(&self.device)
-    }
-
-    fn device_mut(&mut self) -> Result<&mut Device, SdrError> {
-        Ok(&mut self.device)
-    }
-
-    fn get_config(&self) -> SdrConfig {
-        self.sdr_config.clone()
-    }
-
-    fn config(&mut self, config: Value) -> Result<(), SdrError> {
-        if let Some(c_freq) = config.get("center_frequency").and_then(Value::as_f64) {
-            self.device()?
-                .set_frequency(Direction::Rx, 0, c_freq, Args::from(""))
-                .map_err(|e| {
-                    SdrError::OtherError(format!("Failed to set center frequency: {}", e))
-                })?;
-            self.sdr_config.center_frequency_hz = c_freq as f32;
-            thread::sleep(time::Duration::from_millis(100));
-        }
-
-        if let Some(s_rate) = config.get("sample_rate").and_then(Value::as_f64) {
-            self.device()?
-                .set_sample_rate(Direction::Rx, 0, s_rate)
-                .map_err(|e| SdrError::OtherError(format!("Failed to set sample rate: {}", e)))?;
-            self.sdr_config.sample_rate_hz = s_rate as f32;
-            thread::sleep(time::Duration::from_millis(100));
-        }
-
-        if let Some(gain) = config.get("gain").and_then(Value::as_f64) {
-            self.device()?
-                .set_gain(Direction::Rx, 0, gain)
-                .map_err(|e| SdrError::OtherError(format!("Failed to set gain: {}", e)))?;
-            self.sdr_config.gain_db = gain as f32;
-            thread::sleep(time::Duration::from_millis(100));
-        }
-
-        self.sdr_config.frequency_correction = Some(
-            config
-                .get("frequency_correction")
-                .and_then(Value::as_f64)
-                .unwrap_or_default() as f32,
-        );
-        thread::sleep(time::Duration::from_millis(100));
-        //  {
-        //      = freq_corr as f32;
-        //     thread::sleep(time::Duration::from_millis(100));
-        // }
-        // else {
-        //     self.sdr_config.frequency_correction = 0.0;
-        //     thread::sleep(time::Duration::from_millis(100));
-        // }
-
-        if let Some(bandwidth) = config.get("bandwidth").and_then(Value::as_f64) {
-            self.device()?
-                .set_bandwidth(Direction::Rx, 0, bandwidth)
-                .map_err(|e| SdrError::OtherError(format!("Failed to set bandwidth: {}", e)))?;
-            self.sdr_config.bandwidth_hz = bandwidth as f32;
-            thread::sleep(time::Duration::from_millis(100));
-        } else {
-            return Err(SdrError::OtherError(
-                "Bandwidth must be specified for SDR device".to_string(),
-            ));
-        }
-
-        if let Ok(antennas) = self.device()?.antennas(Direction::Rx, 0) {
-            if !antennas.is_empty() {
-                self.device()?
-                    .set_antenna(Direction::Rx, 0, antennas[0].clone())
-                    .map_err(|e| SdrError::OtherError(format!("Failed to set antenna: {}", e)))?;
-                self.sdr_config.antennas = Some(vec![antennas[0].to_string()]);
-                thread::sleep(time::Duration::from_millis(100));
-            }
-        }
-
-        if let Some(gain_mode) = config.get("gain_mode").and_then(Value::as_str) {
-            self.device()?
-                .set_gain_mode(Direction::Rx, 0, true)
-                .map_err(|e| {
-                    SdrError::OtherError(format!("Failed to set automatic gain mode: {}", e))
-                })?;
-            self.sdr_config.gain_mode = Some(gain_mode.to_string());
-            thread::sleep(time::Duration::from_millis(100));
-        } else {
-            self.device()?
-                .set_gain_mode(Direction::Rx, 0, false)
-                .map_err(|e| {
-                    SdrError::OtherError(format!("Failed to set manual gain mode: {}", e))
-                })?;
-            self.sdr_config.gain_mode = Some("agc".to_string());
-            thread::sleep(time::Duration::from_millis(100));
-        }
-
-        if let Some(pps_enabled) = config.get("pps_enabled").and_then(Value::as_bool) {
-            self.sdr_config.pps_enabled = Some(pps_enabled);
-            thread::sleep(time::Duration::from_millis(100));
-        }
-
-        Ok(())
-    }
-
-    fn get_rx_stream_mute(&mut self) -> Option<&mut RxStream<Complex32>> {
-        self.rx_stream.as_mut()
-    }
-
-    fn read_samples(
-        &mut self,
-        buf: &mut [&mut [Complex32]],
-        timeout_us: i64,
-    ) -> Result<usize, SdrError> {
-        // Implementation for reading samples into the buffer, rtl-sdr only has one RX channel
-        let n_samples = self
-            .rx_stream
-            .as_mut()
-            .ok_or(SdrError::OtherError(
-                "RX stream not initialized".to_string(),
-            ))?
-            .read(buf, timeout_us)
-            .map_err(|e| SdrError::StreamError(e.to_string()));
-
-        n_samples
-    }
-
-    #[allow(unused_variables)]
-    fn transmit_samples(&self, buf: &mut [&mut [Complex32]]) -> Result<(), SdrError> {
-        // Implementation for transmitting samples
-        Ok(())
-    }
-}
-
-impl RtlSdr<Device> {
-    // Create a new RTL-SDR device with the given arguments
-    // The `args` parameter is a string that contains all the
-    // device arguments that are obtained from soapy_sdr::enumerate()
-    pub fn new(args: Args) -> Result<Self, SdrError> {
-        let mut args_map = HashMap::new();
-        for (key, value) in args.iter() {
-            args_map.insert(key.to_string(), value.to_string());
-        }
-
-        let new_args =
-            hashmap_to_args(args_map).map_err(|e| SdrError::OtherError(e.to_string()))?;
-        let dev = Device::new(args).map_err(|e| SdrError::DeviceError(e.to_string()))?;
-        let info = RtlSdr::map_args_to_info(new_args)?;
-        Ok(Self {
-            device: dev,
-            rx_stream: None,
-            tx_stream: None,
-            sdr_info: info,
-            sdr_config: SdrConfig::default(),
-        })
-    }
-
-    #[allow(unused_variables)]
-    pub fn start_sdr(&mut self, chnls: &[usize], time_ns: Option<i64>) -> Result<(), SdrError> {
-        // Implementation for starting the RX stream
-        let mut rx_stream = self
-            .device_mut()
-            .unwrap()
-            .rx_stream::<Complex32>(chnls)
-            .map_err(|e| SdrError::StreamError(e.to_string()))?;
-        rx_stream
-            .activate(None)
-            .map_err(|e| SdrError::StreamError(e.to_string()))?;
-        self.rx_stream = Some(rx_stream);
-        Ok(())
-    }
-
-    #[allow(unused_variables)]
-    pub fn stop_sdr(&mut self, chnls: &[usize], time_ns: Option<i64>) -> Result<(), SdrError> {
-        // Implementation for stopping the RX stream
-        Ok(())
-    }
-}
+use crate::sdr_store::device_config::{DeviceProfile, GainStageLimits};
+
+/// The R820T tuner exposes a single TUNER gain stage, the V3 and newer dongles have a bias-tee
+pub const RTL_SDR_PROFILE: DeviceProfile = DeviceProfile {
+    name: "RTL-SDR",
+    bias_tee_setting: Some("biastee"),
+    default_antenna: None,
+    gain_limits: &[GainStageLimits { name: "TUNER", min_db: 0.0, max_db: 49.6 }],
+    clock_sources: &[],
+    validate: None,
+};
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::sdr_mock::device_mock::MockDevice;
+    use crate::sdr_store::backend::SdrBackend;
+    use crate::sdr_store::profiled_sdr::ProfiledSdr;
+    use crate::sdr_store::sample_source::SampleSource;
+    use crate::sdr_store::sdr_wrapper::SdrDeviceWrapper;
+    use num_complex::Complex32;
 
     #[test]
     fn test_rtl_sdr_driver() {
-        let args = Args::new();
-        let rtl_sdr = RtlSdr::<MockDevice>::new(args).expect("Failed to mock a RTL-SDR device");
+        let rtl_sdr = ProfiledSdr::<MockDevice>::new("", &RTL_SDR_PROFILE).expect("Failed to mock a RTL-SDR device");
         assert!(rtl_sdr.sdr_info.long_args.is_none());
         assert!(rtl_sdr.sdr_info.serial_number.is_none());
     }
@@ -209,11 +30,39 @@ mod tests {
     #[test]
     fn test_rtl_sdr_args() {
         let args_str = "driver=rtlsdr, label=Generic RTL2832U OEM :: 00000001, manufacturer=Realtek, product=RTL2838UHIDIR, serial=00000001, tuner=Rafael Micro R820T";
-        let rtl_sdr = RtlSdr::<MockDevice>::new(Args::from(args_str))
+        let rtl_sdr = ProfiledSdr::<MockDevice>::new(args_str, &RTL_SDR_PROFILE)
             .expect("Failed to mock a RTL-SDR device");
         assert!(rtl_sdr.sdr_info.serial_number == Some("00000001".to_string()));
         assert!(rtl_sdr.sdr_info.tuner == Some("Rafael Micro R820T".to_string()));
         assert!(rtl_sdr.sdr_info.manufacturer == Some("Realtek".to_string()));
         assert!(rtl_sdr.sdr_info.product == Some("RTL2838UHIDIR".to_string()));
     }
+
+    #[test]
+    fn test_rtl_sdr_config_and_stream() {
+        let mut rtl_sdr = ProfiledSdr::<MockDevice>::new("driver=rtlsdr", &RTL_SDR_PROFILE)
+            .expect("Failed to mock a RTL-SDR device");
+        rtl_sdr
+            .config(serde_json::json!({
+                "center_frequency": 1575.42e6,
+                "sample_rate": 2.048e6,
+                "gain": 40.0,
+                "bandwidth": 2.048e6,
+            }))
+            .expect("Failed to configure the mocked RTL-SDR device");
+        assert_eq!(rtl_sdr.backend.frequency(0).unwrap(), 1575.42e6);
+        assert_eq!(rtl_sdr.backend.sample_rate(0).unwrap(), 2.048e6);
+        // The config keeps frequencies in f32
+        assert!((rtl_sdr.center_frequency_hz() - 1575.42e6).abs() < 100.0);
+        assert_eq!(rtl_sdr.sdr_config.antennas, Some(vec!["RX".to_string()]));
+
+        // No stream before start_sdr
+        assert!(rtl_sdr.mtu().is_err());
+        rtl_sdr.start_sdr(&[0], None).expect("Failed to start the stream");
+        let mut buf = vec![Complex32::new(0.0, 0.0); rtl_sdr.mtu().unwrap()];
+        assert_eq!(rtl_sdr.read(&mut buf, 1000).unwrap(), buf.len());
+
+        rtl_sdr.stop_sdr(&[0], None).expect("Failed to stop the stream");
+        assert!(rtl_sdr.read(&mut buf, 1000).is_err());
+    }
 }
@@ -0,0 +1,320 @@
+use crate::sdr_store::file_source::SampleFormat;
+use crate::sdr_store::net_source::{SampleAssembler, is_timeout, timeout_duration};
+use crate::sdr_store::sample_source::SampleSource;
+use crate::sdr_store::sdr_wrapper::{SdrConfig, SdrDeviceWrapper, SdrError, SdrInfo};
+use num_complex::Complex32;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+/// Number of samples handed out per read
+pub const RTL_TCP_MTU: usize = 16384;
+
+const RTL_TCP_MAGIC: &[u8; 4] = b"RTL0";
+
+/// Commands of the rtl_tcp protocol, each one sent as the command byte and a big-endian u32
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RtlTcpCommand {
+    SetFrequency = 0x01,           // Hz
+    SetSampleRate = 0x02,          // Hz
+    SetGainMode = 0x03,            // 0 automatic, 1 manual
+    SetGain = 0x04,                // Tenths of dB
+    SetFrequencyCorrection = 0x05, // ppm
+    SetIfGain = 0x06,              // Stage << 16 | tenths of dB
+    SetTestMode = 0x07,
+    SetAgcMode = 0x08,             // RTL2832 digital AGC
+    SetDirectSampling = 0x09,
+    SetOffsetTuning = 0x0a,
+    SetGainByIndex = 0x0d,
+    SetBiasTee = 0x0e,
+}
+
+/// Tuner reported in the rtl_tcp greeting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RtlTcpTuner {
+    Unknown,
+    E4000,
+    Fc0012,
+    Fc0013,
+    Fc2580,
+    R820T,
+    R828D,
+}
+
+impl RtlTcpTuner {
+    fn from_u32(tuner: u32) -> Self {
+        match tuner {
+            1 => RtlTcpTuner::E4000,
+            2 => RtlTcpTuner::Fc0012,
+            3 => RtlTcpTuner::Fc0013,
+            4 => RtlTcpTuner::Fc2580,
+            5 => RtlTcpTuner::R820T,
+            6 => RtlTcpTuner::R828D,
+            _ => RtlTcpTuner::Unknown,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RtlTcpConfig {
+    pub address: String, // "host:port" of the rtl_tcp server, usually port 1234
+    pub center_frequency_hz: f64, // f32 would tune 32 Hz off at L1
+    pub sample_rate_hz: f32,
+    pub gain_db: Option<f32>, // None for the tuner AGC
+    pub frequency_correction_ppm: Option<i32>,
+    #[serde(default)]
+    pub bias_tee: bool,
+}
+
+/// Client of an `rtl_tcp` server, tuned through its command channel
+pub struct RtlTcpSource {
+    stream: TcpStream,
+    assembler: SampleAssembler,
+    byte_buf: Vec<u8>,
+    pub tuner: RtlTcpTuner,
+    pub gain_count: u32,
+    pub sdr_info: SdrInfo,
+    pub sdr_config: SdrConfig,
+}
+
+impl RtlTcpSource {
+    /// Connect, read the greeting and apply the initial settings
+    pub fn connect(rtl_tcp_config: &RtlTcpConfig) -> Result<Self, SdrError> {
+        let mut stream = TcpStream::connect(&rtl_tcp_config.address).map_err(|e| {
+            SdrError::DeviceNotFound(format!("Failed to connect to rtl_tcp at {}: {}", rtl_tcp_config.address, e))
+        })?;
+
+        let mut header = [0u8; 12];
+        stream
+            .read_exact(&mut header)
+            .map_err(|e| SdrError::DeviceError(format!("No rtl_tcp greeting: {}", e)))?;
+        if &header[0..4] != RTL_TCP_MAGIC {
+            return Err(SdrError::DeviceError(format!(
+                "{} is not an rtl_tcp server",
+                rtl_tcp_config.address
+            )));
+        }
+        let tuner = RtlTcpTuner::from_u32(u32::from_be_bytes([header[4], header[5], header[6], header[7]]));
+        let gain_count = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+
+        let sdr_info = SdrInfo {
+            long_args: Some(format!("driver=rtl_tcp, address={}", rtl_tcp_config.address)),
+            driver: Some("rtl_tcp".to_string()),
+            tuner: Some(format!("{:?}", tuner)),
+            label: Some(format!("rtl_tcp {}", rtl_tcp_config.address)),
+            ..SdrInfo::default()
+        };
+
+        let mut source = Self {
+            stream,
+            assembler: SampleAssembler::new(SampleFormat::Uint8Iq),
+            byte_buf: vec![0u8; RTL_TCP_MTU * SampleFormat::Uint8Iq.bytes_per_sample()],
+            tuner,
+            gain_count,
+            sdr_info,
+            sdr_config: SdrConfig::default(),
+        };
+
+        let mut initial = serde_json::json!({
+            "center_frequency": rtl_tcp_config.center_frequency_hz,
+            "sample_rate": rtl_tcp_config.sample_rate_hz,
+            "bias_tee": rtl_tcp_config.bias_tee,
+        });
+        match rtl_tcp_config.gain_db {
+            Some(gain) => initial["gain"] = serde_json::json!(gain),
+            None => initial["gain_mode"] = serde_json::json!("agc"),
+        }
+        if let Some(ppm) = rtl_tcp_config.frequency_correction_ppm {
+            initial["frequency_correction"] = serde_json::json!(ppm);
+        }
+        source.config(initial)?;
+
+        Ok(source)
+    }
+
+    pub fn send_command(&mut self, command: RtlTcpCommand, param: u32) -> Result<(), SdrError> {
+        let mut message = [0u8; 5];
+        message[0] = command as u8;
+        message[1..].copy_from_slice(&param.to_be_bytes());
+        self.stream
+            .write_all(&message)
+            .map_err(|e| SdrError::ConfigError(format!("Failed to send {:?}: {}", command, e)))
+    }
+}
+
+impl SampleSource for RtlTcpSource {
+    fn read(&mut self, buf: &mut [Complex32], timeout_us: i64) -> Result<usize, SdrError> {
+        let buf_len = buf.len().min(RTL_TCP_MTU);
+        if self.assembler.available() == 0 {
+            self.stream
+                .set_read_timeout(timeout_duration(timeout_us))
+                .map_err(|e| SdrError::StreamError(e.to_string()))?;
+            let len = (buf_len * SampleFormat::Uint8Iq.bytes_per_sample()).min(self.byte_buf.len());
+            match self.stream.read(&mut self.byte_buf[..len]) {
+                Ok(0) => return Err(SdrError::EndOfStream("rtl_tcp server closed the connection".to_string())),
+                Ok(n) => self.assembler.push(&self.byte_buf[..n]),
+                Err(e) if is_timeout(&e) => return Ok(0),
+                Err(e) => return Err(SdrError::SampleReadError(e.to_string())),
+            }
+        }
+        Ok(self.assembler.take(&mut buf[..buf_len]))
+    }
+
+    fn mtu(&self) -> Result<usize, SdrError> {
+        Ok(RTL_TCP_MTU)
+    }
+
+    fn sample_rate_hz(&self) -> f64 {
+        self.sdr_config.sample_rate_hz as f64
+    }
+
+    fn center_frequency_hz(&self) -> f64 {
+        self.sdr_config.center_frequency_hz as f64
+    }
+}
+
+impl SdrDeviceWrapper for RtlTcpSource {
+    fn get_config(&self) -> SdrConfig {
+        self.sdr_config.clone()
+    }
+
+    fn get_info(&self) -> SdrInfo {
+        self.sdr_info.clone()
+    }
+
+    /// Same keys as the config of the SoapySDR drivers, the bandwidth follows the sample rate in rtl_tcp
+    fn config(&mut self, config: Value) -> Result<(), SdrError> {
+        if let Some(c_freq) = config.get("center_frequency").and_then(Value::as_f64) {
+            self.send_command(RtlTcpCommand::SetFrequency, c_freq.round() as u32)?;
+            self.sdr_config.center_frequency_hz = c_freq as f32;
+        }
+
+        if let Some(s_rate) = config.get("sample_rate").and_then(Value::as_f64) {
+            self.send_command(RtlTcpCommand::SetSampleRate, s_rate.round() as u32)?;
+            self.sdr_config.sample_rate_hz = s_rate as f32;
+            self.sdr_config.bandwidth_hz = s_rate as f32;
+        }
+
+        if let Some(ppm) = config.get("frequency_correction").and_then(Value::as_f64) {
+            self.send_command(RtlTcpCommand::SetFrequencyCorrection, ppm.round() as i32 as u32)?;
+            self.sdr_config.frequency_correction = Some(ppm as f32);
+        }
+
+        let gain = config.get("gain").and_then(Value::as_f64);
+        let agc = config.get("gain_mode").and_then(Value::as_str) == Some("agc");
+        if agc {
+            self.send_command(RtlTcpCommand::SetGainMode, 0)?;
+            self.sdr_config.gain_mode = Some("agc".to_string());
+        } else if let Some(gain) = gain {
+            self.send_command(RtlTcpCommand::SetGainMode, 1)?;
+            self.send_command(RtlTcpCommand::SetGain, (gain * 10.0).round() as i32 as u32)?;
+            self.sdr_config.gain_db = gain as f32;
+            self.sdr_config.gain_mode = Some("manual".to_string());
+        }
+
+        if let Some(bias_tee) = config.get("bias_tee").and_then(Value::as_bool) {
+            self.send_command(RtlTcpCommand::SetBiasTee, bias_tee as u32)?;
+            self.sdr_config.bias_tee = Some(bias_tee);
+        }
+
+        if let Some(pps_enabled) = config.get("pps_enabled").and_then(Value::as_bool) {
+            self.sdr_config.pps_enabled = Some(pps_enabled);
+        }
+
+        Ok(())
+    }
+
+    #[allow(unused_variables)]
+    fn transmit_samples(&self, buf: &mut [&mut [Complex32]]) -> Result<(), SdrError> {
+        Err(SdrError::TransmitError("rtl_tcp is receive only".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    #[test]
+    fn test_rtl_tcp_commands_and_samples() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (cmd_tx, cmd_rx) = mpsc::channel();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut greeting = RTL_TCP_MAGIC.to_vec();
+            greeting.extend_from_slice(&5u32.to_be_bytes()); // R820T
+            greeting.extend_from_slice(&29u32.to_be_bytes());
+            stream.write_all(&greeting).unwrap();
+
+            // Frequency, sample rate, gain mode, gain, bias-tee
+            for _ in 0..5 {
+                let mut command = [0u8; 5];
+                stream.read_exact(&mut command).unwrap();
+                cmd_tx
+                    .send((command[0], u32::from_be_bytes([command[1], command[2], command[3], command[4]])))
+                    .unwrap();
+            }
+            stream.write_all(&[0, 255, 127, 128, 10]).unwrap();
+        });
+
+        let mut source = RtlTcpSource::connect(&RtlTcpConfig {
+            address,
+            center_frequency_hz: 1_575_420_000.0,
+            sample_rate_hz: 2_048_000.0,
+            gain_db: Some(40.2),
+            frequency_correction_ppm: None,
+            bias_tee: true,
+        })
+        .expect("Failed to connect to the loopback rtl_tcp server");
+        assert_eq!(source.tuner, RtlTcpTuner::R820T);
+        assert_eq!(source.gain_count, 29);
+
+        let commands: Vec<(u8, u32)> = cmd_rx.iter().take(5).collect();
+        assert_eq!(
+            commands,
+            vec![
+                (RtlTcpCommand::SetFrequency as u8, 1_575_420_000),
+                (RtlTcpCommand::SetSampleRate as u8, 2_048_000),
+                (RtlTcpCommand::SetGainMode as u8, 1),
+                (RtlTcpCommand::SetGain as u8, 402),
+                (RtlTcpCommand::SetBiasTee as u8, 1),
+            ]
+        );
+        server.join().unwrap();
+
+        let mut buf = vec![Complex32::new(0.0, 0.0); 4];
+        let mut n = 0;
+        while n < 2 {
+            n += source.read(&mut buf[n..], 100_000).unwrap();
+        }
+        assert_eq!(buf[..2], [Complex32::new(-127.5, 127.5), Complex32::new(-0.5, 0.5)]);
+        // The dangling I byte never makes a sample, the server is gone
+        assert!(matches!(source.read(&mut buf, 100_000), Err(SdrError::EndOfStream(_))));
+    }
+
+    #[test]
+    fn test_rtl_tcp_rejects_other_servers() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"HTTP/1.1 400").unwrap();
+        });
+
+        let result = RtlTcpSource::connect(&RtlTcpConfig {
+            address,
+            center_frequency_hz: 1_575_420_000.0,
+            sample_rate_hz: 2_048_000.0,
+            gain_db: None,
+            frequency_correction_ppm: None,
+            bias_tee: false,
+        });
+        assert!(matches!(result, Err(SdrError::DeviceError(_))));
+        server.join().unwrap();
+    }
+}
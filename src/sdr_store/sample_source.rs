@@ -0,0 +1,34 @@
+use crate::sdr_store::sdr_wrapper::SdrError;
+use num_complex::Complex32;
+
+/// Something that happened on the stream besides delivering samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamEvent {
+    Overflow,  // The device or driver dropped samples because they were not read in time
+    TimeError, // The device timestamps are no longer consistent with the samples
+}
+
+/// Backend-agnostic source of complex baseband samples, implemented by SDR drivers,
+/// recordings and simulators alike
+pub trait SampleSource {
+    /// Read samples into the buffer, returns the number of samples written.
+    /// Zero samples is not an error, e.g. when the read timed out.
+    fn read(&mut self, buf: &mut [Complex32], timeout_us: i64) -> Result<usize, SdrError>;
+
+    /// Maximum number of samples returned by one `read`
+    fn mtu(&self) -> Result<usize, SdrError>;
+
+    fn sample_rate_hz(&self) -> f64;
+
+    fn center_frequency_hz(&self) -> f64;
+
+    /// Time of the first sample returned by the last `read`, when the source has one
+    fn timestamp_ns(&self) -> Option<i64> {
+        None
+    }
+
+    /// Next pending stream event, if any
+    fn poll_event(&mut self) -> Option<StreamEvent> {
+        None
+    }
+}
@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Where the time of the samples comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimeSource {
+    Hardware, // Timestamps of the source, PPS disciplined when `pps_enabled`
+    Host,     // Host clock at the first sample, then counted in samples
+}
+
+/// Time of the sample at `sample_index`, later samples are extrapolated at `sample_rate_hz`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SampleTime {
+    pub sample_index: usize,
+    pub time_ns: i64, // Unix time for the host clock, device time for the hardware clock
+    pub sample_rate_hz: f64,
+    pub source: TimeSource,
+    pub pps_enabled: bool, // From `SdrConfig.pps_enabled`
+}
+
+impl SampleTime {
+    pub fn time_ns_at(&self, sample_index: usize) -> i64 {
+        let offset = sample_index as f64 - self.sample_index as f64;
+        self.time_ns + (offset * 1e9 / self.sample_rate_hz).round() as i64
+    }
+
+    /// The same time reference, anchored at `sample_index`
+    pub fn at(&self, sample_index: usize) -> Self {
+        Self { sample_index, time_ns: self.time_ns_at(sample_index), ..*self }
+    }
+}
+
+/// Host time in ns since the Unix epoch, monotonic from the moment the clock is created
+struct HostClock {
+    start: Instant,
+    start_unix_ns: i64,
+}
+
+impl HostClock {
+    fn new() -> Self {
+        let start_unix_ns = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as i64)
+            .unwrap_or_default();
+        Self { start: Instant::now(), start_unix_ns }
+    }
+
+    fn now_ns(&self) -> i64 {
+        self.start_unix_ns + self.start.elapsed().as_nanos() as i64
+    }
+}
+
+/// Gives the samples read by `sdr_thread` a time, emitting a new `SampleTime` only when
+/// the time can't be extrapolated from the previous one
+pub struct SampleClock {
+    sample_rate_hz: f64,
+    pps_enabled: bool,
+    host: HostClock,
+    anchor: Option<SampleTime>,
+}
+
+impl SampleClock {
+    pub fn new(sample_rate_hz: f64, pps_enabled: bool) -> Self {
+        Self { sample_rate_hz, pps_enabled, host: HostClock::new(), anchor: None }
+    }
+
+    /// Forget the time reference, e.g. after losing an unknown number of samples
+    pub fn reset(&mut self) {
+        self.anchor = None;
+    }
+
+    /// Account for `n_samples` read from `sample_index` on, `hardware_ns` being the source time of
+    /// the first one. Returns the new time reference when it changed.
+    pub fn stamp(&mut self, sample_index: usize, n_samples: usize, hardware_ns: Option<i64>) -> Option<SampleTime> {
+        let time = match hardware_ns {
+            Some(time_ns) => SampleTime {
+                sample_index,
+                time_ns,
+                sample_rate_hz: self.sample_rate_hz,
+                source: TimeSource::Hardware,
+                pps_enabled: self.pps_enabled,
+            },
+            None => {
+                if self.anchor.is_some_and(|a| a.source == TimeSource::Host) {
+                    return None;
+                }
+                // The read returns just after its last sample
+                let duration_ns = (n_samples as f64 * 1e9 / self.sample_rate_hz).round() as i64;
+                SampleTime {
+                    sample_index,
+                    time_ns: self.host.now_ns() - duration_ns,
+                    sample_rate_hz: self.sample_rate_hz,
+                    source: TimeSource::Host,
+                    pps_enabled: self.pps_enabled,
+                }
+            }
+        };
+
+        // Half a sample of hardware timestamp jitter is not a new reference
+        let half_sample_ns = 0.5e9 / self.sample_rate_hz;
+        if let Some(anchor) = self.anchor
+            && anchor.source == time.source
+            && ((anchor.time_ns_at(sample_index) - time.time_ns).abs() as f64) < half_sample_ns
+        {
+            return None;
+        }
+        self.anchor = Some(time);
+        Some(time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_clock() {
+        let mut clock = SampleClock::new(1e6, true);
+        let first = clock.stamp(0, 1000, None).expect("The first read sets the time");
+        assert_eq!(first.source, TimeSource::Host);
+        assert!(first.pps_enabled);
+        // Counted in samples from then on
+        assert_eq!(clock.stamp(1000, 1000, None), None);
+        assert_eq!(first.time_ns_at(1500), first.time_ns + 1_500_000);
+        assert_eq!(first.at(1500).time_ns_at(0), first.time_ns);
+
+        clock.reset();
+        assert!(clock.stamp(2000, 1000, None).is_some());
+
+        // Hardware timestamps replace the host clock, and only jumps give a new reference
+        let hw = clock.stamp(3000, 1000, Some(5_000_000)).unwrap();
+        assert_eq!(hw.source, TimeSource::Hardware);
+        assert_eq!(clock.stamp(4000, 1000, Some(6_000_100)), None);
+        let jump = clock.stamp(5000, 1000, Some(9_000_000)).unwrap();
+        assert_eq!(jump.time_ns_at(5100), 9_100_000);
+    }
+}
@@ -1,37 +1,226 @@
 use crate::rf::samples_buffer::SampleComplex;
-use crate::sdr_store::sdr_wrapper::SdrDeviceWrapper;
+use crate::sdr_store::sample_source::{SampleSource, StreamEvent};
+use crate::sdr_store::sample_time::SampleClock;
 use crate::sdr_store::sdr_wrapper::SdrError;
+use crate::sdr_store::sigmf::SigMfRecorder;
+use crate::sdr_store::stream_monitor::{
+    Discontinuity, DiscontinuityCause, StreamMonitor, TimestampGapDetector,
+};
 use num_complex::Complex32;
 use ringbuf::HeapProd;
 use ringbuf::traits::Producer;
+use std::sync::atomic::{AtomicBool, Ordering};
 // use soapysdr::Direction::Rx;
 
 pub fn sdr_thread(
-    dev: &mut impl SdrDeviceWrapper,
+    dev: &mut (impl SampleSource + ?Sized),
     prod: &mut HeapProd<SampleComplex>,
 ) -> Result<(), SdrError> {
-    let mtu: usize = dev
-        .get_rx_stream_mute()
-        .ok_or(SdrError::StreamError(
-            "Rx stream not initialized".to_string(),
-        ))?
-        .mtu()
-        .map_err(|e| SdrError::StreamError(format!("Failed to get RX stream MTU: {}", e)))?;
+    sdr_thread_monitored(dev, prod, None, &StreamMonitor::new(), &AtomicBool::new(false))
+}
+
+/// Same as `sdr_thread`, also writing every sample read from the device to `recorder` and
+/// reporting overflows, timing errors, timestamp gaps and the time of the stream to `monitor`.
+/// Returns at the end of the stream or once `stop` is set.
+pub fn sdr_thread_monitored(
+    dev: &mut (impl SampleSource + ?Sized),
+    prod: &mut HeapProd<SampleComplex>,
+    mut recorder: Option<&mut SigMfRecorder>,
+    monitor: &StreamMonitor,
+    stop: &AtomicBool,
+) -> Result<(), SdrError> {
+    let mtu: usize = dev.mtu()?;
     // let num_channels = dev.num_channels(Rx)?;  // Not really matter for GNSS
     let mut buf = vec![Complex32::new(0.0, 0.0); mtu];
+    let mut gap_detector = TimestampGapDetector::new(dev.sample_rate_hz());
+    let mut clock = SampleClock::new(dev.sample_rate_hz(), monitor.pps_enabled);
+    let mut sample_index: usize = 0; // Samples pushed so far
     loop {
-        let n_samples = dev.read_samples(&mut [&mut buf[..]], 100000)?;
+        if stop.load(Ordering::Relaxed) {
+            if let Some(recorder) = recorder {
+                recorder.flush()?;
+            }
+            return Ok(());
+        }
+        let read = dev.read(&mut buf, 100000);
+
+        // Overflows are reported with zero samples, the gap is before the next ones
+        while let Some(event) = dev.poll_event() {
+            let cause = match event {
+                StreamEvent::Overflow => DiscontinuityCause::DeviceOverflow,
+                StreamEvent::TimeError => DiscontinuityCause::DeviceTimeError,
+            };
+            monitor.report(Discontinuity { sample_index, lost_samples: None, cause });
+            // The sample count no longer tells the time
+            clock.reset();
+        }
+
+        let n_samples = match read {
+            Ok(n) => n,
+            // A recording has been fully replayed
+            Err(SdrError::EndOfStream(_)) => {
+                if let Some(recorder) = recorder {
+                    recorder.flush()?;
+                }
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
         if n_samples > 0 {
+            let timestamp_ns = dev.timestamp_ns();
+            if let Some(timestamp_ns) = timestamp_ns
+                && let Some(jump) = gap_detector.check(timestamp_ns, n_samples)
+            {
+                monitor.report(Discontinuity {
+                    sample_index,
+                    lost_samples: usize::try_from(jump).ok(), // Unknown when the time went backwards
+                    cause: DiscontinuityCause::TimestampGap,
+                });
+            }
+            if let Some(time) = clock.stamp(sample_index, n_samples, timestamp_ns) {
+                monitor.report_time(time);
+            }
+
+            if let Some(rec) = recorder.as_deref_mut()
+                && let Err(e) = rec.write(&buf[..n_samples])
+            {
+                // Losing the recording must not stop the receiver
+                println!("Warning: Recording stopped: {:?}", e);
+                recorder = None;
+            }
+
             let mut started = 0;
-            while started < n_samples {
+            // Samples still waiting for the ring are dropped on stop
+            while started < n_samples && !stop.load(Ordering::Relaxed) {
                 let pushed = prod.push_slice(&buf[started..n_samples]);
                 started += pushed;
 
                 if pushed == 0 {
-                    // Buffer is full, wait a bit
+                    // Buffer is full, wait a bit. Nothing is lost here, but the device
+                    // may overflow meanwhile
+                    monitor.stats.ring_full_events.fetch_add(1, Ordering::Relaxed);
                     std::thread::sleep(std::time::Duration::from_millis(5));
                 }
             }
+            sample_index += n_samples;
+            monitor.stats.samples_read.fetch_add(n_samples as u64, Ordering::Relaxed);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rf::samples_buffer::create_samples_ring_buffer;
+    use ringbuf::traits::Observer;
+
+    /// Reads of 100 samples, stamped at 1 MHz, with 50 samples missing before the third read
+    /// and an overflow before the fifth
+    struct GappySource {
+        reads: usize,
+        timestamp_ns: i64,
+        event: Option<StreamEvent>,
+    }
+
+    impl SampleSource for GappySource {
+        fn read(&mut self, buf: &mut [Complex32], _timeout_us: i64) -> Result<usize, SdrError> {
+            self.reads += 1;
+            match self.reads {
+                1 => self.timestamp_ns = 0,
+                3 => self.timestamp_ns += 150_000,
+                5 => {
+                    self.event = Some(StreamEvent::Overflow);
+                    return Ok(0);
+                }
+                7 => return Err(SdrError::EndOfStream("done".to_string())),
+                _ => self.timestamp_ns += 100_000,
+            }
+            buf[..100].fill(Complex32::new(self.reads as f32, 0.0));
+            Ok(100)
+        }
+
+        fn mtu(&self) -> Result<usize, SdrError> {
+            Ok(100)
+        }
+
+        fn sample_rate_hz(&self) -> f64 {
+            1e6
+        }
+
+        fn center_frequency_hz(&self) -> f64 {
+            0.0
+        }
+
+        fn timestamp_ns(&self) -> Option<i64> {
+            Some(self.timestamp_ns)
+        }
+
+        fn poll_event(&mut self) -> Option<StreamEvent> {
+            self.event.take()
+        }
+    }
+
+    #[test]
+    fn test_sdr_thread_reports_discontinuities() {
+        let mut source = GappySource { reads: 0, timestamp_ns: 0, event: None };
+        let mut ring_buffer = create_samples_ring_buffer(1000);
+        let monitor = StreamMonitor::new();
+        sdr_thread_monitored(&mut source, &mut ring_buffer.producer, None, &monitor, &AtomicBool::new(false)).unwrap();
+
+        let discontinuities: Vec<Discontinuity> = monitor.take_discontinuities().collect();
+        assert_eq!(
+            discontinuities,
+            vec![
+                Discontinuity {
+                    sample_index: 200,
+                    lost_samples: Some(50),
+                    cause: DiscontinuityCause::TimestampGap,
+                },
+                Discontinuity {
+                    sample_index: 400,
+                    lost_samples: None,
+                    cause: DiscontinuityCause::DeviceOverflow,
+                },
+            ]
+        );
+        assert_eq!(monitor.stats.samples_read.load(Ordering::Relaxed), 500);
+
+        // The timestamps only give a new time reference after the gap and the overflow
+        let times: Vec<(usize, i64)> = monitor.take_times().map(|t| (t.sample_index, t.time_ns)).collect();
+        assert_eq!(times, vec![(0, 0), (200, 250_000), (400, 450_000)]);
+        assert_eq!(ring_buffer.consumer.occupied_len(), 500);
+    }
+
+    #[test]
+    fn test_sdr_thread_hardware_time() {
+        use crate::sdr_mock::device_mock::{MOCK_MTU, MockDevice};
+        use crate::sdr_store::profiled_sdr::ProfiledSdr;
+        use crate::sdr_store::rtl_sdr::RTL_SDR_PROFILE;
+        use crate::sdr_store::sample_time::TimeSource;
+
+        let mut rtl_sdr = ProfiledSdr::<MockDevice>::new("driver=rtlsdr", &RTL_SDR_PROFILE).unwrap();
+        rtl_sdr.backend.sample_rate_hz = 2.048e6;
+        rtl_sdr.sdr_config.sample_rate_hz = 2.048e6;
+        rtl_sdr.backend.next_time_ns = Some(1_000_000_000);
+        rtl_sdr.start_sdr(&[0], None).unwrap();
+
+        let mut ring_buffer = create_samples_ring_buffer(16 * MOCK_MTU);
+        let monitor = StreamMonitor::new();
+        let stop = AtomicBool::new(false);
+        std::thread::scope(|s| {
+            s.spawn(|| sdr_thread_monitored(&mut rtl_sdr, &mut ring_buffer.producer, None, &monitor, &stop));
+            while monitor.stats.samples_read.load(Ordering::Relaxed) < 8 * MOCK_MTU as u64 {
+                std::thread::yield_now();
+            }
+            stop.store(true, Ordering::Relaxed);
+        });
+
+        // The device time of the first block, the later ones follow from the sample count
+        let times: Vec<_> = monitor.take_times().collect();
+        assert_eq!(times.len(), 1);
+        assert_eq!(times[0].sample_index, 0);
+        assert_eq!(times[0].time_ns, 1_000_000_000);
+        assert_eq!(times[0].source, TimeSource::Hardware);
+        assert_eq!(monitor.take_discontinuities().count(), 0);
+    }
+}
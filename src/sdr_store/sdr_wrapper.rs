@@ -5,9 +5,24 @@ use std::hash::Hash;
 use serde::{Serialize, Deserialize};
 use serde_json::Value;
 use strum_macros::EnumIter;
-use soapysdr::{Args, Device, Direction, RxStream, TxStream, Range};
+#[cfg(feature = "soapy")]
+use soapysdr::Args;
 use num_complex::Complex32;
-use crate::sdr_store::rtl_sdr::RtlSdr;
+use crate::config::app_config::AppConfig;
+use crate::sdr_store::file_source::FileSource;
+use crate::sdr_store::net_source::NetSource;
+use crate::sdr_store::rtl_tcp::RtlTcpSource;
+#[cfg(feature = "soapy")]
+use crate::sdr_store::{
+    airspy::AIRSPY_PROFILE, bladerf::BLADERF_PROFILE, hackrf::HACKRF_PROFILE, lime_sdr::LIME_SDR_PROFILE,
+    pluto_sdr::PLUTO_SDR_PROFILE, profiled_sdr::ProfiledSdr, rtl_sdr::RTL_SDR_PROFILE, usrp::USRP_PROFILE,
+};
+use crate::sdr_store::sample_source::SampleSource;
+use crate::simulator::sim_source::SimSource;
+#[cfg(feature = "soapy")]
+use crate::sdr_store::device_selector::DeviceSpec;
+#[cfg(feature = "soapy")]
+use crate::sdr_store::soapy_backend::{SoapyBackend, map_args_to_info};
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, EnumIter)]
 pub enum DriverName {
@@ -17,10 +32,41 @@ pub enum DriverName {
     LimeSdr,
     PlutoSdr,
     AirSpy,
+    Usrp,
     Unknown,
 }
 
-#[derive(Debug, Clone, Default)]
+impl DriverName {
+    /// Map the `device` name of the app config, e.g. "limesdr"
+    pub fn from_device_name(name: &str) -> Self {
+        match name {
+            "rtlsdr" => DriverName::RtlSdr,
+            "bladerf" => DriverName::BladeRf,
+            "hackrf" => DriverName::HackRf,
+            "limesdr" | "lime" => DriverName::LimeSdr,
+            "plutosdr" => DriverName::PlutoSdr,
+            "airspy" => DriverName::AirSpy,
+            "usrp" | "uhd" => DriverName::Usrp,
+            _ => DriverName::Unknown,
+        }
+    }
+
+    /// Driver key of the SoapySDR module
+    pub fn soapy_driver(&self) -> Option<&'static str> {
+        match self {
+            DriverName::RtlSdr => Some("rtlsdr"),
+            DriverName::BladeRf => Some("bladerf"),
+            DriverName::HackRf => Some("hackrf"),
+            DriverName::LimeSdr => Some("lime"),
+            DriverName::PlutoSdr => Some("plutosdr"),
+            DriverName::AirSpy => Some("airspy"),
+            DriverName::Usrp => Some("uhd"),
+            DriverName::Unknown => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SdrInfo {
     pub long_args: Option<String>,
     pub manufacturer: Option<String>,
@@ -34,6 +80,32 @@ pub struct SdrInfo {
     pub product: Option<String>,
 }
 
+impl SdrInfo {
+    /// Parse a SoapySDR style argument string, e.g. "driver=rtlsdr, serial=00000001, tuner=Rafael Micro R820T"
+    pub fn from_args_str(args: &str) -> Self {
+        let mut info = SdrInfo::default();
+        if !args.trim().is_empty() {
+            info.long_args = Some(args.to_string());
+        }
+        for pair in args.split(',') {
+            if let Some((key, value)) = pair.split_once('=') {
+                let value = Some(value.trim().to_string());
+                match key.trim() {
+                    "tuner" => info.tuner = value,
+                    "manufacturer" => info.manufacturer = value,
+                    "model" => info.model = value,
+                    "serial" => info.serial_number = value,
+                    "driver" => info.driver = value,
+                    "label" => info.label = value,
+                    "product" => info.product = value,
+                    _ => {}
+                }
+            }
+        }
+        info
+    }
+}
+
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct SdrConfig {
@@ -45,194 +117,52 @@ pub struct SdrConfig {
     pub antennas: Option<Vec<String>>, // Antennas
     pub gain_mode: Option<String>, // Gain mode (e.g., 'manual', 'agc')
     pub pps_enabled: Option<bool>, // PPS (Pulse Per Second) enabled
+    pub bias_tee: Option<bool>, // DC power on the antenna port for active antennas
+    pub gain_stages: Option<HashMap<String, f32>>, // Gain per stage in dB (e.g. LNA, VGA, MIX), applied after `gain_db`
+    pub clock_source: Option<String>, // Reference clock (e.g. 'internal', 'external', 'gpsdo')
     pub extra_config: Option<HashMap<String, String>>, // Additional configuration options
 }
 
-pub trait SdrDeviceWrapper {
-    fn device(&self) -> Result<&Device, SdrError>;
-
-    fn device_mut(&mut self) -> Result<&mut Device, SdrError>;
-
-    fn get_config(&self) -> SdrConfig;
-
-    fn map_args_to_info(args: Args) -> Result<SdrInfo, SdrError>
-    where 
-        Self: Sized {
-        let long_args = args.to_string();
-        let mut info  = SdrInfo::default();
-        info.long_args = Some(long_args);
-        info.tuner = args.get("tuner").map(|s| s.to_string());
-        info.manufacturer = args.get("manufacturer").map(|s| s.to_string());
-        info.model = args.get("model").map(|s| s.to_string());
-        info.serial_number = args.get("serial").map(|s| s.to_string());
-        info.driver = args.get("driver").map(|s| s.to_string());
-        info.label = args.get("label").map(|s| s.to_string());
-        info.product = args.get("product").map(|s| s.to_string());
-
-        Ok(info)
-    }
-
-    /// List available antennas for the given direction and channel.
-    fn antennas(&self, direction: Direction, channel: usize) -> Result<Vec<String>, SdrError> {
-        self.device()?.antennas(direction, channel).map_err(|e| SdrError::OtherError(e.to_string()))
-    }
-
-    /// Get the selected antenna for the given direction and channel.
-    fn antenna(&self, direction: Direction, channel: usize) -> Result<String, SdrError> {
-        self.device()?.antenna(direction, channel).map_err(|e| SdrError::OtherError(e.to_string()))
-    }
-
-    /// Set the selected antenna for the given direction and channel.
-    fn set_antenna(&self, direction: Direction, channel: usize, antenna: &str) -> Result<(), SdrError> {
-        self.device()?.set_antenna(direction, channel, antenna).map_err(|e| SdrError::OtherError(e.to_string()))
-    }
-
-    /// Get the baseband filter width of the chain in Hz
-    fn bandwidth(&self, direction: Direction, channel: usize) -> Result<f64, SdrError> {
-        self.device()?.bandwidth(direction, channel).map_err(|e| SdrError::OtherError(e.to_string()))
-    }
-
-    /// Get the range of valid bandwidths for the given direction and channel.
-    fn bandwidth_range(&self, direction: Direction, channel: usize) -> Result<Vec<Range>, SdrError> {
-        self.device()?.bandwidth_range(direction, channel).map_err(|e| SdrError::OtherError(e.to_string()))
-    }
-
-    /// Set the baseband filter width of the chain in Hz
-    fn set_bandwidth(&self, direction: Direction, channel: usize, bandwidth: f64) -> Result<(), SdrError> {
-        self.device()?.set_bandwidth(direction, channel, bandwidth).map_err(|e| SdrError::OtherError(e.to_string()))
-    }
-
-    fn driver_key(&self) -> Result<String, SdrError> {
-        self.device()?.driver_key().map_err(|e| SdrError::OtherError(e.to_string()))
-    }
-
-    /// Returns the down-conversion frequency in Hz.
-    fn frequency(&self, direction: Direction, channel: usize) -> Result<f64, SdrError> {
-        self.device()?.frequency(direction, channel).map_err(|e| SdrError::OtherError(e.to_string()))
-    }
-
-    /// Get the range of valid frequencies for the given direction and channel.
-    fn frequency_range(&self, direction: Direction, channel: usize) -> Result<Vec<Range>, SdrError> {
-        self.device()?.frequency_range(direction, channel).map_err(|e| SdrError::OtherError(e.to_string()))
-    }
-
-    /// Set the down-conversion frequency in Hz.
-    fn set_frequency(&self, direction: Direction, channel: usize, frequency: f64) -> Result<(), SdrError> {
-        self.device()?.set_frequency(direction, channel, frequency, Args::from("")).map_err(|e| SdrError::OtherError(e.to_string()))
-    }
-
-    /// Get the overall value of the gain elements in a chain in dB.
-    fn gain(&self, direction: Direction, channel: usize) -> Result<f64, SdrError> {
-        self.device()?.gain(direction, channel).map_err(|e| SdrError::OtherError(e.to_string()))
-    }
-
-    /// Get the range of valid gain values for the given direction and channel.
-    fn gain_range(&self, direction: Direction, channel: usize) -> Result<Range, SdrError> {
-        self.device()?.gain_range(direction, channel).map_err(|e| SdrError::OtherError(e.to_string()))
-    }
-
-    /// Set the overall amplification in a chain.
-    fn set_gain(&self, direction: Direction, channel: usize, gain: f64) -> Result<(), SdrError> {
-        self.device()?.set_gain(direction, channel, gain).map_err(|e| SdrError::OtherError(e.to_string()))
-    }
-
-    /// Returns true if automatic gain control is enabled
-    fn gain_mode(&self, direction: Direction, channel: usize) -> Result<bool, SdrError> {
-        self.device()?.gain_mode(direction, channel).map_err(|e| SdrError::OtherError(e.to_string()))
-    }
-
-    /// Enable or disable automatic gain control.
-    fn set_gain_mode(&self, direction: Direction, channel: usize, automatic: bool) -> Result<(), SdrError> {
-        self.device()?.set_gain_mode(direction, channel, automatic).map_err(|e| SdrError::OtherError(e.to_string()))
-    }
-
-    /// Query a dictionary of available device information.
-    /// This dictionary can any number of values like vendor name, product name, revisions, serials…
-    /// This information can be displayed to the user to help identify the instantiated device.
-    fn hardware_info(&self) -> Result<Args, SdrError> {
-        self.device()?.hardware_info().map_err(|e| SdrError::OtherError(e.to_string()))
-    }
-
-    /// List available tunable elements in the chain.
-    /// Elements should be in order RF to baseband.
-    fn list_frequencies(&self, direction: Direction, channel: usize) -> Result<Vec<String>, SdrError> {
-        let freqs = self.device()?.list_frequencies(direction, channel).map_err(|e| SdrError::OtherError(e.to_string()))?;
-        Ok(freqs)
-    }
-
-    /// Get the number of channels for the given direction.
-    fn num_channels(&self, direction: Direction) -> Result<usize, SdrError> {
-        self.device()?.num_channels(direction).map_err(|e| SdrError::OtherError(e.to_string()))
-    }
-
-    fn rx_stream(&self, channels: &[usize]) -> Result<RxStream<Complex32>, SdrError> {
-        self.device()?.rx_stream(channels).map_err(|e| SdrError::OtherError(e.to_string()))
-    }
-
-    /// Get the baseband sample rate of the chain in samples per second.
-    fn sample_rate(&self, direction: Direction, channel: usize) -> Result<f64, SdrError> {
-        self.device()?.sample_rate(direction, channel).map_err(|e| SdrError::OtherError(e.to_string()))
-    }
-
-    /// Get the range of valid sample rates for the given direction and channel.
-    fn get_sample_rate_range(&self, direction: Direction, channel: usize) -> Result<Vec<Range>, SdrError> {
-        self.device()?.get_sample_rate_range(direction, channel).map_err(|e| SdrError::OtherError(e.to_string()))
-    }
-
-    /// Set the baseband sample rate of the chain in samples per second.
-    fn set_sample_rate(&self, direction: Direction, channel: usize, sample_rate: f64) -> Result<(), SdrError> {
-        self.device()?.set_sample_rate(direction, channel, sample_rate).map_err(|e| SdrError::OtherError(e.to_string()))
+impl SdrConfig {
+    /// The key/value pairs of `SdrDeviceWrapper::config`, e.g. "center_frequency" for `center_frequency_hz`
+    pub fn device_config(&self) -> Value {
+        serde_json::json!({
+            "center_frequency": self.center_frequency_hz,
+            "sample_rate": self.sample_rate_hz,
+            "gain": self.gain_db,
+            "bandwidth": self.bandwidth_hz,
+            "frequency_correction": self.frequency_correction,
+            "antennas": self.antennas,
+            "gain_mode": self.gain_mode,
+            "pps_enabled": self.pps_enabled,
+            "bias_tee": self.bias_tee,
+            "gain_stages": self.gain_stages,
+            "clock_source": self.clock_source,
+        })
     }
+}
 
-    fn tx_stream(&self, channels: &[usize]) -> Result<TxStream<Complex32>, SdrError> {
-        self.device()?.tx_stream(channels).map_err(|e| SdrError::OtherError(e.to_string()))
-    }
+/// An SDR front-end: a sample source which can also be configured
+pub trait SdrDeviceWrapper: SampleSource {
+    fn get_config(&self) -> SdrConfig;
 
-    /// Get a mutable reference to the RxStream.
-    fn get_rx_stream_mute(&mut self) -> Option<&mut RxStream<Complex32>>;
+    fn get_info(&self) -> SdrInfo;
 
     /// Set configuration options using key/value pairs.
     fn config(&mut self, config: Value) -> Result<(), SdrError>;
 
-    /// Reading samples into the buffer
-    fn read_samples(&mut self, buf: &mut [&mut [Complex32]], timeout_us: i64) -> Result<usize, SdrError>;
-
     /// Transmitting samples from the buffer
     fn transmit_samples(&self, buf: &mut [&mut [Complex32]]) -> Result<(), SdrError>;
-}
 
-impl SdrDeviceWrapper for Device {
-    fn device(&self) -> Result<&Device, SdrError> {
-        Ok(self)
-    }
-
-    fn device_mut(&mut self) -> Result<&mut Device, SdrError> {
-        Ok(self)
-    }
-
-    fn get_config(&self) -> SdrConfig {
-        SdrConfig::default()
-    }
-
-    #[allow(unused_variables)]
-    fn config(&mut self, config: Value) -> Result<(), SdrError> {
+    /// Activate the RX stream before the first read, sources which always stream don't need to
+    fn start_stream(&mut self) -> Result<(), SdrError> {
         Ok(())
     }
 
-    #[allow(unused_variables)]
-    fn read_samples(&mut self, buf: &mut [&mut [Complex32]], timeout_us: i64) -> Result<usize, SdrError> {
-        Ok(buf.len())
-    }
-
-    #[allow(unused_variables)]
-    fn transmit_samples(&self, buf: &mut [&mut [Complex32]]) -> Result<(), SdrError> {
+    /// Deactivate the RX stream once reading has stopped
+    fn stop_stream(&mut self) -> Result<(), SdrError> {
         Ok(())
     }
-
-    fn get_rx_stream_mute(&mut self) -> Option<&mut RxStream<Complex32>> {
-        None
-    }
-
 }
 
 // pub fn create_device(sdr: DriverName, args: Args) -> Result<Box<dyn SdrDevice + Send>, SdrError> {
@@ -243,29 +173,71 @@ impl SdrDeviceWrapper for Device {
 // }
 
 
-pub fn start_device_with_name(device_name: String) -> Result<impl SdrDeviceWrapper, SdrError> {
-    let mut devs_args: Vec<Args> = Vec::new();
+/// Open a SoapySDR device, `device` is a device name optionally followed by a selector,
+/// see `DeviceSpec::parse`
+#[cfg(feature = "soapy")]
+pub fn start_device_with_name(device: String) -> Result<Box<dyn SdrDeviceWrapper + Send>, SdrError> {
+    let spec = DeviceSpec::parse(&device)?;
+    let driver_name = DriverName::from_device_name(&spec.device_name);
+    let driver = driver_name
+        .soapy_driver()
+        .ok_or(SdrError::DeviceNotFound(format!("Driver not supported: {}", spec.device_name)))?;
+
     let mut args = Args::new();
-    args.set("driver", device_name.clone());
-    for dev in soapysdr::enumerate(args).map_err(|e| SdrError::OtherError(e.to_string()))? {
-        devs_args.push(dev);
-    }
-    if devs_args.is_empty() {
-        return Err(SdrError::DeviceNotFound(format!("No device found for driver: {}", device_name)));
+    args.set("driver", driver);
+    let devs_args: Vec<Args> = soapysdr::enumerate(args).map_err(|e| SdrError::OtherError(e.to_string()))?;
+    let candidates: Vec<SdrInfo> = devs_args.iter().map(map_args_to_info).collect();
+    let selected = spec.select(&candidates)?;
+    let dev_args: Args = devs_args[selected].iter().collect();
+
+    match driver_name {
+        DriverName::RtlSdr => Ok(Box::new(ProfiledSdr::<SoapyBackend>::new(dev_args, &RTL_SDR_PROFILE)?)),
+        DriverName::HackRf => Ok(Box::new(ProfiledSdr::<SoapyBackend>::new(dev_args, &HACKRF_PROFILE)?)),
+        DriverName::AirSpy => Ok(Box::new(ProfiledSdr::<SoapyBackend>::new(dev_args, &AIRSPY_PROFILE)?)),
+        DriverName::LimeSdr => Ok(Box::new(ProfiledSdr::<SoapyBackend>::new(dev_args, &LIME_SDR_PROFILE)?)),
+        DriverName::PlutoSdr => Ok(Box::new(ProfiledSdr::<SoapyBackend>::new(dev_args, &PLUTO_SDR_PROFILE)?)),
+        DriverName::BladeRf => Ok(Box::new(ProfiledSdr::<SoapyBackend>::new(dev_args, &BLADERF_PROFILE)?)),
+        DriverName::Usrp => Ok(Box::new(ProfiledSdr::<SoapyBackend>::new(dev_args, &USRP_PROFILE)?)),
+        DriverName::Unknown => Err(SdrError::DeviceNotFound(format!("Driver not supported: {}", spec.device_name))),
     }
-    else {
-        if devs_args.len() > 1 {
-            println!("Warning: Multiple devices found for driver: {}. Using the first one.", device_name);
-        }
-        let first_dev_args = devs_args[0].iter();
+}
 
-        match device_name.as_str() {
-            "rtlsdr" => {
-                let rtl_sdr = RtlSdr::<Device>::new(first_dev_args.collect())?;
-                Ok(rtl_sdr)
-            },
-            _ => Err(SdrError::DeviceNotFound(format!("Driver not supported: {}", device_name))),
+/// Open the sample source selected by `AppConfig.device`, either a recording (`device = "file"`)
+/// or a SoapySDR device
+pub fn start_device(app_config: &AppConfig) -> Result<Box<dyn SdrDeviceWrapper + Send>, SdrError> {
+    match app_config.device.as_str() {
+        // The [file] section of a SigMF recording is filled from its metadata
+        "file" | "sigmf" => {
+            let file_config = app_config.file.as_ref().ok_or(SdrError::ConfigError(
+                format!("A [{}] section is required for device = \"{}\"", app_config.device, app_config.device),
+            ))?;
+            Ok(Box::new(FileSource::new(file_config)?))
+        }
+        "sim" => {
+            let sim_config = app_config.sim.as_ref().ok_or(SdrError::ConfigError(
+                "A [sim] section is required for device = \"sim\"".to_string(),
+            ))?;
+            Ok(Box::new(SimSource::new(sim_config)))
+        }
+        "rtl_tcp" => {
+            let rtl_tcp_config = app_config.rtl_tcp.as_ref().ok_or(SdrError::ConfigError(
+                "A [rtl_tcp] section is required for device = \"rtl_tcp\"".to_string(),
+            ))?;
+            Ok(Box::new(RtlTcpSource::connect(rtl_tcp_config)?))
+        }
+        "net" => {
+            let net_config = app_config.net.as_ref().ok_or(SdrError::ConfigError(
+                "A [net] section is required for device = \"net\"".to_string(),
+            ))?;
+            Ok(Box::new(NetSource::new(net_config)?))
         }
+        #[cfg(feature = "soapy")]
+        _ => start_device_with_name(app_config.device.clone()),
+        #[cfg(not(feature = "soapy"))]
+        _ => Err(SdrError::DeviceNotFound(format!(
+            "Device {} requires the \"soapy\" feature",
+            app_config.device
+        ))),
     }
 }
 
@@ -277,6 +249,7 @@ pub enum SdrError {
     StreamError(String),
     SampleReadError(String),
     TransmitError(String),
+    EndOfStream(String),
     OtherError(String),
 }
 
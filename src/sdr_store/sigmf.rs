@@ -0,0 +1,365 @@
+use crate::constants::gps_property_constants::GPS_L1_FREQ_HZ;
+use crate::sdr_store::file_source::{FileSourceConfig, SampleFormat};
+use crate::sdr_store::sdr_wrapper::{SdrConfig, SdrError, SdrInfo};
+use chrono::{SecondsFormat, Utc};
+use num_complex::Complex32;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+pub const SIGMF_VERSION: &str = "1.0.0";
+
+/// Namespace of the non-core metadata fields written by this receiver
+pub const SIGMF_EXTENSION: &str = "gnss_sdr_rs";
+
+/// Datatype of the recorded samples, the pipeline works on complex f32
+pub const SIGMF_RECORD_DATATYPE: &str = "cf32_le";
+
+/// `[record]` section: tap the sample streams into SigMF recordings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigMfRecordConfig {
+    pub path: String, // Base path, ".sigmf-data" and ".sigmf-meta" are appended
+    #[serde(default = "default_record_raw")]
+    pub raw: bool, // Samples as read from the device by `sdr_thread`
+    #[serde(default)]
+    pub frontend: bool, // Samples after the digital frontend, written to "<path>_frontend"
+}
+
+fn default_record_raw() -> bool {
+    true
+}
+
+impl SigMfRecordConfig {
+    pub fn raw_path(&self) -> String {
+        self.path.clone()
+    }
+
+    pub fn frontend_path(&self) -> String {
+        format!("{}_frontend", self.path)
+    }
+}
+
+/// `[sigmf]` section: replay a SigMF recording, used when device = "sigmf"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigMfPlaybackConfig {
+    pub path: String, // The ".sigmf-meta" or ".sigmf-data" file, or the base path
+    pub playback_rate_hz: Option<f32>, // Samples per second, None to read as fast as possible
+    #[serde(default)]
+    pub repeat: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigMfExtension {
+    pub name: String,
+    pub version: String,
+    pub optional: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigMfGlobal {
+    #[serde(rename = "core:datatype")]
+    pub datatype: String,
+    #[serde(rename = "core:sample_rate", default, skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<f64>,
+    #[serde(rename = "core:version")]
+    pub version: String,
+    #[serde(rename = "core:hw", default, skip_serializing_if = "Option::is_none")]
+    pub hw: Option<String>,
+    #[serde(rename = "core:recorder", default, skip_serializing_if = "Option::is_none")]
+    pub recorder: Option<String>,
+    #[serde(rename = "core:description", default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(rename = "core:extensions", default, skip_serializing_if = "Vec::is_empty")]
+    pub extensions: Vec<SigMfExtension>,
+    #[serde(rename = "gnss_sdr_rs:stream", default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<String>, // "raw" or "frontend"
+    #[serde(rename = "gnss_sdr_rs:sdr_info", default, skip_serializing_if = "Option::is_none")]
+    pub sdr_info: Option<SdrInfo>,
+    #[serde(rename = "gnss_sdr_rs:sdr_config", default, skip_serializing_if = "Option::is_none")]
+    pub sdr_config: Option<SdrConfig>, // Gains, bandwidth, antenna, ... at the start of the recording
+}
+
+impl SigMfGlobal {
+    /// Global fields of a recording of `stream` from the device described by `sdr_info` and `sdr_config`
+    pub fn new(stream: &str, sample_rate_hz: f64, sdr_info: &SdrInfo, sdr_config: &SdrConfig) -> Self {
+        Self {
+            datatype: SIGMF_RECORD_DATATYPE.to_string(),
+            sample_rate: Some(sample_rate_hz),
+            version: SIGMF_VERSION.to_string(),
+            hw: sdr_info.label.clone().or(sdr_info.driver.clone()),
+            recorder: Some(format!("gnss-sdr-rs {}", env!("CARGO_PKG_VERSION"))),
+            description: Some(format!("GPS L1 {} samples", stream)),
+            extensions: vec![SigMfExtension {
+                name: SIGMF_EXTENSION.to_string(),
+                version: "0.1.0".to_string(),
+                optional: true,
+            }],
+            stream: Some(stream.to_string()),
+            sdr_info: Some(sdr_info.clone()),
+            sdr_config: Some(sdr_config.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigMfCapture {
+    #[serde(rename = "core:sample_start")]
+    pub sample_start: u64,
+    #[serde(rename = "core:frequency", default, skip_serializing_if = "Option::is_none")]
+    pub frequency: Option<f64>,
+    #[serde(rename = "core:datetime", default, skip_serializing_if = "Option::is_none")]
+    pub datetime: Option<String>, // ISO 8601 UTC time of the first sample of the capture
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigMfMeta {
+    pub global: SigMfGlobal,
+    pub captures: Vec<SigMfCapture>,
+    #[serde(default)]
+    pub annotations: Vec<serde_json::Value>,
+}
+
+/// Paths of the metadata and data files of the recording at `path`, with or without extension
+pub fn sigmf_paths(path: &str) -> (PathBuf, PathBuf) {
+    let base = path
+        .strip_suffix(".sigmf-meta")
+        .or(path.strip_suffix(".sigmf-data"))
+        .unwrap_or(path);
+    (
+        PathBuf::from(format!("{}.sigmf-meta", base)),
+        PathBuf::from(format!("{}.sigmf-data", base)),
+    )
+}
+
+/// Sample format of a SigMF datatype, None for the datatypes a `FileSource` can't read
+pub fn sample_format_from_datatype(datatype: &str) -> Option<SampleFormat> {
+    match datatype {
+        "cf32_le" => Some(SampleFormat::Complex32),
+        "ci16_le" => Some(SampleFormat::Int16Iq),
+        "ci8" => Some(SampleFormat::Int8Iq),
+        "cu8" => Some(SampleFormat::Uint8Iq),
+        "ri8" => Some(SampleFormat::Int8Real),
+        _ => None,
+    }
+}
+
+impl SigMfMeta {
+    pub fn from_file(path: &str) -> Result<Self, SdrError> {
+        let (meta_path, _) = sigmf_paths(path);
+        let meta_str = std::fs::read_to_string(&meta_path).map_err(|e| {
+            SdrError::DeviceNotFound(format!("Failed to read {}: {}", meta_path.display(), e))
+        })?;
+        serde_json::from_str(&meta_str).map_err(|e| {
+            SdrError::ConfigError(format!("Invalid SigMF metadata {}: {}", meta_path.display(), e))
+        })
+    }
+
+    /// Centre frequency of the first capture, L1 when the recording doesn't say
+    pub fn center_frequency_hz(&self) -> f64 {
+        self.captures
+            .first()
+            .and_then(|c| c.frequency)
+            .unwrap_or(GPS_L1_FREQ_HZ as f64)
+    }
+
+    /// Configuration of a `FileSource` replaying this recording
+    pub fn file_source_config(&self, playback: &SigMfPlaybackConfig) -> Result<FileSourceConfig, SdrError> {
+        let format = sample_format_from_datatype(&self.global.datatype).ok_or(SdrError::ConfigError(
+            format!("SigMF datatype {} is not supported", self.global.datatype),
+        ))?;
+        let sample_rate_hz = self.global.sample_rate.ok_or(SdrError::ConfigError(
+            "SigMF recording has no core:sample_rate".to_string(),
+        ))?;
+        let (_, data_path) = sigmf_paths(&playback.path);
+        Ok(FileSourceConfig {
+            path: data_path.to_string_lossy().to_string(),
+            format,
+            sample_rate_hz: sample_rate_hz as f32,
+            freq_if_hz: (self.center_frequency_hz() - GPS_L1_FREQ_HZ as f64) as f32,
+            playback_rate_hz: playback.playback_rate_hz,
+            repeat: playback.repeat,
+        })
+    }
+}
+
+/// Writes a sample stream to a SigMF recording, the metadata is rewritten whenever a capture starts
+pub struct SigMfRecorder {
+    writer: BufWriter<File>,
+    byte_buf: Vec<u8>,
+    meta_path: PathBuf,
+    pub meta: SigMfMeta,
+    pub samples_written: u64,
+}
+
+impl SigMfRecorder {
+    pub fn create(path: &str, global: SigMfGlobal, center_frequency_hz: f64) -> Result<Self, SdrError> {
+        let (meta_path, data_path) = sigmf_paths(path);
+        let file = File::create(&data_path).map_err(|e| {
+            SdrError::OtherError(format!("Failed to create {}: {}", data_path.display(), e))
+        })?;
+        let recorder = Self {
+            writer: BufWriter::new(file),
+            byte_buf: Vec::new(),
+            meta_path,
+            meta: SigMfMeta {
+                global,
+                captures: vec![SigMfCapture {
+                    sample_start: 0,
+                    frequency: Some(center_frequency_hz),
+                    datetime: None,
+                }],
+                annotations: Vec::new(),
+            },
+            samples_written: 0,
+        };
+        recorder.write_meta()?;
+        Ok(recorder)
+    }
+
+    fn write_meta(&self) -> Result<(), SdrError> {
+        let meta_str = serde_json::to_string_pretty(&self.meta)
+            .map_err(|e| SdrError::OtherError(e.to_string()))?;
+        std::fs::write(&self.meta_path, meta_str).map_err(|e| {
+            SdrError::OtherError(format!("Failed to write {}: {}", self.meta_path.display(), e))
+        })
+    }
+
+    /// Append samples, the first ones of a capture stamp its datetime
+    pub fn write(&mut self, samples: &[Complex32]) -> Result<(), SdrError> {
+        if let Some(capture) = self.meta.captures.last_mut()
+            && capture.datetime.is_none()
+        {
+            capture.datetime = Some(Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true));
+            self.write_meta()?;
+        }
+
+        self.byte_buf.clear();
+        for s in samples {
+            self.byte_buf.extend_from_slice(&s.re.to_le_bytes());
+            self.byte_buf.extend_from_slice(&s.im.to_le_bytes());
+        }
+        self.writer
+            .write_all(&self.byte_buf)
+            .map_err(|e| SdrError::OtherError(format!("Failed to write samples: {}", e)))?;
+        self.samples_written += samples.len() as u64;
+        Ok(())
+    }
+
+    /// Start a new capture at the next sample, e.g. after retuning
+    pub fn start_capture(&mut self, center_frequency_hz: f64) -> Result<(), SdrError> {
+        self.meta.captures.push(SigMfCapture {
+            sample_start: self.samples_written,
+            frequency: Some(center_frequency_hz),
+            datetime: None,
+        });
+        self.write_meta()
+    }
+
+    pub fn flush(&mut self) -> Result<(), SdrError> {
+        self.writer
+            .flush()
+            .map_err(|e| SdrError::OtherError(format!("Failed to flush samples: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rf::samples_buffer::create_samples_ring_buffer;
+    use crate::sdr_store::file_source::FileSource;
+    use crate::sdr_store::sample_source::SampleSource;
+    use crate::sdr_store::sdr_thread::sdr_thread_monitored;
+    use crate::sdr_store::stream_monitor::StreamMonitor;
+    use crate::sdr_store::sdr_wrapper::SdrDeviceWrapper;
+    use crate::simulator::signal_generator::{SimConfig, SimSatellite};
+    use crate::simulator::sim_source::SimSource;
+    use ringbuf::traits::Consumer;
+    use std::sync::atomic::AtomicBool;
+
+    fn temp_base(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("gnss_sdr_rs_{}_{}", std::process::id(), name))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let mut sim_config = SimConfig::new(2_048_000.0, 0.0, 3);
+        sim_config.duration_s = Some(0.01);
+        sim_config.satellites.push(SimSatellite::new(5, 10.0, 1000.0, 45.0));
+        let mut source = SimSource::new(&sim_config);
+        source.config(serde_json::json!({ "pps_enabled": true })).unwrap();
+
+        let base = temp_base("record");
+        let global = SigMfGlobal::new("raw", source.sample_rate_hz(), &source.get_info(), &source.get_config());
+        let mut recorder = SigMfRecorder::create(&base, global, source.center_frequency_hz()).unwrap();
+        let mut ring_buffer = create_samples_ring_buffer(32768);
+        sdr_thread_monitored(
+            &mut source,
+            &mut ring_buffer.producer,
+            Some(&mut recorder),
+            &StreamMonitor::new(),
+            &AtomicBool::new(false),
+        )
+        .unwrap();
+        assert_eq!(recorder.samples_written, 20480);
+        drop(recorder);
+
+        let meta = SigMfMeta::from_file(&format!("{}.sigmf-meta", base)).unwrap();
+        assert_eq!(meta.global.datatype, "cf32_le");
+        assert_eq!(meta.global.sample_rate, Some(2_048_000.0));
+        assert_eq!(meta.global.sdr_info.as_ref().unwrap().driver, Some("sim".to_string()));
+        assert_eq!(meta.global.sdr_config.as_ref().unwrap().pps_enabled, Some(true));
+        assert_eq!(meta.captures.len(), 1);
+        assert_eq!(meta.captures[0].frequency, Some(GPS_L1_FREQ_HZ as f64));
+        assert!(meta.captures[0].datetime.as_ref().unwrap().ends_with('Z'));
+
+        let playback = SigMfPlaybackConfig { path: base.clone(), playback_rate_hz: None, repeat: false };
+        let file_config = meta.file_source_config(&playback).unwrap();
+        assert_eq!(file_config.format, SampleFormat::Complex32);
+        assert_eq!(file_config.freq_if_hz, 0.0);
+        let mut replay = FileSource::new(&file_config).unwrap();
+        let mut recorded = vec![Complex32::new(0.0, 0.0); 32768];
+        let n_recorded = ring_buffer.consumer.pop_slice(&mut recorded);
+        let mut replayed = vec![Complex32::new(0.0, 0.0); 32768];
+        let mut n_replayed = 0;
+        while let Ok(n) = replay.read(&mut replayed[n_replayed..], 0) {
+            n_replayed += n;
+        }
+        assert_eq!(n_replayed, n_recorded);
+        assert_eq!(replayed[..n_replayed], recorded[..n_recorded]);
+
+        let _ = std::fs::remove_file(format!("{}.sigmf-meta", base));
+        let _ = std::fs::remove_file(format!("{}.sigmf-data", base));
+    }
+
+    #[test]
+    fn test_read_foreign_metadata() {
+        // Written by another recorder, without the gnss_sdr_rs fields
+        let meta: SigMfMeta = serde_json::from_str(
+            r#"{
+                "global": { "core:datatype": "ci16_le", "core:sample_rate": 4e6, "core:version": "1.0.0",
+                            "core:author": "someone" },
+                "captures": [ { "core:sample_start": 0, "core:frequency": 1575.42e6 } ],
+                "annotations": []
+            }"#,
+        )
+        .unwrap();
+        let playback = SigMfPlaybackConfig {
+            path: "/data/event.sigmf-meta".to_string(),
+            playback_rate_hz: Some(4e6),
+            repeat: true,
+        };
+        let file_config = meta.file_source_config(&playback).unwrap();
+        assert_eq!(file_config.path, "/data/event.sigmf-data");
+        assert_eq!(file_config.format, SampleFormat::Int16Iq);
+        assert_eq!(file_config.sample_rate_hz, 4e6);
+        assert!(file_config.repeat);
+
+        let mut meta = meta;
+        meta.global.datatype = "cf64_be".to_string();
+        assert!(matches!(meta.file_source_config(&playback), Err(SdrError::ConfigError(_))));
+    }
+}
@@ -0,0 +1,185 @@
+use crate::sdr_store::backend::SdrBackend;
+use crate::sdr_store::sample_source::StreamEvent;
+use crate::sdr_store::sdr_wrapper::{SdrError, SdrInfo};
+use num_complex::Complex32;
+use soapysdr::{Args, Device, Direction, ErrorCode, RxStream};
+use std::collections::VecDeque;
+
+/// SoapySDR implementation of `SdrBackend`
+pub struct SoapyBackend {
+    pub device: Device,
+    pub rx_stream: Option<RxStream<Complex32>>,
+    events: VecDeque<StreamEvent>,
+    hardware_time: bool, // The stream times come from the device clock
+    last_time_ns: Option<i64>,
+}
+
+impl SoapyBackend {
+    // The `args` are one of the entries returned by `soapysdr::enumerate()`
+    pub fn new(args: Args) -> Result<Self, SdrError> {
+        let device = Device::new(args).map_err(|e| SdrError::DeviceError(e.to_string()))?;
+        Ok(Self {
+            device,
+            rx_stream: None,
+            events: VecDeque::new(),
+            hardware_time: false,
+            last_time_ns: None,
+        })
+    }
+
+    fn rx_stream_mut(&mut self) -> Result<&mut RxStream<Complex32>, SdrError> {
+        self.rx_stream
+            .as_mut()
+            .ok_or(SdrError::StreamError("RX stream not initialized".to_string()))
+    }
+}
+
+pub fn map_args_to_info(args: &Args) -> SdrInfo {
+    SdrInfo::from_args_str(&args.to_string())
+}
+
+impl SdrBackend for SoapyBackend {
+    fn driver_key(&self) -> Result<String, SdrError> {
+        self.device.driver_key().map_err(|e| SdrError::OtherError(e.to_string()))
+    }
+
+    fn frequency(&self, channel: usize) -> Result<f64, SdrError> {
+        self.device.frequency(Direction::Rx, channel).map_err(|e| SdrError::OtherError(e.to_string()))
+    }
+
+    fn set_frequency(&mut self, channel: usize, frequency_hz: f64) -> Result<(), SdrError> {
+        self.device
+            .set_frequency(Direction::Rx, channel, frequency_hz, Args::from(""))
+            .map_err(|e| SdrError::ConfigError(format!("Failed to set center frequency: {}", e)))
+    }
+
+    fn sample_rate(&self, channel: usize) -> Result<f64, SdrError> {
+        self.device.sample_rate(Direction::Rx, channel).map_err(|e| SdrError::OtherError(e.to_string()))
+    }
+
+    fn set_sample_rate(&mut self, channel: usize, sample_rate_hz: f64) -> Result<(), SdrError> {
+        self.device
+            .set_sample_rate(Direction::Rx, channel, sample_rate_hz)
+            .map_err(|e| SdrError::ConfigError(format!("Failed to set sample rate: {}", e)))
+    }
+
+    fn set_bandwidth(&mut self, channel: usize, bandwidth_hz: f64) -> Result<(), SdrError> {
+        self.device
+            .set_bandwidth(Direction::Rx, channel, bandwidth_hz)
+            .map_err(|e| SdrError::ConfigError(format!("Failed to set bandwidth: {}", e)))
+    }
+
+    fn gain(&self, channel: usize) -> Result<f64, SdrError> {
+        self.device.gain(Direction::Rx, channel).map_err(|e| SdrError::OtherError(e.to_string()))
+    }
+
+    fn set_gain(&mut self, channel: usize, gain_db: f64) -> Result<(), SdrError> {
+        self.device
+            .set_gain(Direction::Rx, channel, gain_db)
+            .map_err(|e| SdrError::ConfigError(format!("Failed to set gain: {}", e)))
+    }
+
+    fn set_gain_mode(&mut self, channel: usize, automatic: bool) -> Result<(), SdrError> {
+        self.device
+            .set_gain_mode(Direction::Rx, channel, automatic)
+            .map_err(|e| SdrError::ConfigError(format!("Failed to set gain mode: {}", e)))
+    }
+
+    fn list_gains(&self, channel: usize) -> Result<Vec<String>, SdrError> {
+        self.device.list_gains(Direction::Rx, channel).map_err(|e| SdrError::OtherError(e.to_string()))
+    }
+
+    fn set_gain_element(&mut self, channel: usize, name: &str, gain_db: f64) -> Result<(), SdrError> {
+        self.device
+            .set_gain_element(Direction::Rx, channel, name, gain_db)
+            .map_err(|e| SdrError::ConfigError(format!("Failed to set {} gain: {}", name, e)))
+    }
+
+    fn write_setting(&mut self, key: &str, value: &str) -> Result<(), SdrError> {
+        self.device
+            .write_setting(key, value)
+            .map_err(|e| SdrError::ConfigError(format!("Failed to write setting {}: {}", key, e)))
+    }
+
+    fn set_clock_source(&mut self, source: &str) -> Result<(), SdrError> {
+        self.device
+            .set_clock_source(source)
+            .map_err(|e| SdrError::ConfigError(format!("Failed to set clock source: {}", e)))
+    }
+
+    fn antennas(&self, channel: usize) -> Result<Vec<String>, SdrError> {
+        self.device.antennas(Direction::Rx, channel).map_err(|e| SdrError::OtherError(e.to_string()))
+    }
+
+    fn set_antenna(&mut self, channel: usize, antenna: &str) -> Result<(), SdrError> {
+        self.device
+            .set_antenna(Direction::Rx, channel, antenna)
+            .map_err(|e| SdrError::ConfigError(format!("Failed to set antenna: {}", e)))
+    }
+
+    fn activate_rx(&mut self, channels: &[usize], time_ns: Option<i64>) -> Result<(), SdrError> {
+        let mut rx_stream = self
+            .device
+            .rx_stream::<Complex32>(channels)
+            .map_err(|e| SdrError::StreamError(e.to_string()))?;
+        rx_stream
+            .activate(time_ns)
+            .map_err(|e| SdrError::StreamError(e.to_string()))?;
+        self.rx_stream = Some(rx_stream);
+        // Without a hardware clock the drivers leave the stream time at zero
+        self.hardware_time = self.device.has_hardware_time(None).unwrap_or(false);
+        self.last_time_ns = None;
+        Ok(())
+    }
+
+    fn deactivate_rx(&mut self, time_ns: Option<i64>) -> Result<(), SdrError> {
+        if let Some(mut rx_stream) = self.rx_stream.take() {
+            rx_stream
+                .deactivate(time_ns)
+                .map_err(|e| SdrError::StreamError(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    fn rx_mtu(&self) -> Result<usize, SdrError> {
+        self.rx_stream
+            .as_ref()
+            .ok_or(SdrError::StreamError("RX stream not initialized".to_string()))?
+            .mtu()
+            .map_err(|e| SdrError::StreamError(format!("Failed to get RX stream MTU: {}", e)))
+    }
+
+    fn read_rx(&mut self, buf: &mut [Complex32], timeout_us: i64) -> Result<usize, SdrError> {
+        let rx_stream = self.rx_stream_mut()?;
+        let read = rx_stream.read(&mut [buf], timeout_us);
+        let time_ns = rx_stream.time_ns();
+        match read {
+            Ok(n_samples) => {
+                if n_samples > 0 && self.hardware_time {
+                    self.last_time_ns = Some(time_ns);
+                }
+                Ok(n_samples)
+            }
+            Err(e) => match e.code {
+                ErrorCode::Timeout => Ok(0),
+                ErrorCode::Overflow => {
+                    self.events.push_back(StreamEvent::Overflow);
+                    Ok(0)
+                }
+                ErrorCode::TimeError => {
+                    self.events.push_back(StreamEvent::TimeError);
+                    Ok(0)
+                }
+                _ => Err(SdrError::StreamError(e.to_string())),
+            },
+        }
+    }
+
+    fn rx_time_ns(&self) -> Option<i64> {
+        self.last_time_ns
+    }
+
+    fn poll_event(&mut self) -> Option<StreamEvent> {
+        self.events.pop_front()
+    }
+}
@@ -0,0 +1,191 @@
+use crate::rf::agc::AgcTelemetry;
+use crate::rf::interference::InterferenceStats;
+use crate::sdr_store::sample_time::SampleTime;
+use crossbeam_channel::{Receiver, Sender};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Why the stream is not contiguous
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscontinuityCause {
+    DeviceOverflow,  // The device or driver dropped samples, how many is unknown
+    DeviceTimeError, // The device reported a timing error
+    TimestampGap,    // The sample timestamps jumped
+}
+
+/// A break in the sample stream: the sample at `sample_index` doesn't follow the one before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Discontinuity {
+    pub sample_index: usize, // Index of the first sample after the break, in the stream it is reported on
+    pub lost_samples: Option<usize>, // None when the number of missing samples is unknown
+    pub cause: DiscontinuityCause,
+}
+
+/// Counters of the sample stream health, updated by the SDR and RF threads
+#[derive(Debug, Default)]
+pub struct StreamStats {
+    pub samples_read: AtomicU64,
+    pub device_overflows: AtomicU64,
+    pub device_time_errors: AtomicU64,
+    pub timestamp_gaps: AtomicU64,
+    pub samples_lost: AtomicU64, // Only the gaps of known length are counted
+    pub ring_full_events: AtomicU64, // `sdr_thread` had to wait for the RF thread
+    pub rf_underruns: AtomicU64, // `rf_thread` had to wait for samples
+}
+
+impl StreamStats {
+    pub fn discontinuities(&self) -> u64 {
+        self.device_overflows.load(Ordering::Relaxed)
+            + self.device_time_errors.load(Ordering::Relaxed)
+            + self.timestamp_gaps.load(Ordering::Relaxed)
+    }
+}
+
+impl Display for StreamStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "samples read: {}, device overflows: {}, device time errors: {}, timestamp gaps: {} ({} samples lost), ring full: {}, RF underruns: {}",
+            self.samples_read.load(Ordering::Relaxed),
+            self.device_overflows.load(Ordering::Relaxed),
+            self.device_time_errors.load(Ordering::Relaxed),
+            self.timestamp_gaps.load(Ordering::Relaxed),
+            self.samples_lost.load(Ordering::Relaxed),
+            self.ring_full_events.load(Ordering::Relaxed),
+            self.rf_underruns.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// Shared between the SDR thread, which reports discontinuities and the time of the raw stream,
+/// and the RF thread, which carries them over to the `MulticastRingBuffer`
+#[derive(Clone)]
+pub struct StreamMonitor {
+    pub stats: Arc<StreamStats>,
+    pub agc: Arc<AgcTelemetry>, // Updated by the RF thread when the AGC is enabled
+    pub interference: Arc<InterferenceStats>, // Updated by the RF thread when blanking or notching
+    pub pps_enabled: bool, // From `SdrConfig.pps_enabled`, stamped on the sample times
+    discontinuity_tx: Sender<Discontinuity>,
+    discontinuity_rx: Receiver<Discontinuity>,
+    time_tx: Sender<SampleTime>,
+    time_rx: Receiver<SampleTime>,
+}
+
+impl Default for StreamMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamMonitor {
+    pub fn new() -> Self {
+        Self::with_pps(false)
+    }
+
+    pub fn with_pps(pps_enabled: bool) -> Self {
+        let (discontinuity_tx, discontinuity_rx) = crossbeam_channel::unbounded();
+        let (time_tx, time_rx) = crossbeam_channel::unbounded();
+        Self {
+            stats: Arc::new(StreamStats::default()),
+            agc: Arc::new(AgcTelemetry::default()),
+            interference: Arc::new(InterferenceStats::default()),
+            pps_enabled,
+            discontinuity_tx,
+            discontinuity_rx,
+            time_tx,
+            time_rx,
+        }
+    }
+
+    pub fn report(&self, discontinuity: Discontinuity) {
+        let counter = match discontinuity.cause {
+            DiscontinuityCause::DeviceOverflow => &self.stats.device_overflows,
+            DiscontinuityCause::DeviceTimeError => &self.stats.device_time_errors,
+            DiscontinuityCause::TimestampGap => &self.stats.timestamp_gaps,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        if let Some(lost) = discontinuity.lost_samples {
+            self.stats.samples_lost.fetch_add(lost as u64, Ordering::Relaxed);
+        }
+        println!("Warning: Sample stream discontinuity: {:?}", discontinuity);
+        let _ = self.discontinuity_tx.send(discontinuity);
+    }
+
+    /// Discontinuities reported since the last call, oldest first
+    pub fn take_discontinuities(&self) -> impl Iterator<Item = Discontinuity> + '_ {
+        self.discontinuity_rx.try_iter()
+    }
+
+    /// A new time reference of the raw stream
+    pub fn report_time(&self, time: SampleTime) {
+        let _ = self.time_tx.send(time);
+    }
+
+    /// Time references reported since the last call, oldest first
+    pub fn take_times(&self) -> impl Iterator<Item = SampleTime> + '_ {
+        self.time_rx.try_iter()
+    }
+}
+
+/// Finds the samples missing between reads from the timestamps of their first samples
+pub struct TimestampGapDetector {
+    sample_period_ns: f64,
+    expected_ns: Option<f64>, // Timestamp the next read should start at
+}
+
+impl TimestampGapDetector {
+    pub fn new(sample_rate_hz: f64) -> Self {
+        Self { sample_period_ns: 1e9 / sample_rate_hz, expected_ns: None }
+    }
+
+    /// Account for a read of `n_samples` starting at `timestamp_ns`, returns the jump in samples
+    /// before it, negative when the time went backwards, None when the read follows on
+    pub fn check(&mut self, timestamp_ns: i64, n_samples: usize) -> Option<i64> {
+        let gap = self.expected_ns.map(|expected| (timestamp_ns as f64 - expected) / self.sample_period_ns);
+        self.expected_ns = Some(timestamp_ns as f64 + n_samples as f64 * self.sample_period_ns);
+        match gap {
+            // Less than half a sample is timestamp jitter
+            Some(gap) if gap.abs() >= 0.5 => Some(gap.round() as i64),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestamp_gap_detector() {
+        // 1 MHz, one sample per microsecond
+        let mut detector = TimestampGapDetector::new(1e6);
+        assert_eq!(detector.check(1_000_000, 1000), None);
+        assert_eq!(detector.check(2_000_000, 1000), None);
+        // 200 ns of jitter
+        assert_eq!(detector.check(3_000_200, 1000), None);
+        assert_eq!(detector.check(4_000_200 + 250_000, 1000), Some(250));
+        assert_eq!(detector.check(4_000_000, 1000), Some(-1250));
+    }
+
+    #[test]
+    fn test_monitor_counts_and_forwards() {
+        let monitor = StreamMonitor::new();
+        monitor.report(Discontinuity {
+            sample_index: 100,
+            lost_samples: None,
+            cause: DiscontinuityCause::DeviceOverflow,
+        });
+        monitor.report(Discontinuity {
+            sample_index: 300,
+            lost_samples: Some(40),
+            cause: DiscontinuityCause::TimestampGap,
+        });
+        assert_eq!(monitor.stats.discontinuities(), 2);
+        assert_eq!(monitor.stats.samples_lost.load(Ordering::Relaxed), 40);
+
+        let reported: Vec<usize> = monitor.take_discontinuities().map(|d| d.sample_index).collect();
+        assert_eq!(reported, vec![100, 300]);
+        assert_eq!(monitor.take_discontinuities().count(), 0);
+    }
+}
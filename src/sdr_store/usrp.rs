@@ -0,0 +1,42 @@
+use crate::sdr_store::device_config::DeviceProfile;
+
+/// Ettus USRP through UHD: the gain stages depend on the daughterboard, the RX2 port is the
+/// receive only input. The reference can be internal, an external 10 MHz or the GPSDO.
+pub const USRP_PROFILE: DeviceProfile = DeviceProfile {
+    name: "USRP",
+    bias_tee_setting: None,
+    default_antenna: Some("RX2"),
+    gain_limits: &[],
+    clock_sources: &["internal", "external", "gpsdo"],
+    validate: None,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdr_mock::device_mock::MockDevice;
+    use crate::sdr_store::profiled_sdr::ProfiledSdr;
+    use crate::sdr_store::sdr_wrapper::{SdrDeviceWrapper, SdrError};
+
+    #[test]
+    fn test_usrp_config() {
+        let mut usrp = ProfiledSdr::<MockDevice>::new("driver=uhd, label=B210 [30F5A66], product=B210, serial=30F5A66, type=b200", &USRP_PROFILE)
+            .expect("Failed to mock a USRP device");
+        assert_eq!(usrp.sdr_info.product, Some("B210".to_string()));
+
+        usrp.config(serde_json::json!({
+            "center_frequency": 1575.42e6,
+            "sample_rate": 4e6,
+            "bandwidth": 4e6,
+            "gain": 50.0,
+            "clock_source": "gpsdo",
+        }))
+        .expect("Failed to configure the mocked USRP device");
+        assert_eq!(usrp.backend.antenna, Some("RX2".to_string()));
+        assert_eq!(usrp.backend.clock_source, Some("gpsdo".to_string()));
+        assert_eq!(usrp.sdr_config.gain_db, 50.0);
+
+        let config = serde_json::json!({ "bandwidth": 4e6, "antennas": ["RX3"] });
+        assert!(matches!(usrp.config(config), Err(SdrError::ConfigError(_))));
+    }
+}
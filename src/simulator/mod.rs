@@ -0,0 +1,2 @@
+pub mod signal_generator;
+pub mod sim_source;
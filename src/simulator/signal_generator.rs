@@ -0,0 +1,342 @@
+use crate::constants::gps_ca_constants::GPS_CA_CODE_32_PRN;
+use crate::constants::gps_property_constants::{
+    GPS_CA_TELEMETRY_SYMBOLS_PER_BIT, GPS_L1_CA_CODE_LENGTH_CHIPS, GPS_L1_CA_CODE_RATE_CHIPS_PER_S,
+    GPS_L1_FREQ_HZ,
+};
+use num_complex::Complex32;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+fn default_cn0_db_hz() -> f64 {
+    45.0
+}
+
+fn default_noise_sigma() -> f64 {
+    1.0
+}
+
+/// One simulated GPS L1 C/A signal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimSatellite {
+    pub prn: u8,
+    #[serde(default)]
+    pub code_phase_chips: f64, // Code phase at the first sample
+    #[serde(default)]
+    pub doppler_hz: f64,
+    #[serde(default)]
+    pub doppler_rate_hz_per_s: f64,
+    #[serde(default = "default_cn0_db_hz")]
+    pub cn0_db_hz: f64,
+    #[serde(default)]
+    pub carrier_phase_rad: f64,
+    #[serde(default)]
+    pub nav_bits: Vec<i8>, // +1/-1, 20 ms each and repeated, empty for no data modulation
+    #[serde(default)]
+    pub bit_offset_ms: u32, // Milliseconds already elapsed in the first bit
+}
+
+impl SimSatellite {
+    pub fn new(prn: u8, code_phase_chips: f64, doppler_hz: f64, cn0_db_hz: f64) -> Self {
+        Self {
+            prn,
+            code_phase_chips,
+            doppler_hz,
+            doppler_rate_hz_per_s: 0.0,
+            cn0_db_hz,
+            carrier_phase_rad: 0.0,
+            nav_bits: Vec::new(),
+            bit_offset_ms: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimConfig {
+    pub sample_rate_hz: f64,
+    #[serde(default)]
+    pub freq_if_hz: f64,
+    #[serde(default = "default_noise_sigma")]
+    pub noise_sigma: f64, // Standard deviation of the thermal noise per I/Q component, 0 disables the noise
+    #[serde(default)]
+    pub seed: u64,
+    pub int8_scale: Option<f64>, // Scale then round and clip the samples to int8, like an 8-bit ADC
+    pub duration_s: Option<f64>, // End of stream when used as a sample source
+    #[serde(default)]
+    pub satellites: Vec<SimSatellite>,
+}
+
+impl SimConfig {
+    pub fn new(sample_rate_hz: f64, freq_if_hz: f64, seed: u64) -> Self {
+        Self {
+            sample_rate_hz,
+            freq_if_hz,
+            noise_sigma: default_noise_sigma(),
+            seed,
+            int8_scale: None,
+            duration_s: None,
+            satellites: Vec::new(),
+        }
+    }
+}
+
+/// SplitMix64 plus Box-Muller, so the noise only depends on the seed and not on an external crate
+struct GaussianNoise {
+    state: u64,
+}
+
+impl GaussianNoise {
+    fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in (0, 1]
+    fn next_uniform(&mut self) -> f64 {
+        ((self.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Two independent samples of N(0, 1)
+    fn next_pair(&mut self) -> (f64, f64) {
+        let r = (-2.0 * self.next_uniform().ln()).sqrt();
+        let theta = 2.0 * PI * self.next_uniform();
+        (r * theta.cos(), r * theta.sin())
+    }
+}
+
+/// Deterministic generator of complex GPS L1 C/A samples, the same config and seed always
+/// give the same samples. Consecutive calls continue the signal without phase jumps.
+pub struct SignalGenerator {
+    pub config: SimConfig,
+    amplitudes: Vec<f64>,
+    noise: GaussianNoise,
+    sample_index: u64,
+}
+
+impl SignalGenerator {
+    pub fn new(config: SimConfig) -> Self {
+        for sat in config.satellites.iter() {
+            assert!(
+                (1..=GPS_CA_CODE_32_PRN.len() as u8).contains(&sat.prn),
+                "PRN {} is out of range",
+                sat.prn
+            );
+        }
+        // C/N0 = A^2 / N0, with N0 = 2 * sigma^2 / fs for complex noise. Without noise the
+        // amplitudes stay the ones of unit noise.
+        let sigma = if config.noise_sigma > 0.0 { config.noise_sigma } else { 1.0 };
+        let n0 = 2.0 * sigma * sigma / config.sample_rate_hz;
+        let amplitudes = config
+            .satellites
+            .iter()
+            .map(|sat| (10f64.powf(sat.cn0_db_hz / 10.0) * n0).sqrt())
+            .collect();
+
+        Self {
+            noise: GaussianNoise::new(config.seed),
+            config,
+            amplitudes,
+            sample_index: 0,
+        }
+    }
+
+    /// Index of the next sample to be generated
+    pub fn sample_index(&self) -> u64 {
+        self.sample_index
+    }
+
+    pub fn samples_per_ms(&self) -> usize {
+        (self.config.sample_rate_hz / 1000.0).round() as usize
+    }
+
+    /// Fill the buffer with the next samples
+    pub fn generate(&mut self, out: &mut [Complex32]) {
+        let fs = self.config.sample_rate_hz;
+        let code_length = GPS_L1_CA_CODE_LENGTH_CHIPS as f64;
+        let code_rate = GPS_L1_CA_CODE_RATE_CHIPS_PER_S as f64;
+        let bit_length_ms = GPS_CA_TELEMETRY_SYMBOLS_PER_BIT as i64;
+
+        for (n, sample) in out.iter_mut().enumerate() {
+            let k = (self.sample_index + n as u64) as f64;
+            let t = k / fs;
+            let mut value = Complex32::new(0.0, 0.0);
+
+            for (sat, amplitude) in self.config.satellites.iter().zip(self.amplitudes.iter()) {
+                // Integrated Doppler in cycles, it also stretches the code
+                let doppler_cycles = sat.doppler_hz * t + 0.5 * sat.doppler_rate_hz_per_s * t * t;
+                let carrier_phase = sat.carrier_phase_rad
+                    + 2.0 * PI * (self.config.freq_if_hz * t + doppler_cycles);
+                let chips = sat.code_phase_chips
+                    + code_rate * k / fs // Exact on chip boundaries for integer oversampling
+                    + doppler_cycles * code_rate / GPS_L1_FREQ_HZ as f64;
+
+                let chip = GPS_CA_CODE_32_PRN[sat.prn as usize - 1]
+                    [chips.rem_euclid(code_length) as usize % 1023] as f64;
+                let bit = if sat.nav_bits.is_empty() {
+                    1.0
+                } else {
+                    let epoch_ms = (chips / code_length).floor() as i64 + sat.bit_offset_ms as i64;
+                    let bit_index = epoch_ms.div_euclid(bit_length_ms)
+                        .rem_euclid(sat.nav_bits.len() as i64);
+                    sat.nav_bits[bit_index as usize] as f64
+                };
+
+                let a = amplitude * chip * bit;
+                value += Complex32::new(
+                    (a * carrier_phase.cos()) as f32,
+                    (a * carrier_phase.sin()) as f32,
+                );
+            }
+
+            if self.config.noise_sigma > 0.0 {
+                let (n_i, n_q) = self.noise.next_pair();
+                value += Complex32::new(
+                    (n_i * self.config.noise_sigma) as f32,
+                    (n_q * self.config.noise_sigma) as f32,
+                );
+            }
+
+            if let Some(scale) = self.config.int8_scale {
+                value = Complex32::new(
+                    quantize_int8(value.re as f64 * scale),
+                    quantize_int8(value.im as f64 * scale),
+                );
+            }
+
+            *sample = value;
+        }
+
+        self.sample_index += out.len() as u64;
+    }
+
+    pub fn generate_samples(&mut self, num_samples: usize) -> Vec<Complex32> {
+        let mut samples = vec![Complex32::new(0.0, 0.0); num_samples];
+        self.generate(&mut samples);
+        samples
+    }
+}
+
+#[inline(always)]
+fn quantize_int8(x: f64) -> f32 {
+    x.round().clamp(i8::MIN as f64, i8::MAX as f64) as f32
+}
+
+/// Interleave quantized samples as int8 I/Q, e.g. to write a recording for `FileSource`
+pub fn to_int8_iq(samples: &[Complex32]) -> Vec<i8> {
+    samples
+        .iter()
+        .flat_map(|s| [quantize_int8(s.re as f64) as i8, quantize_int8(s.im as f64) as i8])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utilities::ca_code::generate_ca_code_samples;
+
+    #[test]
+    fn test_noise_free_signal_matches_ca_code() {
+        let fs = 4_092_000.0;
+        let mut config = SimConfig::new(fs, 0.0, 0);
+        config.noise_sigma = 0.0;
+        config.satellites.push(SimSatellite::new(7, 0.0, 0.0, 45.0));
+        let mut generator = SignalGenerator::new(config);
+
+        let samples = generator.generate_samples(generator.samples_per_ms());
+        let ca_code = generate_ca_code_samples(7, GPS_L1_CA_CODE_RATE_CHIPS_PER_S, fs as f32);
+        assert_eq!(samples.len(), ca_code.len());
+
+        // Skip the chip edges, `generate_ca_code_samples` computes the chip index in f32
+        let amplitude = samples[0].re.abs();
+        for (n, (s, c)) in samples.iter().zip(ca_code.iter()).enumerate() {
+            if n % 4 == 0 {
+                continue;
+            }
+            assert!((s.re - amplitude * *c as f32).abs() < 1e-6);
+            assert!(s.im.abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_generator_is_deterministic_and_continuous() {
+        let mut config = SimConfig::new(2_048_000.0, 1000.0, 42);
+        config.satellites.push(SimSatellite::new(1, 100.0, 1500.0, 50.0));
+        config.satellites.push(SimSatellite::new(12, 700.5, -2500.0, 42.0));
+
+        let mut one_shot = SignalGenerator::new(config.clone());
+        let all = one_shot.generate_samples(3000);
+
+        let mut chunked = SignalGenerator::new(config.clone());
+        let mut parts = chunked.generate_samples(1000);
+        parts.extend(chunked.generate_samples(2000));
+        assert_eq!(all, parts);
+        assert_eq!(chunked.sample_index(), 3000);
+
+        config.seed = 43;
+        let other_seed = SignalGenerator::new(config).generate_samples(3000);
+        assert_ne!(all, other_seed);
+    }
+
+    #[test]
+    fn test_noise_power_and_cn0() {
+        let fs = 4_092_000.0;
+        let mut config = SimConfig::new(fs, 0.0, 1);
+        config.noise_sigma = 2.0;
+        let mut generator = SignalGenerator::new(config.clone());
+        let noise = generator.generate_samples(100_000);
+        let power = noise.iter().map(|s| s.norm_sqr() as f64).sum::<f64>() / noise.len() as f64;
+        assert!((power - 8.0).abs() < 0.2, "Noise power {} instead of 8", power);
+
+        // Coherent 1 ms correlation of a noise free 50 dB-Hz signal gives A^2 = C/N0 * N0
+        config.noise_sigma = 0.0;
+        config.satellites.push(SimSatellite::new(3, 0.0, 0.0, 50.0));
+        let mut generator = SignalGenerator::new(config);
+        let samples = generator.generate_samples(generator.samples_per_ms());
+        let a2 = (samples[0].re as f64).powi(2);
+        assert!((a2 / (2.0 / fs) - 1e5).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_nav_bits_flip_on_bit_edges() {
+        let fs = 1_023_000.0;
+        let mut config = SimConfig::new(fs, 0.0, 0);
+        config.noise_sigma = 0.0;
+        let mut sat = SimSatellite::new(5, 0.0, 0.0, 45.0);
+        sat.nav_bits = vec![1, -1];
+        sat.bit_offset_ms = 10;
+        config.satellites.push(sat);
+        let mut generator = SignalGenerator::new(config);
+        let samples_per_ms = generator.samples_per_ms();
+        let samples = generator.generate_samples(40 * samples_per_ms);
+
+        // Same chip, 1 ms apart: sign flips only when crossing a bit edge after 10 ms and 30 ms
+        for ms in 1..40 {
+            let product = samples[ms * samples_per_ms].re * samples[(ms - 1) * samples_per_ms].re;
+            if ms == 10 || ms == 30 {
+                assert!(product < 0.0, "Expected a bit transition at {} ms", ms);
+            } else {
+                assert!(product > 0.0, "Unexpected bit transition at {} ms", ms);
+            }
+        }
+    }
+
+    #[test]
+    fn test_int8_quantization() {
+        let mut config = SimConfig::new(2_048_000.0, 0.0, 3);
+        config.noise_sigma = 30.0;
+        config.int8_scale = Some(2.0);
+        let mut generator = SignalGenerator::new(config);
+        let samples = generator.generate_samples(10_000);
+        assert!(samples.iter().all(|s| s.re.fract() == 0.0 && s.im.fract() == 0.0));
+        assert!(samples.iter().any(|s| s.re == 127.0 || s.re == -128.0));
+
+        let iq = to_int8_iq(&samples[..2]);
+        assert_eq!(iq, vec![samples[0].re as i8, samples[0].im as i8, samples[1].re as i8, samples[1].im as i8]);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::constants::gps_property_constants::GPS_L1_FREQ_HZ;
+use crate::sdr_store::sample_source::SampleSource;
+use crate::sdr_store::sdr_wrapper::{SdrConfig, SdrDeviceWrapper, SdrError, SdrInfo};
+use crate::simulator::signal_generator::{SignalGenerator, SimConfig};
+use num_complex::Complex32;
+use serde_json::Value;
+
+/// Number of samples generated per read
+pub const SIM_SOURCE_MTU: usize = 16384;
+
+/// Feeds the pipeline with simulated samples as if they came from an SDR
+pub struct SimSource {
+    pub generator: SignalGenerator,
+    pub sdr_info: SdrInfo,
+    pub sdr_config: SdrConfig,
+    total_samples: Option<u64>, // End of stream after this many samples
+    read_index: u64, // Index of the first sample of the last read
+}
+
+impl SimSource {
+    pub fn new(sim_config: &SimConfig) -> Self {
+        let prns = sim_config
+            .satellites
+            .iter()
+            .map(|sat| sat.prn.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let sdr_info = SdrInfo {
+            long_args: Some(format!("driver=sim, seed={}", sim_config.seed)),
+            driver: Some("sim".to_string()),
+            label: Some("GPS L1 C/A simulator".to_string()),
+            description: Some(format!("PRN {}", prns)),
+            ..SdrInfo::default()
+        };
+
+        let sdr_config = SdrConfig {
+            center_frequency_hz: GPS_L1_FREQ_HZ + sim_config.freq_if_hz as f32,
+            sample_rate_hz: sim_config.sample_rate_hz as f32,
+            bandwidth_hz: sim_config.sample_rate_hz as f32,
+            ..SdrConfig::default()
+        };
+
+        Self {
+            generator: SignalGenerator::new(sim_config.clone()),
+            sdr_info,
+            sdr_config,
+            total_samples: sim_config
+                .duration_s
+                .map(|d| (d * sim_config.sample_rate_hz).round() as u64),
+            read_index: 0,
+        }
+    }
+}
+
+impl SampleSource for SimSource {
+    #[allow(unused_variables)]
+    fn read(&mut self, buf: &mut [Complex32], timeout_us: i64) -> Result<usize, SdrError> {
+        let mut n_samples = buf.len().min(SIM_SOURCE_MTU);
+        if let Some(total) = self.total_samples {
+            let remaining = total.saturating_sub(self.generator.sample_index());
+            if remaining == 0 {
+                return Err(SdrError::EndOfStream("Simulation finished".to_string()));
+            }
+            n_samples = n_samples.min(remaining as usize);
+        }
+
+        self.read_index = self.generator.sample_index();
+        self.generator.generate(&mut buf[..n_samples]);
+        Ok(n_samples)
+    }
+
+    fn mtu(&self) -> Result<usize, SdrError> {
+        Ok(SIM_SOURCE_MTU)
+    }
+
+    fn sample_rate_hz(&self) -> f64 {
+        self.generator.config.sample_rate_hz
+    }
+
+    fn center_frequency_hz(&self) -> f64 {
+        self.sdr_config.center_frequency_hz as f64
+    }
+
+    /// The simulated device clock starts at zero with the first sample
+    fn timestamp_ns(&self) -> Option<i64> {
+        Some((self.read_index as f64 * 1e9 / self.generator.config.sample_rate_hz).round() as i64)
+    }
+}
+
+impl SdrDeviceWrapper for SimSource {
+    fn get_config(&self) -> SdrConfig {
+        self.sdr_config.clone()
+    }
+
+    fn get_info(&self) -> SdrInfo {
+        self.sdr_info.clone()
+    }
+
+    /// The simulation defines the stream, only the PPS flag is taken from the config
+    fn config(&mut self, config: Value) -> Result<(), SdrError> {
+        if let Some(pps_enabled) = config.get("pps_enabled").and_then(Value::as_bool) {
+            self.sdr_config.pps_enabled = Some(pps_enabled);
+        }
+        Ok(())
+    }
+
+    #[allow(unused_variables)]
+    fn transmit_samples(&self, buf: &mut [&mut [Complex32]]) -> Result<(), SdrError> {
+        Err(SdrError::TransmitError("Simulator is receive only".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdr_store::sdr_thread::sdr_thread;
+    use crate::simulator::signal_generator::SimSatellite;
+    use crate::rf::samples_buffer::create_samples_ring_buffer;
+    use ringbuf::traits::Consumer;
+
+    #[test]
+    fn test_sim_source_feeds_sdr_thread() {
+        let mut sim_config = SimConfig::new(2_048_000.0, 4000.0, 7);
+        sim_config.duration_s = Some(0.01);
+        sim_config.satellites.push(SimSatellite::new(4, 0.0, 1000.0, 45.0));
+        let mut source = SimSource::new(&sim_config);
+        assert_eq!(source.get_info().driver.as_deref(), Some("sim"));
+        assert_eq!(source.center_frequency_hz(), (GPS_L1_FREQ_HZ + 4000.0) as f64);
+
+        let mut ring_buffer = create_samples_ring_buffer(65536);
+        sdr_thread(&mut source, &mut ring_buffer.producer).expect("sdr_thread failed");
+
+        let mut samples = vec![Complex32::new(0.0, 0.0); 65536];
+        let n = ring_buffer.consumer.pop_slice(&mut samples);
+        assert_eq!(n, 20480);
+
+        let expected = SignalGenerator::new(sim_config).generate_samples(n);
+        assert_eq!(&samples[..n], &expected[..]);
+    }
+}
@@ -0,0 +1,391 @@
+use crate::fft::FFT;
+use crate::sdr_store::sample_time::SampleTime;
+use crate::utilities::multicast_ring_buffer::{MulticastRingBuffer, RingReadError, RingSample};
+use crossbeam_channel::{Sender, TrySendError};
+use num_complex::Complex32;
+use plotpy::{Contour, Curve, Plot};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::f32::consts::PI;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, PoisonError};
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct SpectrumError(pub String);
+
+impl fmt::Display for SpectrumError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SpectrumError: {}", self.0)
+    }
+}
+
+impl Error for SpectrumError {}
+
+impl<T> From<PoisonError<T>> for SpectrumError {
+    fn from(_: PoisonError<T>) -> Self {
+        SpectrumError("MulticastRingBuffer lock poisoned".to_string())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SpectrumConfig {
+    #[serde(default = "default_fft_size")]
+    pub fft_size: usize,
+    #[serde(default = "default_averages")]
+    pub averages: usize, // Welch segments per frame
+    #[serde(default = "default_overlap")]
+    pub overlap: f32, // Of consecutive segments, 0 to below 1
+    #[serde(default = "default_frame_interval_ms")]
+    pub frame_interval_ms: f32, // One frame per interval, at most
+    #[serde(default = "default_waterfall_rows")]
+    pub waterfall_rows: usize,
+    pub png_path: Option<String>, // PSD and waterfall plotted there when the monitor stops
+}
+
+fn default_fft_size() -> usize {
+    1024
+}
+
+fn default_averages() -> usize {
+    16
+}
+
+fn default_overlap() -> f32 {
+    0.5
+}
+
+fn default_frame_interval_ms() -> f32 {
+    100.0
+}
+
+fn default_waterfall_rows() -> usize {
+    200
+}
+
+impl SpectrumConfig {
+    /// Samples used for one frame, `averages` segments of `fft_size` overlapping by `overlap`
+    pub fn frame_samples(&self) -> usize {
+        self.fft_size + (self.averages.max(1) - 1) * segment_hop(self.fft_size, self.overlap)
+    }
+}
+
+/// Samples between the starts of consecutive segments
+fn segment_hop(fft_size: usize, overlap: f32) -> usize {
+    ((fft_size as f32 * (1.0 - overlap)).round() as usize).max(1)
+}
+
+impl Default for SpectrumConfig {
+    fn default() -> Self {
+        Self {
+            fft_size: default_fft_size(),
+            averages: default_averages(),
+            overlap: default_overlap(),
+            frame_interval_ms: default_frame_interval_ms(),
+            waterfall_rows: default_waterfall_rows(),
+            png_path: None,
+        }
+    }
+}
+
+/// Welch power spectral density: Hann windowed segments, overlapping, their periodograms averaged
+pub struct WelchPsd {
+    fft: FFT<f32>,
+    pub fft_size: usize,
+    hop: usize, // Samples between the starts of consecutive segments
+    window: Vec<f32>,
+    window_power: f32, // Sum of the squared window, for the PSD scale
+    segment: Vec<Complex32>,
+}
+
+impl WelchPsd {
+    pub fn new(fft_size: usize, overlap: f32) -> Self {
+        let window: Vec<f32> = (0..fft_size).map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / fft_size as f32).cos()).collect();
+        let window_power = window.iter().map(|w| w * w).sum();
+        Self {
+            fft: FFT::new(fft_size),
+            fft_size,
+            hop: segment_hop(fft_size, overlap),
+            window,
+            window_power,
+            segment: vec![Complex32::new(0.0, 0.0); fft_size],
+        }
+    }
+
+    /// Samples covered by `segments` segments
+    pub fn span(&self, segments: usize) -> usize {
+        self.fft_size + (segments.max(1) - 1) * self.hop
+    }
+
+    /// PSD of all the whole segments in `samples`, in dB/Hz, from -fs/2 up, DC at `fft_size / 2`
+    pub fn estimate_db(&mut self, samples: &[Complex32], sample_rate_hz: f32) -> Vec<f32> {
+        let mut psd = vec![0.0f32; self.fft_size];
+        let mut segments = 0;
+        let mut start = 0;
+        while start + self.fft_size <= samples.len() {
+            for ((s, x), w) in self.segment.iter_mut().zip(&samples[start..]).zip(&self.window) {
+                *s = x * w;
+            }
+            for (p, bin) in psd.iter_mut().zip(self.fft.power_spectrum(&mut self.segment)) {
+                *p += bin;
+            }
+            segments += 1;
+            start += self.hop;
+        }
+
+        let scale = 1.0 / (segments.max(1) as f32 * sample_rate_hz * self.window_power);
+        psd.rotate_right(self.fft_size / 2);
+        psd.iter().map(|p| 10.0 * (p * scale).max(1e-30).log10()).collect()
+    }
+}
+
+/// One PSD estimate of the frontend output
+#[derive(Clone, Debug, Serialize)]
+pub struct SpectrumFrame {
+    pub sample_index: usize, // First sample of the frame, indexed like the `MulticastRingBuffer` head
+    pub time_ns: Option<i64>, // Time of that sample, when the stream is timed
+    pub sample_rate_hz: f32,
+    pub psd_db: Vec<f32>, // dB/Hz, from -fs/2 up, DC at `psd_db.len() / 2`
+}
+
+impl SpectrumFrame {
+    /// Offset of each bin from the center frequency
+    pub fn frequencies_hz(&self) -> Vec<f32> {
+        let n = self.psd_db.len();
+        (0..n).map(|k| (k as f32 - (n / 2) as f32) * self.sample_rate_hz / n as f32).collect()
+    }
+
+    /// Frequency offset and level of the strongest bin
+    pub fn peak(&self) -> (f32, f32) {
+        let (k, db) = self
+            .psd_db
+            .iter()
+            .enumerate()
+            .fold((0, f32::MIN), |best, (k, db)| if *db > best.1 { (k, *db) } else { best });
+        (self.frequencies_hz()[k], db)
+    }
+}
+
+/// The latest PSDs, oldest first
+pub struct Waterfall {
+    rows: VecDeque<Vec<f32>>,
+    max_rows: usize,
+}
+
+impl Waterfall {
+    pub fn new(max_rows: usize) -> Self {
+        Self { rows: VecDeque::with_capacity(max_rows), max_rows: max_rows.max(1) }
+    }
+
+    pub fn push(&mut self, psd_db: &[f32]) {
+        if self.rows.len() == self.max_rows {
+            self.rows.pop_front();
+        }
+        self.rows.push_back(psd_db.to_vec());
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &Vec<f32>> {
+        self.rows.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+}
+
+/// Turns blocks of samples into spectrum frames and keeps their waterfall
+pub struct SpectrumMonitor {
+    pub config: SpectrumConfig,
+    pub sample_rate_hz: f32,
+    welch: WelchPsd,
+    pub waterfall: Waterfall,
+    last_frame: Option<SpectrumFrame>,
+}
+
+impl SpectrumMonitor {
+    pub fn new(config: SpectrumConfig, sample_rate_hz: f32) -> Self {
+        let welch = WelchPsd::new(config.fft_size, config.overlap);
+        let waterfall = Waterfall::new(config.waterfall_rows);
+        Self { config, sample_rate_hz, welch, waterfall, last_frame: None }
+    }
+
+    /// Samples used for one frame
+    pub fn frame_samples(&self) -> usize {
+        self.welch.span(self.config.averages)
+    }
+
+    /// Samples from the start of one frame to the next
+    pub fn frame_interval(&self) -> usize {
+        let interval = (self.config.frame_interval_ms * 1e-3 * self.sample_rate_hz).round() as usize;
+        interval.max(self.frame_samples())
+    }
+
+    pub fn last_frame(&self) -> Option<&SpectrumFrame> {
+        self.last_frame.as_ref()
+    }
+
+    pub fn process(&mut self, samples: &[Complex32], sample_index: usize, time: Option<SampleTime>) -> SpectrumFrame {
+        let frame = SpectrumFrame {
+            sample_index,
+            time_ns: time.map(|t| t.time_ns),
+            sample_rate_hz: self.sample_rate_hz,
+            psd_db: self.welch.estimate_db(samples, self.sample_rate_hz),
+        };
+        self.waterfall.push(&frame.psd_db);
+        self.last_frame = Some(frame.clone());
+        frame
+    }
+
+    /// Plot the last PSD over the waterfall, needs python3 with matplotlib
+    pub fn save_png(&self, path: &str) -> Result<(), SpectrumError> {
+        let frame = self.last_frame.as_ref().ok_or(SpectrumError("No spectrum to plot yet".to_string()))?;
+        let freqs_khz: Vec<f32> = frame.frequencies_hz().iter().map(|f| f / 1e3).collect();
+
+        let mut psd = Curve::new();
+        psd.draw(&freqs_khz, &frame.psd_db);
+
+        let (mut x, mut y, mut z) = (Vec::new(), Vec::new(), Vec::new());
+        for (i, row) in self.waterfall.rows().enumerate() {
+            x.push(freqs_khz.clone());
+            y.push(vec![i as f32; row.len()]);
+            z.push(row.clone());
+        }
+        let mut waterfall = Contour::new();
+        waterfall.set_no_lines(true).set_no_labels(true).set_colormap_name("viridis");
+        waterfall.draw(&x, &y, &z);
+
+        let mut plot = Plot::new();
+        plot.set_figure_size_inches(8.0, 8.0);
+        plot.set_subplot(2, 1, 1).set_title("PSD").add(&psd).grid_labels_legend("frequency/kHz", "PSD/(dB/Hz)");
+        plot.set_subplot(2, 1, 2)
+            .set_title("Waterfall")
+            .add(&waterfall)
+            .grid_labels_legend("frequency/kHz", "frame");
+        plot.save(path).map_err(|e| SpectrumError(format!("Failed to save {}: {}", path, e)))
+    }
+}
+
+/// Publishes spectrum frames of the newest samples to `to_subscriber`, until `stop` is set and
+/// the samples written are used up. Frames are dropped while the subscriber is behind.
+pub fn run<T: RingSample>(
+    multi_buffer: Arc<MulticastRingBuffer<T>>,
+    sample_rate_hz: f32,
+    config: SpectrumConfig,
+    mut to_subscriber: Option<Sender<SpectrumFrame>>,
+    stop: &AtomicBool,
+) -> Result<(), SpectrumError> {
+    let mut monitor = SpectrumMonitor::new(config, sample_rate_hz);
+    let (span, interval) = (monitor.frame_samples(), monitor.frame_interval());
+    let mut samples = vec![Complex32::new(0.0, 0.0); span];
+    let mut next_index = 0;
+    loop {
+        let mut head = multi_buffer.get_head();
+        if head < next_index + span {
+            let mut head_guard = multi_buffer.notifier.lock()?;
+            while multi_buffer.get_head() < next_index + span {
+                // No more samples are written once stopped
+                if stop.load(Ordering::Relaxed) {
+                    drop(head_guard);
+                    if let Some(path) = &monitor.config.png_path
+                        && monitor.last_frame().is_some()
+                    {
+                        monitor.save_png(path)?;
+                    }
+                    return Ok(());
+                }
+                head_guard = multi_buffer.condvar.wait_timeout(head_guard, Duration::from_millis(50))?.0;
+            }
+            head = multi_buffer.get_head();
+        }
+
+        // Only the newest samples matter, skip what was missed
+        if head >= next_index + span + interval {
+            next_index = head - span;
+        }
+        // Pinned while copied, the writer waits instead of overwriting the frame
+        match multi_buffer.read(next_index, span) {
+            Ok(frame_samples) => frame_samples.copy_to_slice(&mut samples),
+            Err(RingReadError::Lagging { .. }) => {
+                next_index = multi_buffer.get_head().saturating_sub(span);
+                continue;
+            }
+            Err(e) => return Err(SpectrumError(format!("Failed to read the frame samples: {}", e))),
+        }
+        let frame = monitor.process(&samples, next_index, multi_buffer.time_of(next_index));
+        if let Some(tx) = &to_subscriber
+            && let Err(TrySendError::Disconnected(_)) = tx.try_send(frame)
+        {
+            to_subscriber = None;
+        }
+        next_index += interval;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::{FilterConfig, design_channel_filter};
+    use crate::simulator::signal_generator::{SignalGenerator, SimConfig};
+
+    fn noise(n: usize) -> Vec<Complex32> {
+        let mut samples = vec![Complex32::new(0.0, 0.0); n];
+        SignalGenerator::new(SimConfig::new(2_048_000.0, 0.0, 7)).generate(&mut samples);
+        samples
+    }
+
+    #[test]
+    fn test_welch_psd_level_and_tone() {
+        let fs = 2_048_000.0;
+        let mut welch = WelchPsd::new(256, 0.5);
+        assert_eq!(welch.span(16), 256 + 15 * 128);
+
+        // Complex noise of unit variance per component: 2 / fs per Hz
+        let mut samples = noise(welch.span(64));
+        let psd = welch.estimate_db(&samples, fs);
+        let mean_db = psd.iter().sum::<f32>() / psd.len() as f32;
+        assert!((mean_db - 10.0 * (2.0 / fs).log10()).abs() < 0.5, "{} dB/Hz", mean_db);
+
+        // A jammer 30 dB above the noise in a bin, at -256 kHz
+        for (n, s) in samples.iter_mut().enumerate() {
+            *s += Complex32::from_polar(10.0, -2.0 * PI * 0.125 * n as f32);
+        }
+        let frame = SpectrumFrame { sample_index: 0, time_ns: None, sample_rate_hz: fs, psd_db: welch.estimate_db(&samples, fs) };
+        let (freq, level) = frame.peak();
+        assert_eq!(freq, -256_000.0);
+        assert!(level - mean_db > 30.0);
+    }
+
+    #[test]
+    fn test_monitor_shows_filter_shape() {
+        let fs = 2_048_000.0;
+        let config = SpectrumConfig { fft_size: 256, waterfall_rows: 3, ..SpectrumConfig::default() };
+        let mut monitor = SpectrumMonitor::new(config, fs);
+        assert_eq!(monitor.frame_interval(), 204_800);
+
+        let filter_config = FilterConfig { passband_hz: 400e3, stopband_hz: 600e3, taps: None, attenuation_db: 60.0 };
+        let mut filter = design_channel_filter(&filter_config, fs);
+        let n = monitor.frame_samples();
+        for i in 0..5 {
+            let mut samples = noise(n + 512);
+            filter.process(&mut samples);
+            monitor.process(&samples[512..], i * n, None);
+        }
+        assert_eq!(monitor.waterfall.len(), 3);
+
+        // Flat in the passband, more than 50 dB down in the stopband
+        let frame = monitor.last_frame().unwrap();
+        let level = |f_hz: f32| {
+            let k = frame.frequencies_hz().iter().position(|f| *f >= f_hz).unwrap();
+            frame.psd_db[k]
+        };
+        assert!((level(-200e3) - level(200e3)).abs() < 1.0);
+        assert!(level(0.0) - level(800e3) > 50.0);
+        assert!(level(0.0) - level(-800e3) > 50.0);
+    }
+}
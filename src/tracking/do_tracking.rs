@@ -3,8 +3,10 @@ use crate::constants::gps_ca_constants::GPS_CA_CODE_32_PRN;
 use crate::constants::gps_property_constants::{
     GPS_L1_CA_CODE_LENGTH_CHIPS, GPS_L1_CA_CODE_RATE_CHIPS_PER_S,
 };
+use crate::sdr_store::sample_time::SampleTime;
+use crate::sdr_store::stream_monitor::Discontinuity;
 use crate::utilities::ca_code::generate_ca_code_samples;
-use crate::utilities::multicast_ring_buffer::MulticastRingBuffer;
+use crate::utilities::multicast_ring_buffer::{MulticastRingBuffer, ReaderCursor, RingReadError, RingSample};
 use crossbeam_channel::{Receiver, Sender};
 use num_complex::Complex32;
 use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
@@ -12,6 +14,8 @@ use std::error::Error;
 use std::f32::consts::PI;
 use std::sync::Arc;
 use std::sync::PoisonError;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
 const LOCK_THRESHOLD: f32 = 15.0;
 const MAX_LOST_EPOCHS: u32 = 20; // ms
@@ -27,6 +31,8 @@ static PLL_SUM_CARR: f32 = 0.001;
 static DLL_SUM_CODE: f32 = 0.001;
 static EARLY_LATE_SPACE: f32 = 0.5;
 pub static LOOP_MS: usize = 10;
+/// Longest skip a lagging channel coasts through, the code phase drifts too far beyond
+const MAX_COAST_S: f32 = 1.0;
 
 #[derive(Debug, Clone)]
 pub struct TrackingError;
@@ -92,9 +98,12 @@ pub struct TrackingChannel {
     pub lost_counter: u32,
     pub fs: f32,
     pub next_sample_index: usize,
+    pub handled_discontinuity: Option<usize>, // Sample index of the last stream break caught up with
+    pub epoch_time: Option<SampleTime>, // Time of the first sample of the epoch being correlated
+    pub cursor: Option<ReaderCursor>, // Registered on the first update, None when all reader slots are taken
     pub num_samples_per_code: usize,
     pub ca_code_samples: Vec<i8>,
-    pub data_samples: Vec<Complex32>,
+    pub data_samples: Vec<Complex32>, // Epoch for `do_work`, `update` correlates the ring buffer in place
 
     pub carrier_freq: f32,
     pub carrier_phase: f32,
@@ -124,6 +133,9 @@ impl TrackingChannel {
             state: ChannelState::Idle,
             lost_counter: 0,
             next_sample_index: 0,
+            handled_discontinuity: None,
+            epoch_time: None,
+            cursor: None,
             num_samples_per_code: num_ca_samples,
             ca_code_samples: Vec::with_capacity((1.5 * num_ca_samples as f32).round() as usize), // pre-allocate more samples to avoid frequent resizing during tracking
             data_samples: Vec::with_capacity((1.5 * num_ca_samples as f32).round() as usize),
@@ -150,6 +162,7 @@ impl TrackingChannel {
         self.carrier_freq = result.carrier_freq;
         self.code_phase = result.code_phase_chips;
         self.next_sample_index = result.sample_global_index;
+        self.epoch_time = result.sample_time;
         self.state = ChannelState::Tracking(result.prn);
     }
 
@@ -157,7 +170,7 @@ impl TrackingChannel {
         self.state == ChannelState::Tracking(self.prn)
     }
 
-    pub fn update(&mut self, buff: Arc<MulticastRingBuffer>) -> Option<TrackingMessage> {
+    pub fn update<T: RingSample>(&mut self, buff: Arc<MulticastRingBuffer<T>>) -> Option<TrackingMessage> {
         if self.state != ChannelState::Tracking(self.prn) {
             return None;
         }
@@ -165,23 +178,106 @@ impl TrackingChannel {
         self.ca_code_samples = generate_ca_code_samples(self.prn, self.code_rate, self.fs);
         self.num_samples_per_code = self.ca_code_samples.len();
 
+        if self.cursor.is_none() {
+            self.cursor = buff.register_reader(self.next_sample_index).ok();
+        }
+        if let Some(cursor) = &self.cursor {
+            cursor.advance_to(self.next_sample_index);
+        }
+
         let head = buff.get_head();
 
         if (head.wrapping_sub(self.next_sample_index + self.num_samples_per_code) as isize) < 0 {
             return None;
         }
 
-        buff.copy_to_slice(
+        if let Some(discontinuity) = buff.discontinuity_in(
             self.next_sample_index,
-            &mut self.data_samples[0..self.num_samples_per_code],
-        );
+            self.next_sample_index + self.num_samples_per_code,
+        ) && self.handled_discontinuity != Some(discontinuity.sample_index)
+        {
+            return self.handle_discontinuity(discontinuity);
+        }
 
-        self.do_work()
+        self.epoch_time = buff.time_of(self.next_sample_index);
+        let correlations = match buff.read(self.next_sample_index, self.num_samples_per_code) {
+            Ok(epoch) => {
+                let (first, second) = epoch.as_slices();
+                self.correlate(first, second)
+            }
+            Err(RingReadError::Lagging { .. }) => return self.resync(&buff),
+            Err(_) => return None, // Retried on the next update
+        };
+
+        self.finish_epoch(correlations)
     }
 
-    #[inline(always)]
-    fn do_work(&mut self) -> Option<TrackingMessage> {
-        let (i_p, q_p, i_e, q_e, i_l, q_l) = self.early_late_correlation();
+    /// The samples from `discontinuity.sample_index` on don't follow the ones before. With a known
+    /// number of lost samples the replica is moved on by the skipped and lost samples, else the
+    /// code phase is unknown and the satellite goes back to acquisition.
+    pub fn handle_discontinuity(&mut self, discontinuity: Discontinuity) -> Option<TrackingMessage> {
+        match discontinuity.lost_samples {
+            Some(lost) => {
+                self.coast(discontinuity.sample_index - self.next_sample_index + lost);
+                self.next_sample_index = discontinuity.sample_index;
+                self.handled_discontinuity = Some(discontinuity.sample_index);
+                None
+            }
+            None => {
+                let prn = self.prn;
+                self.reset();
+                self.free_data();
+                Some(TrackingMessage::SatelliteLost(prn))
+            }
+        }
+    }
+
+    /// The channel fell more than the buffer behind and its samples are overwritten. It skips to
+    /// the latest epoch written, coasting the code and carrier phases, or goes back to
+    /// acquisition when the skip is too long or crosses a break of unknown length.
+    pub fn resync<T: RingSample>(&mut self, buff: &MulticastRingBuffer<T>) -> Option<TrackingMessage> {
+        let target = buff.get_head().saturating_sub(self.num_samples_per_code);
+        let skipped = target.saturating_sub(self.next_sample_index);
+        let lost = match buff.discontinuity_in(self.next_sample_index, target) {
+            Some(discontinuity) => discontinuity.lost_samples,
+            None => Some(0),
+        };
+        match lost {
+            Some(lost) if (skipped + lost) as f32 / self.fs <= MAX_COAST_S => {
+                println!("Warning: PRN {} lagging, skipping {} samples", self.prn, skipped);
+                self.coast(skipped + lost);
+                self.next_sample_index = target;
+                None
+            }
+            _ => {
+                println!("Warning: PRN {} lagging by {} samples, back to acquisition", self.prn, skipped);
+                let prn = self.prn;
+                self.reset();
+                self.free_data();
+                Some(TrackingMessage::SatelliteLost(prn))
+            }
+        }
+    }
+
+    /// Move the replica on by `samples` not correlated
+    fn coast(&mut self, samples: usize) {
+        let (samples, fs) = (samples as f64, self.fs as f64);
+        self.code_phase =
+            ((self.code_phase as f64 + self.code_rate as f64 / fs * samples) % 1023.0) as f32;
+        self.carrier_phase = ((self.carrier_phase as f64
+            + 2.0 * std::f64::consts::PI * self.carrier_freq as f64 * samples / fs)
+            % (2.0 * std::f64::consts::PI)) as f32;
+    }
+
+    /// Track one epoch from `data_samples`
+    pub fn do_work(&mut self) -> Option<TrackingMessage> {
+        let correlations = self.early_late_correlation();
+        self.finish_epoch(correlations)
+    }
+
+    /// Close the loops on the correlations of the epoch and move on to the next one
+    fn finish_epoch(&mut self, correlations: (f32, f32, f32, f32, f32, f32)) -> Option<TrackingMessage> {
+        let (i_p, q_p, i_e, q_e, i_l, q_l) = correlations;
 
         let power = i_p * i_p + q_p * q_p;
 
@@ -196,9 +292,10 @@ impl TrackingChannel {
         } else {
             self.lost_counter += 1;
             if self.lost_counter >= MAX_LOST_EPOCHS {
+                let prn = self.prn; // Cleared by the reset
                 self.reset();
                 self.free_data();
-                Some(TrackingMessage::SatelliteLost(self.prn))
+                Some(TrackingMessage::SatelliteLost(prn))
             } else {
                 self.next_sample_index += self.num_samples_per_code;
                 self.num_samples_per_code =
@@ -229,18 +326,17 @@ impl TrackingChannel {
     // }
 
     pub fn early_late_correlation(&mut self) -> (f32, f32, f32, f32, f32, f32) {
-        for i in 0..self.num_samples_per_code {
-            let phase = self.carrier_phase + (2.0 * PI * self.carrier_freq * (i as f32) / self.fs);
-            let cos_p = phase.cos();
-            let sin_p = -phase.sin();
-
-            self.data_samples[i] = self.data_samples[i] * Complex32::new(cos_p, sin_p);
-        }
-
-        self.carrier_phase = (self.carrier_phase
-            + 2.0 * PI * self.carrier_freq * (self.num_samples_per_code as f32 / self.fs))
-            % (2.0 * PI);
+        let samples = std::mem::take(&mut self.data_samples);
+        let correlations = self.correlate(&samples[..self.num_samples_per_code], &[]);
+        self.data_samples = samples;
+        correlations
+    }
 
+    /// Early, prompt and late correlations of the epoch in `first` followed by `second`, the
+    /// samples are converted and the carrier wiped off on the fly so they can be borrowed from
+    /// the ring buffer in whatever type it stores
+    pub fn correlate<T: RingSample>(&mut self, first: &[T], second: &[T]) -> (f32, f32, f32, f32, f32, f32) {
+        let num_samples = first.len() + second.len();
         let mut i_p = 0.0_f32;
         let mut q_p = 0.0_f32;
         let mut i_e = 0.0_f32;
@@ -248,22 +344,28 @@ impl TrackingChannel {
         let mut i_l = 0.0_f32;
         let mut q_l = 0.0_f32;
 
-        for i in 0..self.num_samples_per_code {
+        for (i, sample) in first.iter().chain(second).enumerate() {
+            let phase = self.carrier_phase + (2.0 * PI * self.carrier_freq * (i as f32) / self.fs);
+            let baseband = sample.to_complex32() * Complex32::new(phase.cos(), -phase.sin());
+
             let chip_idx = (self.code_phase + (i as f32 * (self.code_rate / self.fs))) % 1023.0;
             let p_chip = self.get_ca_chip(chip_idx);
             let e_chip = self.get_ca_chip(chip_idx + EARLY_LATE_SPACE);
             let l_chip = self.get_ca_chip(chip_idx - EARLY_LATE_SPACE);
 
-            i_p += self.data_samples[i].re * p_chip;
-            q_p += self.data_samples[i].im * p_chip;
-            i_e += self.data_samples[i].re * e_chip;
-            q_e += self.data_samples[i].im * e_chip;
-            i_l += self.data_samples[i].re * l_chip;
-            q_l += self.data_samples[i].im * l_chip;
+            i_p += baseband.re * p_chip;
+            q_p += baseband.im * p_chip;
+            i_e += baseband.re * e_chip;
+            q_e += baseband.im * e_chip;
+            i_l += baseband.re * l_chip;
+            q_l += baseband.im * l_chip;
         }
 
+        self.carrier_phase = (self.carrier_phase
+            + 2.0 * PI * self.carrier_freq * (num_samples as f32 / self.fs))
+            % (2.0 * PI);
         self.code_phase = (self.code_phase
-            + (self.code_rate / self.fs) * (self.num_samples_per_code as f32))
+            + (self.code_rate / self.fs) * (num_samples as f32))
             % 1023.0;
 
         self.i_prompt = i_p;
@@ -273,7 +375,7 @@ impl TrackingChannel {
 
     pub fn get_ca_chip(&self, phase: f32) -> f32 {
         let idx = (phase.floor() as usize) % 1023;
-        GPS_CA_CODE_32_PRN[self.prn as usize][idx] as f32
+        GPS_CA_CODE_32_PRN[self.prn as usize - 1][idx] as f32
     }
 
     pub fn run_loop_filters(&mut self, i_p: f32, q_p: f32, i_e: f32, q_e: f32, i_l: f32, q_l: f32) {
@@ -313,6 +415,9 @@ impl TrackingChannel {
         self.state = ChannelState::Idle;
         self.lost_counter = 0;
         self.next_sample_index = 0;
+        self.handled_discontinuity = None;
+        self.epoch_time = None;
+        self.cursor = None;
         self.carrier_freq = 0.0;
         self.carrier_phase = 0.0;
         self.carrier_error = 0.0;
@@ -347,7 +452,7 @@ impl TrackingManager {
         }
     }
 
-    pub fn process_channels(&mut self, multi_ring_buf: Arc<MulticastRingBuffer>) {
+    pub fn process_channels<T: RingSample>(&mut self, multi_ring_buf: Arc<MulticastRingBuffer<T>>) {
         while let Ok(msg) = self.acq_to_trk.try_recv() {
             if let Some(channel) = self
                 .channels
@@ -381,11 +486,14 @@ impl TrackingManager {
     }
 }
 
-pub fn run(
-    multi_ring_buf: Arc<MulticastRingBuffer>,
+/// Tracks the satellites handed over by acquisition until `stop` is set and the channels
+/// caught up with the samples written
+pub fn run<T: RingSample>(
+    multi_ring_buf: Arc<MulticastRingBuffer<T>>,
     acq_to_trk: Receiver<AcquisitionResult>,
     trk_to_acq: Sender<TrackingMessage>,
     fs: f32,
+    stop: &AtomicBool,
 ) -> Result<(), TrackingError> {
     let mut manager = TrackingManager::new(acq_to_trk, trk_to_acq, fs);
     loop {
@@ -398,7 +506,14 @@ pub fn run(
                 .wrapping_sub(manager.next_tracking_index()) as isize)
                 < 0
             {
-                head_guard = multi_ring_buf.condvar.wait(head_guard)?;
+                // No more samples are written once stopped
+                if stop.load(Ordering::Relaxed) {
+                    return Ok(());
+                }
+                head_guard = multi_ring_buf
+                    .condvar
+                    .wait_timeout(head_guard, Duration::from_millis(50))?
+                    .0;
             }
 
             curr_head = multi_ring_buf.get_head();
@@ -406,6 +521,9 @@ pub fn run(
         }
 
         while (curr_head.wrapping_sub(required_idx) as isize) >= 0 {
+            if stop.load(Ordering::Relaxed) && !manager.channels.iter().any(|c| c.is_active()) {
+                return Ok(());
+            }
             manager.process_channels(multi_ring_buf.clone());
 
             required_idx = manager.next_tracking_index();
@@ -419,58 +537,40 @@ mod tests {
     use super::*;
     use crate::acquisition::do_acquisition::AcquisitionResult;
     use crate::acquisition::{do_acquisition, doppler_shift};
-    use crate::constants::gps_property_constants;
     use crate::tracking::do_tracking::TrackingChannel;
-    use crate::utilities::ca_code;
-    use num_complex::Complex32;
-    use std::f32::consts::PI;
+    use crate::simulator::signal_generator::{SignalGenerator, SimConfig, SimSatellite};
+    use num_complex::{Complex, Complex32};
     use std::fs::File;
     use std::io::Read;
     use std::os::unix::fs::FileExt;
     use std::path::Path;
     use std::time::Instant;
 
-    /// Helper to generate 1ms of synthetic GPS L1 data
+    /// Helper to generate 1ms of noise free synthetic GPS L1 data
     fn generate_synthetic_signal(
-        ca_code: &[i8],
+        prn: u8,
         doppler: f32,
         starting_carrier_phase: f32,
         starting_code_phase: f32,
         f_sampling: f32,
     ) -> Vec<Complex32> {
-        let samples_per_ms = (f_sampling / 1000.0) as usize;
-        let mut samples = Vec::with_capacity(samples_per_ms);
-        let code_phase_step = gps_property_constants::GPS_L1_CA_CODE_RATE_CHIPS_PER_S / f_sampling;
-
-        for i in 0..samples_per_ms {
-            // 1. Calculate continuous carrier phase
-            let carrier_phase =
-                starting_carrier_phase + (2.0 * PI * doppler / f_sampling * i as f32);
-
-            // 2. Calculate code phase and lookup chip
-            let current_code_phase = starting_code_phase + (code_phase_step * i as f32);
-            let chip_idx = (current_code_phase.floor() as usize) % 1023;
-            let code_val = ca_code[chip_idx] as f32;
-
-            // 3. Synthesize the complex sample (Code * Carrier)
-            samples.push(Complex32::new(
-                code_val * carrier_phase.cos(),
-                code_val * carrier_phase.sin(),
-            ));
-        }
-        samples
+        let mut config = SimConfig::new(f_sampling as f64, 0.0, 0);
+        config.noise_sigma = 0.0;
+        let mut sat = SimSatellite::new(prn, starting_code_phase as f64, doppler as f64, 45.0);
+        sat.carrier_phase_rad = starting_carrier_phase as f64;
+        config.satellites.push(sat);
+
+        let mut generator = SignalGenerator::new(config);
+        generator.generate_samples(generator.samples_per_ms())
     }
 
     #[test]
     fn test_pll_frequency_pull_in() {
         let prn = 2;
         let f_sampling = 4_096_000.0;
-        let mock_ca_code =
-            ca_code::generate_ca_code_samples(prn, GPS_L1_CA_CODE_RATE_CHIPS_PER_S, f_sampling);
 
         let true_doppler = 3000.0;
-        let signal_samples =
-            generate_synthetic_signal(&mock_ca_code, true_doppler, 0.0, 0.0, f_sampling);
+        let signal_samples = generate_synthetic_signal(prn, true_doppler, 0.0, 0.0, f_sampling);
 
         let buf = Arc::new(MulticastRingBuffer::new(8 * signal_samples.len()));
         let _ = buf.write_samples(&signal_samples);
@@ -486,6 +586,8 @@ mod tests {
             fs: f_sampling,
             mag_relative: 10.0,
             sample_global_index: 0,
+            sample_time: None,
+            detection: None,
         });
 
         let now = Instant::now();
@@ -573,10 +675,8 @@ mod tests {
     fn test_dll_code_phase_tracking() {
         let f_sampling = 4_096_000.0;
         let prn = 3;
-        let mock_ca_code =
-            ca_code::generate_ca_code_samples(prn, GPS_L1_CA_CODE_RATE_CHIPS_PER_S, f_sampling);
 
-        let signal_samples = generate_synthetic_signal(&mock_ca_code, 0.0, 0.0, 0.25, f_sampling);
+        let signal_samples = generate_synthetic_signal(prn, 0.0, 0.0, 0.25, f_sampling);
 
         let buf = Arc::new(MulticastRingBuffer::new(2 * signal_samples.len()));
         let _ = buf.write_samples(&signal_samples);
@@ -592,6 +692,8 @@ mod tests {
             fs: f_sampling,
             mag_relative: 10.0,
             sample_global_index: 0,
+            sample_time: None,
+            detection: None,
         });
 
         let now = Instant::now();
@@ -654,6 +756,186 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_tracking_prn_32() {
+        // The last code of GPS_CA_CODE_32_PRN, indexing the table by the PRN went past its end
+        let f_sampling = 4_096_000.0;
+        let prn = 32;
+        let signal_samples = generate_synthetic_signal(prn, 1000.0, 0.0, 0.0, f_sampling);
+        let buf = Arc::new(MulticastRingBuffer::new(8 * signal_samples.len()));
+
+        let mut trk_chl = TrackingChannel::new(prn, f_sampling);
+        trk_chl.start(AcquisitionResult {
+            prn,
+            carrier_freq: 1000.0,
+            code_phase_samples: 0,
+            code_phase_chips: 0.0,
+            fs: f_sampling,
+            mag_relative: 10.0,
+            sample_global_index: 0,
+            sample_time: None,
+            detection: None,
+        });
+        for epoch in 1..=4 {
+            let _ = buf.write_samples(&signal_samples);
+            trk_chl.update(buf.clone());
+            // Correlating with the code of another PRN would lose the lock
+            assert!(trk_chl.is_active() && trk_chl.lost_counter == 0, "Lost PRN 32 at epoch {}", epoch);
+            assert_eq!(trk_chl.next_sample_index, epoch * signal_samples.len());
+        }
+    }
+
+    #[test]
+    fn test_tracking_across_stream_discontinuity() {
+        use crate::sdr_store::stream_monitor::DiscontinuityCause;
+
+        let prn = 7;
+        let f_sampling = 4_096_000.0;
+        let mut config = SimConfig::new(f_sampling as f64, 0.0, 0);
+        config.noise_sigma = 0.0;
+        config.satellites.push(SimSatellite::new(prn, 200.0, 1000.0, 45.0));
+        let mut generator = SignalGenerator::new(config);
+        let n = generator.samples_per_ms();
+
+        // 3 ms, then 700 samples are lost, then 6 ms more
+        let buf = Arc::new(MulticastRingBuffer::new(16 * n));
+        let _ = buf.write_samples(&generator.generate_samples(3 * n));
+        let _ = generator.generate_samples(700);
+        let _ = buf.mark_discontinuity(Discontinuity {
+            sample_index: 3 * n,
+            lost_samples: Some(700),
+            cause: DiscontinuityCause::TimestampGap,
+        });
+        let _ = buf.write_samples(&generator.generate_samples(6 * n));
+
+        let mut trk_chl = TrackingChannel::new(0, f_sampling);
+        trk_chl.start(AcquisitionResult {
+            prn,
+            carrier_freq: 1000.0,
+            code_phase_samples: 0,
+            code_phase_chips: 200.0,
+            fs: f_sampling,
+            mag_relative: 10.0,
+            sample_global_index: 0,
+            sample_time: None,
+            detection: None,
+        });
+
+        let mut prompt = Vec::new();
+        while trk_chl.next_sample_index + 2 * n < buf.get_head() {
+            assert!(trk_chl.update(buf.clone()).is_none());
+            prompt.push(trk_chl.i_prompt.hypot(trk_chl.q_prompt));
+        }
+        assert_eq!(trk_chl.handled_discontinuity, Some(3 * n));
+        assert!(trk_chl.is_active());
+        // The correlation is as strong after the gap as before
+        let before = prompt[0];
+        let after = *prompt.last().unwrap();
+        assert!(after > 0.9 * before, "Prompt {} after the gap, {} before", after, before);
+
+        // Without the number of lost samples the code phase is lost too
+        let _ = buf.mark_discontinuity(Discontinuity {
+            sample_index: trk_chl.next_sample_index + 10,
+            lost_samples: None,
+            cause: DiscontinuityCause::DeviceOverflow,
+        });
+        assert!(matches!(trk_chl.update(buf.clone()), Some(TrackingMessage::SatelliteLost(7))));
+        assert!(!trk_chl.is_active());
+    }
+
+    #[test]
+    fn test_tracking_from_int8_buffer() {
+        let prn = 7;
+        let f_sampling = 4_096_000.0;
+        let mut config = SimConfig::new(f_sampling as f64, 0.0, 1);
+        config.noise_sigma = 0.7; // About the unit power of the AGC output
+        config.satellites.push(SimSatellite::new(prn, 200.0, 1000.0, 50.0));
+        let mut generator = SignalGenerator::new(config);
+        let n = generator.samples_per_ms();
+        let samples = generator.generate_samples(20 * n);
+
+        let float_buf = Arc::new(MulticastRingBuffer::new(32 * n));
+        let int8_buf = Arc::new(MulticastRingBuffer::<Complex<i8>>::with_size(32 * n));
+        let _ = float_buf.write_samples(&samples);
+        let _ = int8_buf.write_samples(&samples);
+
+        let acquisition = AcquisitionResult {
+            prn,
+            carrier_freq: 1000.0,
+            code_phase_samples: 0,
+            code_phase_chips: 200.0,
+            fs: f_sampling,
+            mag_relative: 10.0,
+            sample_global_index: 0,
+            sample_time: None,
+            detection: None,
+        };
+        let mut float_chl = TrackingChannel::new(0, f_sampling);
+        let mut int8_chl = TrackingChannel::new(1, f_sampling);
+        float_chl.start(acquisition.clone());
+        int8_chl.start(acquisition);
+        let (mut float_power, mut int8_power) = (0.0, 0.0);
+        while float_chl.next_sample_index + 2 * n < float_buf.get_head() {
+            assert!(float_chl.update(float_buf.clone()).is_none());
+            assert!(int8_chl.update(int8_buf.clone()).is_none());
+            float_power += float_chl.i_prompt.powi(2) + float_chl.q_prompt.powi(2);
+            int8_power += int8_chl.i_prompt.powi(2) + int8_chl.q_prompt.powi(2);
+        }
+        assert!(int8_chl.is_active());
+        // Hardly any loss from the 8 bit samples
+        let ratio = int8_power / float_power;
+        assert!((0.95..1.05).contains(&ratio), "int8 / float prompt power: {}", ratio);
+    }
+
+    #[test]
+    fn test_tracking_resyncs_when_lagging() {
+        let prn = 7;
+        let f_sampling = 4_096_000.0;
+        let mut config = SimConfig::new(f_sampling as f64, 0.0, 0);
+        config.noise_sigma = 0.0;
+        config.satellites.push(SimSatellite::new(prn, 200.0, 1000.0, 45.0));
+        let mut generator = SignalGenerator::new(config);
+        let n = generator.samples_per_ms();
+
+        let buf = Arc::new(MulticastRingBuffer::new(16 * n));
+        let _ = buf.write_samples(&generator.generate_samples(3 * n));
+        let mut trk_chl = TrackingChannel::new(0, f_sampling);
+        trk_chl.start(AcquisitionResult {
+            prn,
+            carrier_freq: 1000.0,
+            code_phase_samples: 0,
+            code_phase_chips: 200.0,
+            fs: f_sampling,
+            mag_relative: 10.0,
+            sample_global_index: 0,
+            sample_time: None,
+            detection: None,
+        });
+        assert!(trk_chl.update(buf.clone()).is_none());
+        let before = trk_chl.i_prompt.hypot(trk_chl.q_prompt);
+
+        // The channel stalls while 40 ms are written to a 16 ms buffer
+        for _ in 0..40 {
+            let _ = buf.write_samples(&generator.generate_samples(n));
+        }
+        assert_eq!(trk_chl.cursor.as_ref().map(|c| c.slack()), Some(0));
+        assert!(trk_chl.update(buf.clone()).is_none());
+        assert_eq!(trk_chl.next_sample_index, buf.get_head() - n);
+        assert_eq!(buf.reader_stats.lagging_reads.load(Ordering::Relaxed), 1);
+
+        let _ = buf.write_samples(&generator.generate_samples(5 * n));
+        while trk_chl.next_sample_index + n < buf.get_head() {
+            assert!(trk_chl.update(buf.clone()).is_none());
+        }
+        assert!(trk_chl.is_active());
+        let after = trk_chl.i_prompt.hypot(trk_chl.q_prompt);
+        assert!(after > 0.9 * before, "Prompt {} after the resync, {} before", after, before);
+        assert!(buf.min_reader_slack().is_some());
+
+        trk_chl.reset();
+        assert_eq!(buf.min_reader_slack(), None);
+    }
+
     #[test]
     fn test_tracking_with_real_signal() {
         const FS: f32 = 16_367_600.0;
@@ -1,9 +1,13 @@
-use num::complex::Complex32;
+use crate::sdr_store::sample_time::SampleTime;
+use crate::sdr_store::stream_monitor::Discontinuity;
+use num::complex::{Complex, Complex32};
+use serde::Deserialize;
 use std::cell::UnsafeCell;
+use std::collections::VecDeque;
 use std::error::Error;
 use std::fmt;
-use std::sync::PoisonError;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, PoisonError};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Condvar, Mutex};
 
 #[derive(Debug, Clone)]
@@ -23,43 +27,360 @@ impl<T> From<PoisonError<T>> for MulticastRingBuffError {
     }
 }
 
-// TODO: We can consider using a more zero-copy approach in the future:
-// Option 1: when reading samples, we can return a reference to the buffer 
-// instead of copying data to a separate buffer, returning two slices if 
-// the data is wrapped around. But we need to be careful about the lifetime
-// of the reference and ensure that it is not used after the buffer is 
-// overwritten by new samples.
-// Option 2: Virtual Memory Double Mapping, OS system calls (mmap on Linux/macOS,
-// VirtualAlloc on Windows). If your buffer size is N bytes, Virtual Address 
-// 0 to N points to your physical memory. Virtual Address N to 2N points
-// to the exact same physical memory.
-pub struct MulticastRingBuffer {
-    pub buffer: Vec<UnsafeCell<Complex32>>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RingReadError {
+    NotWritten, // Some of the samples are not written yet
+    Lagging { lost: usize }, // The reader fell more than the buffer size behind, `lost` samples are overwritten
+    TooLong, // More samples than the buffer holds
+    NoReaderSlot, // MAX_READERS views or cursors are held already
+}
+
+impl fmt::Display for RingReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RingReadError::NotWritten => write!(f, "Samples not written yet"),
+            RingReadError::Lagging { lost } => write!(f, "Reader lagging, {} samples already overwritten", lost),
+            RingReadError::TooLong => write!(f, "Read longer than the buffer"),
+            RingReadError::NoReaderSlot => write!(f, "No free reader slot"),
+        }
+    }
+}
+
+impl Error for RingReadError {}
+
+/// How close the writer came to overrunning the registered readers
+#[derive(Debug)]
+pub struct ReaderStats {
+    pub min_slack: AtomicUsize, // Fewest samples left before overwriting a cursor, usize::MAX before any
+    pub lagging_reads: AtomicU64, // Checks and reads of already overwritten samples
+    pub writer_waits: AtomicU64, // Writes delayed by a pinned sample
+}
+
+impl Default for ReaderStats {
+    fn default() -> Self {
+        Self {
+            min_slack: AtomicUsize::new(usize::MAX),
+            lagging_reads: AtomicU64::new(0),
+            writer_waits: AtomicU64::new(0),
+        }
+    }
+}
+
+impl fmt::Display for ReaderStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.min_slack.load(Ordering::Relaxed) {
+            usize::MAX => write!(f, "min reader slack: none")?,
+            slack => write!(f, "min reader slack: {} samples", slack)?,
+        }
+        write!(
+            f,
+            ", lagging reads: {}, writer waits: {}",
+            self.lagging_reads.load(Ordering::Relaxed),
+            self.writer_waits.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// Sample types the ring buffer can store, converted from Complex32 when written and back when
+/// read. The integer types hold `SCALE` times the value, set for the unit power of the AGC output.
+pub trait RingSample: Copy + Send + Sync + 'static {
+    const SCALE: f32;
+
+    fn from_complex32(x: Complex32) -> Self;
+
+    fn to_complex32(self) -> Complex32;
+
+    fn from_complex32_slice(src: &[Complex32], dest: &mut [Self]) {
+        for (d, s) in dest.iter_mut().zip(src) {
+            *d = Self::from_complex32(*s);
+        }
+    }
+
+    fn to_complex32_slice(src: &[Self], dest: &mut [Complex32]) {
+        for (d, s) in dest.iter_mut().zip(src) {
+            *d = s.to_complex32();
+        }
+    }
+}
+
+impl RingSample for Complex32 {
+    const SCALE: f32 = 1.0;
+
+    #[inline(always)]
+    fn from_complex32(x: Complex32) -> Self {
+        x
+    }
+
+    #[inline(always)]
+    fn to_complex32(self) -> Complex32 {
+        self
+    }
+
+    fn from_complex32_slice(src: &[Complex32], dest: &mut [Self]) {
+        dest.copy_from_slice(src);
+    }
+
+    fn to_complex32_slice(src: &[Self], dest: &mut [Complex32]) {
+        dest.copy_from_slice(src);
+    }
+}
+
+/// 11 sigma of headroom at unit power
+impl RingSample for Complex<i16> {
+    const SCALE: f32 = 4096.0;
+
+    #[inline(always)]
+    fn from_complex32(x: Complex32) -> Self {
+        // Float to int casts saturate
+        Complex::new((x.re * Self::SCALE).round() as i16, (x.im * Self::SCALE).round() as i16)
+    }
+
+    #[inline(always)]
+    fn to_complex32(self) -> Complex32 {
+        Complex32::new(self.re as f32, self.im as f32) * (1.0 / Self::SCALE)
+    }
+}
+
+/// 5.6 sigma of headroom at unit power, 0.2 dB of quantization loss
+impl RingSample for Complex<i8> {
+    const SCALE: f32 = 32.0;
+
+    #[inline(always)]
+    fn from_complex32(x: Complex32) -> Self {
+        Complex::new((x.re * Self::SCALE).round() as i8, (x.im * Self::SCALE).round() as i8)
+    }
+
+    #[inline(always)]
+    fn to_complex32(self) -> Complex32 {
+        Complex32::new(self.re as f32, self.im as f32) * (1.0 / Self::SCALE)
+    }
+}
+
+/// Sample type of the buffer between the frontend and the other stages, see `RingSample`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BufferFormat {
+    #[default]
+    Complex32, // Complex32
+    Int16Iq,   // Complex<i16>, needs the AGC
+    Int8Iq,    // Complex<i8>, needs the AGC
+}
+
+impl BufferFormat {
+    pub fn bytes_per_sample(&self) -> usize {
+        match self {
+            BufferFormat::Complex32 => 8,
+            BufferFormat::Int16Iq => 4,
+            BufferFormat::Int8Iq => 2,
+        }
+    }
+}
+
+// Readers either copy with `copy_to_slice`, which doesn't stop the writer from overwriting the
+// samples during the copy, or borrow them with `read`. A `RingSlice` pins its first sample and
+// the writer waits before overwriting a pinned sample, so the view stays valid until dropped.
+// It is two slices when the samples wrap around; a double-mapped buffer (mmap of the same pages
+// twice) would make it one, at the cost of page-sized buffers and platform specific code.
+// Readers that copy register a `ReaderCursor` instead, the writer doesn't wait for them but
+// tracks their slack and `check_range` tells them when they fell behind.
+// Samples are stored as `T` and only converted to Complex32 by the readers, as they use them.
+pub struct MulticastRingBuffer<T: RingSample = Complex32> {
+    pub buffer: Vec<UnsafeCell<T>>,
     buf_size: usize,
     mask: usize,           // For fast modulo: index & mask
     pub head: AtomicUsize, // Written by DFE
+    reserved: AtomicUsize, // Head once the write in progress is done
+    read_pins: Vec<AtomicUsize>, // First sample of each `RingSlice` held, UNPINNED when free
+    reader_cursors: Vec<AtomicUsize>, // Next sample of each `ReaderCursor` registered, UNPINNED when free
+    pub reader_stats: Arc<ReaderStats>,
     pub notifier: Mutex<bool>,
     pub condvar: Condvar,
+    pub discontinuities: Mutex<VecDeque<Discontinuity>>, // Breaks in the stream, indexed like `head`
+    pub block_times: Mutex<VecDeque<SampleTime>>, // Time of the first sample of each written block, oldest first
 }
 
+/// Borrowed views held at once, one per tracking channel plus the other stages is plenty
+pub const MAX_READERS: usize = 64;
+
+const UNPINNED: usize = usize::MAX;
+
+/// Samples `start..start + len()` borrowed from the ring buffer, not overwritten while held
+pub struct RingSlice<'a, T: RingSample = Complex32> {
+    ring: &'a MulticastRingBuffer<T>,
+    slot: usize,
+    start: usize,
+    first: &'a [T],
+    second: &'a [T], // Empty unless the samples wrap around
+}
+
+impl<'a, T: RingSample> RingSlice<'a, T> {
+    /// The samples in order, the second slice continues the first
+    pub fn as_slices(&self) -> (&'a [T], &'a [T]) {
+        (self.first, self.second)
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn len(&self) -> usize {
+        self.first.len() + self.second.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'a T> + 'a {
+        self.first.iter().chain(self.second.iter())
+    }
+
+    pub fn copy_to_slice(&self, dest: &mut [Complex32]) {
+        T::to_complex32_slice(self.first, &mut dest[..self.first.len()]);
+        T::to_complex32_slice(self.second, &mut dest[self.first.len()..self.len()]);
+    }
+}
+
+impl<T: RingSample> Drop for RingSlice<'_, T> {
+    fn drop(&mut self) {
+        self.ring.read_pins[self.slot].store(UNPINNED, Ordering::Release);
+    }
+}
+
+/// What a `ReaderCursor` needs of the buffer, whatever its sample type
+trait CursorRing: Send + Sync {
+    fn cursor(&self, slot: usize) -> &AtomicUsize;
+    fn check_range(&self, start: usize, len: usize) -> Result<(), RingReadError>;
+    fn slack(&self, position: usize) -> usize;
+}
+
+impl<T: RingSample> CursorRing for MulticastRingBuffer<T> {
+    fn cursor(&self, slot: usize) -> &AtomicUsize {
+        &self.reader_cursors[slot]
+    }
+
+    fn check_range(&self, start: usize, len: usize) -> Result<(), RingReadError> {
+        MulticastRingBuffer::check_range(self, start, len)
+    }
+
+    fn slack(&self, position: usize) -> usize {
+        (position + self.buf_size).saturating_sub(self.get_head())
+    }
+}
+
+/// Position of a reader in the stream, registered with the buffer for its slack to be tracked,
+/// unregistered when dropped
+pub struct ReaderCursor {
+    ring: Arc<dyn CursorRing>,
+    slot: usize,
+}
+
+impl ReaderCursor {
+    /// Index of the next sample the reader needs
+    pub fn position(&self) -> usize {
+        self.ring.cursor(self.slot).load(Ordering::Relaxed)
+    }
+
+    /// The samples before `position` are done with
+    pub fn advance_to(&self, position: usize) {
+        self.ring.cursor(self.slot).store(position, Ordering::Relaxed);
+    }
+
+    /// Whether the next `len` samples are written and not overwritten yet
+    pub fn check(&self, len: usize) -> Result<(), RingReadError> {
+        self.ring.check_range(self.position(), len)
+    }
+
+    /// Samples the writer can write before overwriting the cursor, 0 once lagging
+    pub fn slack(&self) -> usize {
+        self.ring.slack(self.position())
+    }
+}
+
+impl Drop for ReaderCursor {
+    fn drop(&mut self) {
+        self.ring.cursor(self.slot).store(UNPINNED, Ordering::Release);
+    }
+}
+
+/// Discontinuities kept for the readers, older ones concern overwritten samples anyway
+const MAX_DISCONTINUITIES: usize = 64;
+
+/// Block times kept for the readers, enough to cover the buffer with blocks of 2048 samples
+const MAX_BLOCK_TIMES: usize = 1024;
+
 impl MulticastRingBuffer {
     pub fn new(buf_size: usize) -> Self {
+        Self::with_size(buf_size)
+    }
+}
+
+impl<T: RingSample> MulticastRingBuffer<T> {
+    /// A buffer of `buf_size` samples of type `T`, e.g. `MulticastRingBuffer::<Complex<i8>>::with_size`
+    pub fn with_size(buf_size: usize) -> Self {
         assert!(
             buf_size.is_power_of_two(),
             "Buffer size must be a power of two"
         );
         Self {
             buffer: (0..buf_size)
-                .map(|_| UnsafeCell::new(Complex32::new(0.0, 0.0)))
+                .map(|_| UnsafeCell::new(T::from_complex32(Complex32::new(0.0, 0.0))))
                 .collect(),
             buf_size: buf_size,
             mask: buf_size - 1,
             head: AtomicUsize::new(0),
+            reserved: AtomicUsize::new(0),
+            read_pins: (0..MAX_READERS).map(|_| AtomicUsize::new(UNPINNED)).collect(),
+            reader_cursors: (0..MAX_READERS).map(|_| AtomicUsize::new(UNPINNED)).collect(),
+            reader_stats: Arc::new(ReaderStats::default()),
             notifier: Mutex::new(false),
             condvar: Condvar::new(),
+            discontinuities: Mutex::new(VecDeque::with_capacity(MAX_DISCONTINUITIES)),
+            block_times: Mutex::new(VecDeque::with_capacity(MAX_BLOCK_TIMES)),
         }
     }
 
+    /// Record the time of the sample at `time.sample_index`, to be called in sample order before
+    /// the samples are written, at least once per block
+    pub fn mark_time(&self, time: SampleTime) -> Result<(), MulticastRingBuffError> {
+        let mut block_times = self.block_times.lock()?;
+        if block_times.len() == MAX_BLOCK_TIMES {
+            block_times.pop_front();
+        }
+        block_times.push_back(time);
+        Ok(())
+    }
+
+    /// Time of the sample at `sample_index`, from the last time marked at or before it
+    pub fn time_of(&self, sample_index: usize) -> Option<SampleTime> {
+        let block_times = self.block_times.lock().ok()?;
+        block_times
+            .iter()
+            .rev()
+            .find(|t| t.sample_index <= sample_index)
+            .map(|t| t.at(sample_index))
+    }
+
+    /// Record that the samples from `discontinuity.sample_index` on don't follow the ones before,
+    /// to be called before those samples are written
+    pub fn mark_discontinuity(&self, discontinuity: Discontinuity) -> Result<(), MulticastRingBuffError> {
+        let mut discontinuities = self.discontinuities.lock()?;
+        if discontinuities.len() == MAX_DISCONTINUITIES {
+            discontinuities.pop_front();
+        }
+        discontinuities.push_back(discontinuity);
+        Ok(())
+    }
+
+    /// First discontinuity with a sample index in `start..end`
+    pub fn discontinuity_in(&self, start: usize, end: usize) -> Option<Discontinuity> {
+        let discontinuities = self.discontinuities.lock().ok()?;
+        discontinuities
+            .iter()
+            .find(|d| d.sample_index >= start && d.sample_index < end)
+            .copied()
+    }
+
     /// It is safe because the input samples are in contigous memory and the buffer is also a large
     /// contiguous memory. But, copying data is still costly, we can consider using a more zero-copy
     /// approach in the future
@@ -68,29 +389,42 @@ impl MulticastRingBuffer {
         let start = current_head & self.mask;
         let n = samples.len();
 
-        unsafe {
-            let ptr = self.buffer.as_ptr() as *mut Complex32;
-            let dest = ptr.add(start);
-
-            // Can we use a more zero-copy approach to write samples to the buffer?
-            // For example, we can use a separate buffer to store the samples and then swap
-            // the buffer pointer with the ring buffer pointer. This way, we can avoid copying
-            // data and improve performance.
+        // Announce the write before looking for pins, a reader pinning meanwhile sees it and backs off
+        self.reserved.store(current_head + n, Ordering::SeqCst);
+        let mut waited = false;
+        while self.read_pins.iter().any(|pin| {
+            let pinned = pin.load(Ordering::SeqCst);
+            pinned != UNPINNED && pinned + self.buf_size < current_head + n
+        }) {
+            waited = true;
+            std::thread::yield_now();
+        }
+        if waited {
+            self.reader_stats.writer_waits.fetch_add(1, Ordering::Relaxed);
+        }
 
+        // No reader borrows the samples written, the pins above see to it
+        unsafe {
+            let ptr = self.buffer.as_ptr() as *mut T;
             if start + n <= self.buf_size {
-                std::ptr::copy_nonoverlapping(samples.as_ptr(), dest, n);
+                T::from_complex32_slice(samples, std::slice::from_raw_parts_mut(ptr.add(start), n));
             } else {
                 let first_part = self.buf_size - start;
-                std::ptr::copy_nonoverlapping(samples.as_ptr(), dest, first_part);
-                std::ptr::copy_nonoverlapping(
-                    samples.as_ptr().add(first_part),
-                    ptr,
-                    n - first_part,
+                T::from_complex32_slice(
+                    &samples[..first_part],
+                    std::slice::from_raw_parts_mut(ptr.add(start), first_part),
+                );
+                T::from_complex32_slice(
+                    &samples[first_part..],
+                    std::slice::from_raw_parts_mut(ptr, n - first_part),
                 );
             }
         }
 
         self.head.store(current_head + n, Ordering::Release);
+        if let Some(slack) = self.min_reader_slack() {
+            self.reader_stats.min_slack.fetch_min(slack, Ordering::Relaxed);
+        }
 
         // Wake up Tracking after writing new samples
         let mut guard = self.notifier.lock()?;
@@ -104,39 +438,119 @@ impl MulticastRingBuffer {
         self.head.load(Ordering::Acquire)
     }
 
+    /// Register a reader starting at `position`, see `ReaderCursor`
+    pub fn register_reader(self: &Arc<Self>, position: usize) -> Result<ReaderCursor, RingReadError> {
+        let slot = self
+            .reader_cursors
+            .iter()
+            .position(|cursor| {
+                cursor
+                    .compare_exchange(UNPINNED, position, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok()
+            })
+            .ok_or(RingReadError::NoReaderSlot)?;
+        Ok(ReaderCursor { ring: Arc::clone(self) as Arc<dyn CursorRing>, slot })
+    }
+
+    /// Slack of the slowest registered reader, None without readers
+    pub fn min_reader_slack(&self) -> Option<usize> {
+        let head = self.get_head();
+        self.reader_cursors
+            .iter()
+            .map(|cursor| cursor.load(Ordering::Relaxed))
+            .filter(|position| *position != UNPINNED)
+            .map(|position| (position + self.buf_size).saturating_sub(head))
+            .min()
+    }
+
+    /// Whether the samples `start..start + len` are written and not overwritten, nor about to be
+    /// by the write in progress
+    pub fn check_range(&self, start: usize, len: usize) -> Result<(), RingReadError> {
+        if len > self.buf_size {
+            return Err(RingReadError::TooLong);
+        }
+        let reserved = self.reserved.load(Ordering::SeqCst);
+        if start + self.buf_size < reserved {
+            self.reader_stats.lagging_reads.fetch_add(1, Ordering::Relaxed);
+            Err(RingReadError::Lagging { lost: reserved - self.buf_size - start })
+        } else if start + len > self.head.load(Ordering::Acquire) {
+            Err(RingReadError::NotWritten)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Borrow the samples `start..start + len`, the writer waits for the view to be dropped before
+    /// overwriting them. Views should be short lived, a held view stalls the whole stream.
+    pub fn read(&self, start: usize, len: usize) -> Result<RingSlice<'_, T>, RingReadError> {
+        if len > self.buf_size {
+            return Err(RingReadError::TooLong);
+        }
+        let slot = self
+            .read_pins
+            .iter()
+            .position(|pin| {
+                pin.compare_exchange(UNPINNED, start, Ordering::SeqCst, Ordering::Relaxed)
+                    .is_ok()
+            })
+            .ok_or(RingReadError::NoReaderSlot)?;
+
+        // Pinned before loading `reserved`, so either this sees the write or the writer sees the pin
+        if let Err(error) = self.check_range(start, len) {
+            self.read_pins[slot].store(UNPINNED, Ordering::Release);
+            return Err(error);
+        }
+
+        let physical_start = start & self.mask;
+        let first_len = len.min(self.buf_size - physical_start);
+        // The pinned samples are not written to until the view is dropped
+        let (first, second) = unsafe {
+            let ptr = self.buffer.as_ptr() as *const T;
+            (
+                std::slice::from_raw_parts(ptr.add(physical_start), first_len),
+                std::slice::from_raw_parts(ptr, len - first_len),
+            )
+        };
+        Ok(RingSlice { ring: self, slot, start, first, second })
+    }
+
+    /// Convert the samples from `start` on into `dest`, without guard against the writer
     pub fn copy_to_slice(&self, start: usize, dest: &mut [Complex32]) {
         let n = dest.len();
         let physical_start = start & self.mask;
 
         unsafe {
-            let ptr = self.buffer.as_ptr() as *const Complex32;
-            let src = ptr.add(physical_start);
+            let ptr = self.buffer.as_ptr() as *const T;
             if physical_start + n <= self.buf_size {
-                // dest.copy_from_slice(&self.buffer[physical_start..physical_start + n]);
-                std::ptr::copy_nonoverlapping(src, dest.as_mut_ptr(), n);
+                T::to_complex32_slice(std::slice::from_raw_parts(ptr.add(physical_start), n), dest);
             } else {
                 let first_part = self.buf_size - physical_start;
-                // dest[..first_part].copy_from_slice(&self.buffer[physical_start..]);
-                // dest[first_part..].copy_from_slice(&self.buffer[..n - first_part]);
-                std::ptr::copy_nonoverlapping(src, dest.as_mut_ptr(), first_part);
-                std::ptr::copy_nonoverlapping(
-                    ptr,
-                    dest.as_mut_ptr().add(first_part),
-                    n - first_part,
+                T::to_complex32_slice(
+                    std::slice::from_raw_parts(ptr.add(physical_start), first_part),
+                    &mut dest[..first_part],
+                );
+                T::to_complex32_slice(
+                    std::slice::from_raw_parts(ptr, n - first_part),
+                    &mut dest[first_part..],
                 );
             }
         }
     }
 }
 
-unsafe impl Sync for MulticastRingBuffer {}
+unsafe impl<T: RingSample> Sync for MulticastRingBuffer<T> {}
 
 #[cfg(test)]
 mod tests {
     use std::cell::UnsafeCell;
 
-    use crate::utilities::multicast_ring_buffer::MulticastRingBuffer;
+    use crate::sdr_store::sample_time::{SampleTime, TimeSource};
+
+    use crate::utilities::multicast_ring_buffer::{MulticastRingBuffer, RingReadError, RingSample};
     use num_complex::Complex;
+    use std::sync::Arc;
+    use std::sync::atomic::Ordering;
+    use std::time::{Duration, Instant};
 
     fn as_slice(r_cells: &[UnsafeCell<Complex<f32>>]) -> &[Complex<f32>] {
         unsafe {
@@ -207,4 +621,174 @@ mod tests {
                 .as_slice()
         );
     }
+
+    #[test]
+    fn test_block_times() {
+        let ring_buf = MulticastRingBuffer::new(1024);
+        assert_eq!(ring_buf.time_of(0), None);
+
+        let time = SampleTime {
+            sample_index: 0,
+            time_ns: 1_000_000,
+            sample_rate_hz: 1e6,
+            source: TimeSource::Hardware,
+            pps_enabled: true,
+        };
+        let _ = ring_buf.mark_time(time);
+        let _ = ring_buf.write_samples(&[Complex::new(0.0, 0.0); 100]);
+        // A new reference after 50 lost samples
+        let _ = ring_buf.mark_time(SampleTime { sample_index: 100, time_ns: 1_150_000, ..time });
+        let _ = ring_buf.write_samples(&[Complex::new(0.0, 0.0); 100]);
+
+        assert_eq!(ring_buf.time_of(99).unwrap().time_ns, 1_099_000);
+        let after_gap = ring_buf.time_of(120).unwrap();
+        assert_eq!((after_gap.sample_index, after_gap.time_ns), (120, 1_170_000));
+        assert!(after_gap.pps_enabled);
+    }
+
+    #[test]
+    fn test_read_views() {
+        let ring_buf = MulticastRingBuffer::new(1024);
+        let samples: Vec<Complex<f32>> = (0..1030).map(|i| Complex::new(i as f32, 0.0)).collect();
+        let _ = ring_buf.write_samples(&samples);
+
+        let view = ring_buf.read(1020, 10).unwrap();
+        let (first, second) = view.as_slices();
+        assert_eq!((first.len(), second.len()), (4, 6));
+        assert!(view.iter().zip(1020..1030).all(|(x, i)| x.re == i as f32));
+        let mut dest = vec![Complex::new(0.0, 0.0); 10];
+        view.copy_to_slice(&mut dest);
+        assert_eq!(dest, samples[1020..1030]);
+
+        assert_eq!(ring_buf.read(1025, 10).err(), Some(RingReadError::NotWritten));
+        assert_eq!(ring_buf.read(0, 10).err(), Some(RingReadError::Lagging { lost: 6 }));
+        assert_eq!(ring_buf.read(0, 2048).err(), Some(RingReadError::TooLong));
+    }
+
+    #[test]
+    fn test_integer_samples() {
+        let ring_buf = MulticastRingBuffer::<Complex<i8>>::with_size(1024);
+        assert_eq!(std::mem::size_of_val(&ring_buf.buffer[..]), 2 * 1024);
+
+        let samples: Vec<Complex<f32>> =
+            (0..1030).map(|i| Complex::from_polar(1.0, i as f32 * 0.1)).collect();
+        let _ = ring_buf.write_samples(&samples);
+        let _ = ring_buf.write_samples(&[Complex::new(100.0, -100.0)]);
+
+        // Converted back when read, within half a step
+        let mut dest = vec![Complex::new(0.0, 0.0); 10];
+        ring_buf.copy_to_slice(1020, &mut dest);
+        assert!(dest.iter().zip(&samples[1020..]).all(|(a, b)| (a - b).norm() < 0.71 / 32.0));
+        let view = ring_buf.read(1020, 11).unwrap();
+        let mut from_view = vec![Complex::new(0.0, 0.0); 11];
+        view.copy_to_slice(&mut from_view);
+        assert_eq!(from_view[..10], dest[..]);
+        // Saturated
+        assert_eq!(*view.iter().last().unwrap(), Complex::new(127, -128));
+        assert_eq!(from_view[10], Complex::new(127.0, -128.0) / <Complex<i8>>::SCALE);
+    }
+
+    #[test]
+    fn test_reader_cursor_lagging() {
+        let ring_buf = Arc::new(MulticastRingBuffer::new(1024));
+        let cursor = ring_buf.register_reader(0).unwrap();
+        let _ = ring_buf.write_samples(&[Complex::new(0.0, 0.0); 1000]);
+        assert_eq!((cursor.slack(), ring_buf.min_reader_slack()), (24, Some(24)));
+        assert_eq!(cursor.check(1000), Ok(()));
+        assert_eq!(cursor.check(1001), Err(RingReadError::NotWritten));
+
+        // The writer doesn't wait for cursors, the reader finds out it fell behind
+        let _ = ring_buf.write_samples(&[Complex::new(0.0, 0.0); 100]);
+        assert_eq!(cursor.slack(), 0);
+        assert_eq!(cursor.check(10), Err(RingReadError::Lagging { lost: 76 }));
+        cursor.advance_to(1000);
+        assert_eq!(cursor.check(100), Ok(()));
+        assert_eq!(ring_buf.reader_stats.min_slack.load(Ordering::Relaxed), 0);
+        assert_eq!(ring_buf.reader_stats.lagging_reads.load(Ordering::Relaxed), 1);
+
+        drop(cursor);
+        assert_eq!(ring_buf.min_reader_slack(), None);
+    }
+
+    #[test]
+    fn test_writer_waits_for_view() {
+        let ring_buf = Arc::new(MulticastRingBuffer::new(1024));
+        let _ = ring_buf.write_samples(&[Complex::new(1.0, 0.0); 1024]);
+        let view = ring_buf.read(0, 16).unwrap();
+
+        let writer = {
+            let ring_buf = ring_buf.clone();
+            std::thread::spawn(move || ring_buf.write_samples(&[Complex::new(2.0, 0.0); 8]))
+        };
+        std::thread::sleep(Duration::from_millis(50));
+        // The write would overwrite the first 8 samples of the view
+        assert_eq!(ring_buf.get_head(), 1024);
+        assert!(view.iter().all(|x| x.re == 1.0));
+
+        drop(view);
+        writer.join().unwrap().unwrap();
+        assert_eq!(ring_buf.get_head(), 1032);
+        assert_eq!(ring_buf.reader_stats.writer_waits.load(Ordering::Relaxed), 1);
+    }
+
+    /// Throughput of 12 tracking-like readers of 1 ms epochs at 16 MS/s, copying or borrowing.
+    /// Run with `cargo test --release -- --ignored bench_copy_vs_borrow --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_copy_vs_borrow() {
+        const FS: usize = 16_000_000;
+        const EPOCH: usize = FS / 1000;
+        const READERS: usize = 12;
+        const BLOCK: usize = 2048;
+        const SECONDS: usize = 4;
+
+        for borrow in [false, true] {
+            let ring_buf = Arc::new(MulticastRingBuffer::new(1 << 22));
+            let total = SECONDS * FS / BLOCK * BLOCK;
+            let start = Instant::now();
+            let readers: Vec<_> = (0..READERS)
+                .map(|_| {
+                    let ring_buf = ring_buf.clone();
+                    std::thread::spawn(move || {
+                        let mut epoch = vec![Complex::new(0.0f32, 0.0); EPOCH];
+                        let mut sum = Complex::new(0.0f32, 0.0);
+                        let mut next = 0;
+                        while next + EPOCH <= total {
+                            if ring_buf.get_head() < next + EPOCH {
+                                std::thread::yield_now();
+                                continue;
+                            }
+                            if borrow {
+                                match ring_buf.read(next, EPOCH) {
+                                    Ok(view) => sum += view.iter().sum::<Complex<f32>>(),
+                                    Err(_) => continue,
+                                }
+                            } else {
+                                ring_buf.copy_to_slice(next, &mut epoch);
+                                sum += epoch.iter().sum::<Complex<f32>>();
+                            }
+                            next += EPOCH;
+                        }
+                        std::hint::black_box(sum);
+                    })
+                })
+                .collect();
+
+            let block: Vec<Complex<f32>> = (0..BLOCK).map(|i| Complex::new(i as f32, 0.0)).collect();
+            for _ in 0..total / BLOCK {
+                let _ = ring_buf.write_samples(&block);
+            }
+            readers.into_iter().for_each(|r| r.join().unwrap());
+
+            let elapsed = start.elapsed().as_secs_f64();
+            println!(
+                "{}: {} readers at {:.1} MS/s each, {:.1}x real time at 16 MS/s, {} writer waits",
+                if borrow { "borrow" } else { "copy" },
+                READERS,
+                total as f64 / elapsed / 1e6,
+                total as f64 / elapsed / FS as f64,
+                ring_buf.reader_stats.writer_waits.load(Ordering::Relaxed),
+            );
+        }
+    }
 }